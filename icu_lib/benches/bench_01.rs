@@ -8,20 +8,22 @@ use icu_lib::midata::MiData;
 macro_rules! test_encode_decode {
     ($data:expr, $cf:tt) => {{
         let data = ($data).clone();
-        let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(data));
-        let data = mid.encode_into(
-            &lvgl::LVGL {},
-            EncoderParams {
-                color_format: lvgl::ColorFormat::$cf,
-                stride_align: 256,
-                lvgl_version: lvgl::LVGLVersion::V9,
-                ..Default::default()
-            },
-        );
+        let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(data)).unwrap();
+        let data = mid
+            .encode_into(
+                &lvgl::LVGL {},
+                EncoderParams {
+                    color_format: lvgl::ColorFormat::$cf,
+                    stride_align: 256,
+                    lvgl_version: lvgl::LVGLVersion::V9,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
         fs::write("img_0.bin", data).expect("Unable to write file");
 
         let data = fs::read("img_0.bin").expect("Unable to read file");
-        MiData::decode_from(&lvgl::LVGL {}, Vec::from(data));
+        MiData::decode_from(&lvgl::LVGL {}, Vec::from(data)).unwrap();
     }};
 }
 
@@ -1,11 +1,39 @@
+pub mod options;
+mod tiff_encoder;
+
 use crate::EncoderParams;
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageEncoder, ImageFormat};
 use png;
+use std::borrow::Cow;
 use std::io::Cursor;
 
+use crate::endecoder::utils::color_space::{
+    convert_profile, from_embedded_profile, linear_srgb, ColorSpace, EmbeddedProfile,
+};
+use crate::endecoder::utils::dither::{dither, dither_f32, DitherTarget};
+use crate::endecoder::utils::metadata::Metadata;
+use crate::endecoder::utils::palette::png_palette;
+use crate::endecoder::utils::quantize::Palette;
 use crate::endecoder::{lvgl, EnDecoder, ImageInfo};
-use crate::midata::MiData;
+use crate::error::{IcuError, Result};
+use crate::midata::{IndexedImage, MiData};
 use serde_json::json;
 
+/// `decode` and `decode_in` of a format the image crate decodes, see [`decode_common`]
+macro_rules! decode_as {
+    ($format:expr) => {
+        fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+            self.decode_in(data, ColorSpace::Srgb)
+        }
+
+        fn decode_in(&self, data: Vec<u8>, color_space: ColorSpace) -> Result<MiData> {
+            decode_common(&data, $format, color_space)
+        }
+    };
+}
+
 pub struct AutoDetect {}
 
 pub struct PNG {}
@@ -32,26 +60,52 @@ pub struct PAM {}
 
 pub struct TGA {}
 
+pub struct QOI {}
+
+/// DXT1, DXT3 and DXT5 textures, decode only
+pub struct DDS {}
+
+/// Radiance RGBE
+pub struct HDR {}
+
+/// OpenEXR
+pub struct EXR {}
+
+pub struct FARBFELD {}
+
 impl EnDecoder for AutoDetect {
+    /// Only formats with magic bytes, [`find_endecoder`](crate::endecoder::find_endecoder)
+    /// tries TGA once the LVGL formats didn't match
     fn can_decode(&self, data: &[u8]) -> bool {
         image::guess_format(data).is_ok()
     }
 
-    fn encode(&self, _data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        unimplemented!()
+    fn encode(&self, _data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        Err(IcuError::Unsupported(
+            "AutoDetect can only decode, pick a concrete format to encode".to_owned(),
+        ))
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        self.decode_in(data, ColorSpace::Srgb)
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
+    fn decode_in(&self, data: Vec<u8>, color_space: ColorSpace) -> Result<MiData> {
         log::trace!("AutoDectect::decoding");
-        let img = image::load_from_memory(&data).unwrap();
-        log::trace!("AutoDectect::decoded");
-        MiData::RGBA(img.to_rgba8())
+        if let Some(indexed) = decode_indexed_png(&data)? {
+            return from_embedded_profile(&data, MiData::INDEXED(indexed), color_space);
+        }
+        let format = guess_format(&data)?;
+        if matches!(format, ImageFormat::Hdr | ImageFormat::OpenExr) {
+            return decode_linear(&data, format, color_space);
+        }
+        decode_common(&data, format, color_space)
     }
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         log::trace!("AutoDectect::decoding");
-        let img = image::load_from_memory(data).unwrap();
-        let img_format = image::guess_format(data).unwrap();
+        let img_format = guess_format(data)?;
+        let img = image::load_from_memory_with_format(data, img_format)?;
         log::trace!("AutoDectect::decoded");
 
         let mut other_info = serde_json::Map::new();
@@ -61,6 +115,10 @@ impl EnDecoder for AutoDetect {
             json!(format!("{:?}", img.color())),
         );
 
+        if let Some(embedded) = EmbeddedProfile::detect(data) {
+            other_info.insert("Color Profile".to_string(), json!(embedded.description));
+        }
+
         // Try to parse EXIF data
         if let Ok(reader) = exif::Reader::new().read_from_container(&mut std::io::Cursor::new(data))
         {
@@ -76,13 +134,13 @@ impl EnDecoder for AutoDetect {
             }
         }
 
-        ImageInfo {
+        Ok(ImageInfo {
             width: img.width(),
             height: img.height(),
             data_size: img.as_bytes().len() as u32,
             format: img_format.to_mime_type().to_owned(),
             other_info: serde_json::Value::Object(other_info),
-        }
+        })
     }
 }
 
@@ -96,20 +154,38 @@ impl EnDecoder for PNG {
         }
     }
 
-    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
-                let color_format = if encoder_params.color_format == lvgl::ColorFormat::UNKNOWN {
-                    lvgl::ColorFormat::ARGB8888
-                } else {
-                    encoder_params.color_format
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        let indexed = match data {
+            MiData::INDEXED(indexed) => Some(indexed),
+            _ => None,
+        };
+        match data.to_rgba() {
+            Some(img) => {
+                let color_format = match (encoder_params.color_format, data) {
+                    (lvgl::ColorFormat::UNKNOWN, MiData::INDEXED(indexed)) => {
+                        match indexed.palette().len() {
+                            0..=2 => lvgl::ColorFormat::I1,
+                            3..=4 => lvgl::ColorFormat::I2,
+                            5..=16 => lvgl::ColorFormat::I4,
+                            _ => lvgl::ColorFormat::I8,
+                        }
+                    }
+                    (lvgl::ColorFormat::UNKNOWN, MiData::GRAY(_)) => lvgl::ColorFormat::L8,
+                    (lvgl::ColorFormat::UNKNOWN, _) => lvgl::ColorFormat::ARGB8888,
+                    (color_format, _) => color_format,
                 };
+                // High bit depth sources stay 16-bit unless a color format is asked for
+                let sixteen_bits = encoder_params.color_format == lvgl::ColorFormat::UNKNOWN
+                    && data.is_high_precision();
 
                 let mut buf = Cursor::new(Vec::new());
 
-                let mut encoder = png::Encoder::new(&mut buf, img.width(), img.height());
-                encoder.set_compression(png::Compression::Balanced);
-                encoder.set_filter(png::Filter::NoFilter);
+                let mut encoder = png::Encoder::with_info(
+                    &mut buf,
+                    png_info(img.width(), img.height(), encoder_params.metadata.as_ref()),
+                )?;
+                encoder.set_compression(encoder_params.png_compression.into());
+                encoder.set_filter(encoder_params.png_filter.into());
 
                 match color_format {
                     lvgl::ColorFormat::I1
@@ -119,50 +195,85 @@ impl EnDecoder for PNG {
                         let bpp = color_format.get_bpp();
                         let color_map_size = 1 << bpp;
 
-                        let data = img.to_vec();
-                        let nq = color_quant::NeuQuant::new(
-                            encoder_params.dither.unwrap_or(1) as i32,
-                            color_map_size,
-                            &data,
-                        );
-                        let mut indexes_iter = data.chunks(4).map(|pix| nq.index_of(pix) as u8);
-                        let palette = nq.color_map_rgb();
-                        let trns = nq
-                            .color_map_rgba()
+                        // Indexed sources that fit keep their palette order and indexes
+                        let (colors, indexes) = match indexed
+                            .filter(|_| encoder_params.palette.is_none())
+                            .and_then(|indexed| {
+                                Some((indexed.palette_within(color_map_size)?, indexed.indexes()))
+                            }) {
+                            Some((colors, indexes)) => (colors.to_vec(), indexes.to_vec()),
+                            None => {
+                                let quantized = Palette::for_params(
+                                    img.as_raw(),
+                                    color_map_size,
+                                    &encoder_params,
+                                    encoder_params.dither.unwrap_or(1) as i32,
+                                )?;
+                                let mut dithered = img.clone().into_owned();
+                                if encoder_params.dither.is_some() {
+                                    let target = DitherTarget::Palette(&quantized);
+                                    if data.is_high_precision() {
+                                        let precise =
+                                            data.to_rgba32f().ok_or(IcuError::UnsupportedData)?;
+                                        dithered = dither_f32(
+                                            &precise,
+                                            &target,
+                                            encoder_params.dither_algorithm,
+                                            encoder_params.dither_strength,
+                                        );
+                                    } else {
+                                        dither(
+                                            &mut dithered,
+                                            &target,
+                                            encoder_params.dither_algorithm,
+                                            encoder_params.dither_strength,
+                                        );
+                                    }
+                                }
+                                let indexes = quantized.indexes(dithered.as_raw());
+                                (quantized.colors().to_vec(), indexes)
+                            }
+                        };
+                        let palette = colors
                             .iter()
-                            .skip(3)
-                            .step_by(4)
+                            .flat_map(|color| &color[..3])
                             .copied()
                             .collect::<Vec<_>>();
+                        let trns = colors.iter().map(|color| color[3]).collect::<Vec<_>>();
 
                         encoder.set_color(png::ColorType::Indexed);
-                        encoder.set_depth(png::BitDepth::from_u8(bpp as u8).unwrap());
+                        encoder.set_depth(
+                            png::BitDepth::from_u8(bpp as u8)
+                                .ok_or(IcuError::UnsupportedColorFormat(color_format))?,
+                        );
 
                         encoder.set_palette(palette);
                         encoder.set_trns(trns);
 
-                        let width = img.width();
-                        let stride_bytes = color_format.get_stride_size(width, 1) as usize;
-                        let mut indexes = vec![0; stride_bytes * img.height() as usize];
-                        indexes.chunks_exact_mut(stride_bytes).for_each(|row| {
-                            let mut iter = row.iter_mut();
-                            let mut byte = &mut 0u8;
-
-                            for i in 0..width as u16 {
-                                let alpha = indexes_iter.next().unwrap();
-                                if i % (8 / bpp) == 0 {
-                                    if let Some(next_byte) = iter.next() {
-                                        byte = next_byte;
-                                    } else {
-                                        break;
-                                    }
-                                }
-                                *byte |= (alpha) << ((8 / bpp - 1 - i % (8 / bpp)) * bpp);
-                            }
-                        });
+                        let indexes = pack_indexes(&indexes, bpp as usize, img.width());
 
-                        let mut writer = encoder.write_header().unwrap();
-                        writer.write_image_data(&indexes).unwrap();
+                        let mut writer = encoder.write_header()?;
+                        writer.write_image_data(&indexes)?;
+                    }
+                    lvgl::ColorFormat::L8 => {
+                        // An asked for L8 has no alpha, like in LVGL the luminance is premultiplied
+                        // by it. Grayscale sources keep their alpha when some pixel needs it.
+                        let gray = data.to_gray().ok_or(IcuError::UnsupportedData)?;
+                        let opaque = gray.pixels().all(|pix| pix[1] == 0xFF);
+                        let data = if opaque || encoder_params.color_format == lvgl::ColorFormat::L8
+                        {
+                            encoder.set_color(png::ColorType::Grayscale);
+                            gray.pixels()
+                                .map(|pix| (pix[0] as u16 * pix[1] as u16 / 0xFF) as u8)
+                                .collect()
+                        } else {
+                            encoder.set_color(png::ColorType::GrayscaleAlpha);
+                            gray.as_raw().clone()
+                        };
+                        encoder.set_depth(png::BitDepth::Eight);
+
+                        let mut writer = encoder.write_header()?;
+                        writer.write_image_data(&data)?;
                     }
                     lvgl::ColorFormat::RGB888 => {
                         let data = img
@@ -174,39 +285,52 @@ impl EnDecoder for PNG {
                         encoder.set_color(png::ColorType::Rgb);
                         encoder.set_depth(png::BitDepth::Eight);
 
-                        let mut writer = encoder.write_header().unwrap();
-                        writer.write_image_data(&data).unwrap();
+                        let mut writer = encoder.write_header()?;
+                        writer.write_image_data(&data)?;
+                    }
+                    lvgl::ColorFormat::ARGB8888 if sixteen_bits => {
+                        let img = data.to_rgba16().ok_or(IcuError::UnsupportedData)?;
+                        let data = img
+                            .as_raw()
+                            .iter()
+                            .flat_map(|c| c.to_be_bytes())
+                            .collect::<Vec<_>>();
+                        encoder.set_color(png::ColorType::Rgba);
+                        encoder.set_depth(png::BitDepth::Sixteen);
+
+                        let mut writer = encoder.write_header()?;
+                        writer.write_image_data(&data)?;
                     }
                     lvgl::ColorFormat::ARGB8888 => {
                         let data = img.to_vec();
                         encoder.set_color(png::ColorType::Rgba);
                         encoder.set_depth(png::BitDepth::Eight);
 
-                        let mut writer = encoder.write_header().unwrap();
-                        writer.write_image_data(&data).unwrap();
-                    }
-                    _ => {
-                        unimplemented!()
+                        let mut writer = encoder.write_header()?;
+                        writer.write_image_data(&data)?;
                     }
+                    _ => return Err(IcuError::UnsupportedColorFormat(color_format)),
                 }
-                {}
 
-                buf.into_inner()
+                Ok(buf.into_inner())
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Png)
-                .unwrap()
-                .to_rgba8(),
-        )
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        self.decode_in(data, ColorSpace::Srgb)
+    }
+
+    fn decode_in(&self, data: Vec<u8>, color_space: ColorSpace) -> Result<MiData> {
+        if let Some(indexed) = decode_indexed_png(&data)? {
+            return from_embedded_profile(&data, MiData::INDEXED(indexed), color_space);
+        }
+        decode_common(&data, ImageFormat::Png, color_space)
     }
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
-        let mut info = AutoDetect {}.info(data);
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        let mut info = AutoDetect {}.info(data)?;
 
         // Add PNG specific info
         if let Ok(decoder) = png::Decoder::new(Cursor::new(data)).read_info() {
@@ -227,10 +351,181 @@ impl EnDecoder for PNG {
             }
         }
 
-        info
+        Ok(info)
+    }
+}
+
+/// Format of a common image, TGA is recognized by its header as it has no magic bytes,
+/// for data that is already known to be a common image
+fn guess_format(data: &[u8]) -> Result<ImageFormat> {
+    match image::guess_format(data) {
+        Err(_) if is_tga(data) => Ok(ImageFormat::Tga),
+        format => Ok(format?),
     }
 }
 
+/// The TGA 2.0 footer, or else a header whose fields all make sense
+/// and enough data for the color map and the uncompressed pixels
+fn is_tga(data: &[u8]) -> bool {
+    if data.ends_with(b"TRUEVISION-XFILE.\0") {
+        return true;
+    }
+    let Some(header) = data.get(..18) else {
+        return false;
+    };
+    let le_u16 = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]) as usize;
+    let (id_length, color_map_type, image_type) = (header[0] as usize, header[1], header[2]);
+    let (color_map_length, color_map_depth) = (le_u16(5), header[7]);
+    let (width, height, depth, descriptor) = (le_u16(12), le_u16(14), header[16], header[17]);
+
+    let valid_type = match image_type & !8 {
+        1 => color_map_type == 1 && matches!(depth, 8 | 16),
+        2 => matches!(depth, 15 | 16 | 24 | 32),
+        3 => matches!(depth, 8 | 16),
+        _ => false,
+    };
+    let color_map_bytes = match color_map_type {
+        0 if color_map_length == 0 && color_map_depth == 0 => Some(0),
+        1 if matches!(color_map_depth, 15 | 16 | 24 | 32) => {
+            Some(color_map_length * (color_map_depth as usize).div_ceil(8))
+        }
+        _ => None,
+    };
+    // Run length encoded pixels take at least a packet per row
+    let pixel_bytes = match image_type & 8 {
+        0 => width * height * (depth as usize).div_ceil(8),
+        _ => height,
+    };
+    let alpha_bits = descriptor & 0x0F;
+    match color_map_bytes {
+        Some(color_map_bytes) => {
+            valid_type
+                && width > 0
+                && height > 0
+                && descriptor & 0xC0 == 0
+                && alpha_bits <= 8
+                && data.len() >= 18 + id_length + color_map_bytes + pixel_bytes
+        }
+        None => false,
+    }
+}
+
+/// Decode with the `image` crate and convert the embedded color profile to `color_space`
+fn decode_common(data: &[u8], format: ImageFormat, color_space: ColorSpace) -> Result<MiData> {
+    let img = image::load_from_memory_with_format(data, format)?;
+    from_embedded_profile(data, img.into(), color_space)
+}
+
+/// HDR and OpenEXR hold linear light, it is clipped to the range of the panel
+fn decode_linear(data: &[u8], format: ImageFormat, color_space: ColorSpace) -> Result<MiData> {
+    let img = image::load_from_memory_with_format(data, format)?;
+    convert_profile(
+        MiData::RGBA32F(img.into_rgba32f()),
+        &linear_srgb(),
+        &color_space.profile(),
+    )
+}
+
+/// The sRGB colors of `data` as linear light
+fn to_linear(data: &MiData) -> Result<DynamicImage> {
+    let img = data.to_rgba32f().ok_or(IcuError::UnsupportedData)?;
+    let linear = convert_profile(
+        MiData::RGBA32F(img.into_owned()),
+        &ColorSpace::Srgb.profile(),
+        &linear_srgb(),
+    )?;
+    let img = linear.to_rgba32f().ok_or(IcuError::UnsupportedData)?;
+    Ok(DynamicImage::ImageRgba32F(img.into_owned()))
+}
+
+/// PNG header with the EXIF, text chunks and resolution of `metadata`
+fn png_info(width: u32, height: u32, metadata: Option<&Metadata>) -> png::Info<'_> {
+    let mut info = png::Info::with_size(width, height);
+    let Some(metadata) = metadata else {
+        return info;
+    };
+
+    info.exif_metadata = metadata.exif.as_deref().map(Cow::Borrowed);
+    info.pixel_dims = metadata
+        .pixels_per_meter()
+        .map(|(xppu, yppu)| png::PixelDimensions {
+            xppu,
+            yppu,
+            unit: png::Unit::Meter,
+        });
+    for (keyword, text) in &metadata.text {
+        // tEXt is Latin-1, everything else needs iTXt
+        if text.chars().all(|c| (c as u32) < 0x100) {
+            info.uncompressed_latin1_text
+                .push(png::text_metadata::TEXtChunk::new(keyword, text));
+        } else {
+            info.utf8_text
+                .push(png::text_metadata::ITXtChunk::new(keyword, text));
+        }
+    }
+    info
+}
+
+fn set_exif(encoder: &mut impl ImageEncoder, metadata: Option<&Metadata>) -> Result<()> {
+    if let Some(exif) = metadata.and_then(|metadata| metadata.exif.clone()) {
+        encoder
+            .set_exif_metadata(exif)
+            .map_err(|err| IcuError::Image(image::ImageError::Unsupported(err)))?;
+    }
+    Ok(())
+}
+
+/// Palette and indexes of an indexed PNG, `None` for other images
+fn decode_indexed_png(data: &[u8]) -> Result<Option<IndexedImage>> {
+    if image::guess_format(data).ok() != Some(image::ImageFormat::Png) {
+        return Ok(None);
+    }
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(png::Transformations::IDENTITY);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| IcuError::UnknownFormat(format!("bad PNG: {e}")))?;
+    if reader.info().color_type != png::ColorType::Indexed {
+        return Ok(None);
+    }
+    let palette = png_palette(reader.info());
+
+    let mut buf = vec![0; reader.output_buffer_size().unwrap_or_default()];
+    let frame = reader
+        .next_frame(&mut buf)
+        .map_err(|e| IcuError::UnknownFormat(format!("bad PNG: {e}")))?;
+
+    let bpp = frame.bit_depth as usize;
+    let mask = ((1u16 << bpp) - 1) as u8;
+    let indexes = buf
+        .chunks_exact(frame.line_size)
+        .take(frame.height as usize)
+        .flat_map(|row| {
+            (0..frame.width as usize).map(move |x| {
+                let bit = x * bpp;
+                (row[bit / 8] >> (8 - bpp - bit % 8)) & mask
+            })
+        })
+        .collect();
+    IndexedImage::new(frame.width, frame.height, palette, indexes).map(Some)
+}
+
+/// Pack one index per pixel into MSB first rows of `bpp` bits
+fn pack_indexes(indexes: &[u8], bpp: usize, width: u32) -> Vec<u8> {
+    let stride = (width as usize * bpp).div_ceil(8);
+    let mut rows = vec![0; stride * indexes.len() / (width as usize).max(1)];
+    for (row, indexes) in rows
+        .chunks_exact_mut(stride.max(1))
+        .zip(indexes.chunks_exact((width as usize).max(1)))
+    {
+        for (x, &index) in indexes.iter().enumerate() {
+            let bit = x * bpp;
+            row[bit / 8] |= index << (8 - bpp - bit % 8);
+        }
+    }
+    rows
+}
+
 impl EnDecoder for JPEG {
     fn can_decode(&self, data: &[u8]) -> bool {
         if let Ok(format) = image::guess_format(data) {
@@ -241,26 +536,44 @@ impl EnDecoder for JPEG {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
-                let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Jpeg).unwrap();
-                buf.into_inner()
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
+                let (Ok(width), Ok(height)) =
+                    (u16::try_from(img.width()), u16::try_from(img.height()))
+                else {
+                    return Err(IcuError::InvalidGeometry(format!(
+                        "{}x{} exceeds the 65535x65535 limit of JPEG",
+                        img.width(),
+                        img.height()
+                    )));
+                };
+                let mut buf = Vec::new();
+                // The encoder of the image crate always writes 4:4:4 chroma
+                let mut encoder =
+                    jpeg_encoder::Encoder::new(&mut buf, encoder_params.quality.clamp(1, 100));
+                encoder.set_sampling_factor(encoder_params.jpeg_subsampling.into());
+                let metadata = encoder_params.metadata.as_ref();
+                if let Some(exif) = metadata.and_then(|metadata| metadata.exif.as_ref()) {
+                    encoder.add_app_segment(1, [b"Exif\0\0".as_slice(), exif].concat())?;
+                }
+                if let Some((x, y)) = metadata.and_then(|metadata| metadata.dpi) {
+                    encoder.set_density(jpeg_encoder::PixelDensity {
+                        density: (x.round() as u16, y.round() as u16),
+                        unit: jpeg_encoder::PixelDensityUnit::Inches,
+                    });
+                }
+                // The alpha channel is dropped
+                encoder.encode(img.as_raw(), width, height, jpeg_encoder::ColorType::Rgba)?;
+                Ok(buf)
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Jpeg)
-                .unwrap()
-                .to_rgba8(),
-        )
-    }
+    decode_as!(ImageFormat::Jpeg);
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
@@ -275,26 +588,20 @@ impl EnDecoder for BMP {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
                 let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Bmp).unwrap();
-                buf.into_inner()
+                img.write_to(&mut buf, image::ImageFormat::Bmp)?;
+                Ok(buf.into_inner())
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Bmp)
-                .unwrap()
-                .to_rgba8(),
-        )
-    }
+    decode_as!(ImageFormat::Bmp);
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
@@ -309,26 +616,20 @@ impl EnDecoder for GIF {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
                 let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Gif).unwrap();
-                buf.into_inner()
+                img.write_to(&mut buf, image::ImageFormat::Gif)?;
+                Ok(buf.into_inner())
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Gif)
-                .unwrap()
-                .to_rgba8(),
-        )
-    }
+    decode_as!(ImageFormat::Gif);
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
@@ -343,26 +644,27 @@ impl EnDecoder for TIFF {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
-                let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Tiff).unwrap();
-                buf.into_inner()
-            }
-            _ => Vec::new(),
-        }
-    }
-
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Tiff)
-                .unwrap()
-                .to_rgba8(),
+    /// 16-bit and floating point images are written at their own depth
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        let img = match data {
+            MiData::RGBA16(img) => DynamicImage::ImageRgba16(img.clone()),
+            MiData::RGBA32F(img) => DynamicImage::ImageRgba32F(img.clone()),
+            _ => DynamicImage::ImageRgba8(
+                data.to_rgba()
+                    .ok_or(IcuError::UnsupportedData)?
+                    .into_owned(),
+            ),
+        };
+        tiff_encoder::write_tiff(
+            &img,
+            encoder_params.tiff_compression,
+            encoder_params.metadata.as_ref(),
         )
     }
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    decode_as!(ImageFormat::Tiff);
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
@@ -377,30 +679,47 @@ impl EnDecoder for WEBP {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
-                let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::WebP).unwrap();
-                buf.into_inner()
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
+                let mut buf = Vec::new();
+                let mut img = img.into_owned();
+                round_colors(&mut img, encoder_params.webp_near_lossless);
+                // image-webp only writes lossless VP8L
+                let mut encoder = WebPEncoder::new_lossless(&mut buf);
+                set_exif(&mut encoder, encoder_params.metadata.as_ref())?;
+                DynamicImage::ImageRgba8(img).write_with_encoder(encoder)?;
+                Ok(buf)
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::WebP)
-                .unwrap()
-                .to_rgba8(),
-        )
-    }
+    decode_as!(ImageFormat::WebP);
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
 
+/// Near-lossless preprocessing: RGB is rounded to fewer levels, nothing at `level` 100,
+/// 1 bit dropped from 80 to 99 up to 5 bits below 20, the steps of libwebp.
+/// Unlike libwebp, which leaves smooth areas alone, every pixel is rounded.
+/// The rounded colors repeat more often, so VP8L codes them in fewer bits.
+fn round_colors(img: &mut image::RgbaImage, level: u8) {
+    let bits = 5 - level.min(100) as u16 / 20;
+    if bits == 0 {
+        return;
+    }
+    let half = 1u16 << (bits - 1);
+    let mask = !((1u16 << bits) - 1);
+    for pix in img.pixels_mut() {
+        for c in &mut pix.0[..3] {
+            *c = ((*c as u16 + half) & mask).min(0xFF) as u8;
+        }
+    }
+}
+
 impl EnDecoder for ICO {
     fn can_decode(&self, data: &[u8]) -> bool {
         if let Ok(format) = image::guess_format(data) {
@@ -411,26 +730,20 @@ impl EnDecoder for ICO {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
                 let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Ico).unwrap();
-                buf.into_inner()
+                img.write_to(&mut buf, image::ImageFormat::Ico)?;
+                Ok(buf.into_inner())
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Ico)
-                .unwrap()
-                .to_rgba8(),
-        )
-    }
+    decode_as!(ImageFormat::Ico);
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
@@ -445,26 +758,31 @@ impl EnDecoder for PBM {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::GRAY(img) => {
-                let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Pnm).unwrap();
-                buf.into_inner()
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_gray() {
+            Some(img) => {
+                // The PBM encoder takes 0 for black and 1 for white, and there is no alpha
+                let bits = image::GrayImage::from_fn(img.width(), img.height(), |x, y| {
+                    image::Luma([(img.get_pixel(x, y)[0] >= 0x80) as u8])
+                });
+                write_pnm(bits, PnmSubtype::Bitmap(SampleEncoding::Binary))
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::GRAY(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Pnm)
-                .unwrap()
-                .to_luma_alpha8(),
-        )
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        self.decode_in(data, ColorSpace::Srgb)
     }
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn decode_in(&self, data: Vec<u8>, color_space: ColorSpace) -> Result<MiData> {
+        let img = MiData::GRAY(
+            image::load_from_memory_with_format(&data, image::ImageFormat::Pnm)?.to_luma_alpha8(),
+        );
+        from_embedded_profile(&data, img, color_space)
+    }
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
@@ -479,30 +797,39 @@ impl EnDecoder for PGM {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::GRAY(img) => {
-                let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Pnm).unwrap();
-                buf.into_inner()
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_gray() {
+            Some(img) => {
+                // PGM has no alpha
+                let luma = DynamicImage::ImageLumaA8(img.into_owned()).into_luma8();
+                write_pnm(luma, PnmSubtype::Graymap(SampleEncoding::Binary))
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::GRAY(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Pnm)
-                .unwrap()
-                .to_luma_alpha8(),
-        )
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        self.decode_in(data, ColorSpace::Srgb)
+    }
+
+    fn decode_in(&self, data: Vec<u8>, color_space: ColorSpace) -> Result<MiData> {
+        let img = MiData::GRAY(
+            image::load_from_memory_with_format(&data, image::ImageFormat::Pnm)?.to_luma_alpha8(),
+        );
+        from_embedded_profile(&data, img, color_space)
     }
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
 
+fn write_pnm(img: image::GrayImage, subtype: PnmSubtype) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    img.write_with_encoder(PnmEncoder::new(&mut buf).with_subtype(subtype))?;
+    Ok(buf)
+}
+
 impl EnDecoder for PPM {
     fn can_decode(&self, data: &[u8]) -> bool {
         if let Ok(format) = image::guess_format(data) {
@@ -513,26 +840,20 @@ impl EnDecoder for PPM {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
                 let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Pnm).unwrap();
-                buf.into_inner()
+                img.write_to(&mut buf, image::ImageFormat::Pnm)?;
+                Ok(buf.into_inner())
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Pnm)
-                .unwrap()
-                .to_rgba8(),
-        )
-    }
+    decode_as!(ImageFormat::Pnm);
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
@@ -547,56 +868,181 @@ impl EnDecoder for PAM {
         }
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
                 let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Pnm).unwrap();
-                buf.into_inner()
+                img.write_to(&mut buf, image::ImageFormat::Pnm)?;
+                Ok(buf.into_inner())
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Pnm)
-                .unwrap()
-                .to_rgba8(),
-        )
-    }
+    decode_as!(ImageFormat::Pnm);
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
 
 impl EnDecoder for TGA {
-    fn can_decode(&self, _data: &[u8]) -> bool {
-        log::error!("TGA is not supported yet");
-        false
+    fn can_decode(&self, data: &[u8]) -> bool {
+        is_tga(data)
     }
 
-    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        match data {
-            MiData::RGBA(img) => {
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
                 let mut buf = Cursor::new(Vec::new());
-                img.write_to(&mut buf, image::ImageFormat::Tga).unwrap();
-                buf.into_inner()
+                img.write_to(&mut buf, image::ImageFormat::Tga)?;
+                Ok(buf.into_inner())
             }
-            _ => Vec::new(),
+            _ => Err(IcuError::UnsupportedData),
         }
     }
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        MiData::RGBA(
-            image::load_from_memory_with_format(&data, image::ImageFormat::Tga)
-                .unwrap()
-                .to_rgba8(),
-        )
+    decode_as!(ImageFormat::Tga);
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        AutoDetect {}.info(data)
+    }
+}
+
+impl EnDecoder for QOI {
+    fn can_decode(&self, data: &[u8]) -> bool {
+        if let Ok(format) = image::guess_format(data) {
+            format == image::ImageFormat::Qoi
+        } else {
+            log::error!("It's not a QOI file");
+            false
+        }
+    }
+
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        match data.to_rgba() {
+            Some(img) => {
+                let mut buf = Cursor::new(Vec::new());
+                img.write_to(&mut buf, image::ImageFormat::Qoi)?;
+                Ok(buf.into_inner())
+            }
+            _ => Err(IcuError::UnsupportedData),
+        }
+    }
+
+    decode_as!(ImageFormat::Qoi);
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        AutoDetect {}.info(data)
+    }
+}
+
+impl EnDecoder for DDS {
+    fn can_decode(&self, data: &[u8]) -> bool {
+        if let Ok(format) = image::guess_format(data) {
+            format == image::ImageFormat::Dds
+        } else {
+            log::error!("It's not a DDS file");
+            false
+        }
+    }
+
+    fn encode(&self, _data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        Err(IcuError::Unsupported(
+            "DDS can only be decoded, there is no DXT encoder".to_owned(),
+        ))
     }
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
+    decode_as!(ImageFormat::Dds);
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        AutoDetect {}.info(data)
+    }
+}
+
+impl EnDecoder for HDR {
+    fn can_decode(&self, data: &[u8]) -> bool {
+        if let Ok(format) = image::guess_format(data) {
+            format == image::ImageFormat::Hdr
+        } else {
+            log::error!("It's not an HDR file");
+            false
+        }
+    }
+
+    /// Radiance RGBE has no alpha, the colors are written as linear light
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        let img = DynamicImage::ImageRgb32F(to_linear(data)?.into_rgb32f());
+        let mut buf = Cursor::new(Vec::new());
+        img.write_to(&mut buf, image::ImageFormat::Hdr)?;
+        Ok(buf.into_inner())
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        self.decode_in(data, ColorSpace::Srgb)
+    }
+
+    fn decode_in(&self, data: Vec<u8>, color_space: ColorSpace) -> Result<MiData> {
+        decode_linear(&data, image::ImageFormat::Hdr, color_space)
+    }
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        AutoDetect {}.info(data)
+    }
+}
+
+impl EnDecoder for EXR {
+    fn can_decode(&self, data: &[u8]) -> bool {
+        if let Ok(format) = image::guess_format(data) {
+            format == image::ImageFormat::OpenExr
+        } else {
+            log::error!("It's not an OpenEXR file");
+            false
+        }
+    }
+
+    /// The colors are written as linear light
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        let mut buf = Cursor::new(Vec::new());
+        to_linear(data)?.write_to(&mut buf, image::ImageFormat::OpenExr)?;
+        Ok(buf.into_inner())
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        self.decode_in(data, ColorSpace::Srgb)
+    }
+
+    fn decode_in(&self, data: Vec<u8>, color_space: ColorSpace) -> Result<MiData> {
+        decode_linear(&data, image::ImageFormat::OpenExr, color_space)
+    }
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        AutoDetect {}.info(data)
+    }
+}
+
+impl EnDecoder for FARBFELD {
+    fn can_decode(&self, data: &[u8]) -> bool {
+        if let Ok(format) = image::guess_format(data) {
+            format == image::ImageFormat::Farbfeld
+        } else {
+            log::error!("It's not a farbfeld file");
+            false
+        }
+    }
+
+    /// Farbfeld is always 16-bit RGBA
+    fn encode(&self, data: &MiData, _encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        let img = data.to_rgba16().ok_or(IcuError::UnsupportedData)?;
+        let mut buf = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba16(img.into_owned())
+            .write_to(&mut buf, image::ImageFormat::Farbfeld)?;
+        Ok(buf.into_inner())
+    }
+
+    decode_as!(ImageFormat::Farbfeld);
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
         AutoDetect {}.info(data)
     }
 }
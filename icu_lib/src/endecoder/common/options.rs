@@ -0,0 +1,96 @@
+//! Options of the common image encoders.
+
+use tiff::encoder::compression::DeflateLevel;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum PngCompression {
+    /// Stored deflate blocks, the fastest and the largest
+    None,
+    Fastest,
+    Fast,
+    #[default]
+    Balanced,
+    /// The smallest and the slowest
+    High,
+}
+
+/// Row filter applied before the deflate compression
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum PngFilter {
+    #[default]
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Picks the best filter for every row, usually the smallest for photos
+    Adaptive,
+}
+
+/// Resolution of the JPEG chroma planes against the luma plane
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ChromaSubsampling {
+    /// Full resolution chroma
+    #[default]
+    Yuv444,
+    /// Half the horizontal chroma resolution
+    Yuv422,
+    /// Half the horizontal and vertical chroma resolution, the smallest
+    Yuv420,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum TiffCompression {
+    #[default]
+    None,
+    Lzw,
+    Deflate,
+    /// Run length encoding, only pays off for flat images
+    PackBits,
+}
+
+impl From<PngCompression> for png::Compression {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::None => png::Compression::NoCompression,
+            PngCompression::Fastest => png::Compression::Fastest,
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Balanced => png::Compression::Balanced,
+            PngCompression::High => png::Compression::High,
+        }
+    }
+}
+
+impl From<PngFilter> for png::Filter {
+    fn from(filter: PngFilter) -> Self {
+        match filter {
+            PngFilter::None => png::Filter::NoFilter,
+            PngFilter::Sub => png::Filter::Sub,
+            PngFilter::Up => png::Filter::Up,
+            PngFilter::Avg => png::Filter::Avg,
+            PngFilter::Paeth => png::Filter::Paeth,
+            PngFilter::Adaptive => png::Filter::Adaptive,
+        }
+    }
+}
+
+impl From<ChromaSubsampling> for jpeg_encoder::SamplingFactor {
+    fn from(subsampling: ChromaSubsampling) -> Self {
+        match subsampling {
+            ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+impl From<TiffCompression> for tiff::encoder::Compression {
+    fn from(compression: TiffCompression) -> Self {
+        match compression {
+            TiffCompression::None => tiff::encoder::Compression::Uncompressed,
+            TiffCompression::Lzw => tiff::encoder::Compression::Lzw,
+            TiffCompression::Deflate => tiff::encoder::Compression::Deflate(DeflateLevel::Balanced),
+            TiffCompression::PackBits => tiff::encoder::Compression::Packbits,
+        }
+    }
+}
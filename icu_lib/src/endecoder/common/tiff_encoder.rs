@@ -0,0 +1,235 @@
+//! TIFF writer that keeps the metadata the image crate drops.
+//!
+//! The descriptive tags of the EXIF IFD0 go into the image directory, the EXIF and GPS
+//! sub-IFDs are copied field by field and the resolution is written in pixels per inch.
+
+use crate::endecoder::common::options::TiffCompression;
+use crate::endecoder::utils::metadata::Metadata;
+use crate::error::{IcuError, Result};
+use image::DynamicImage;
+use std::borrow::Cow;
+use std::io::{Cursor, Seek, Write};
+use tiff::encoder::colortype::{ColorType, RGBA32Float, RGBA16, RGBA8};
+use tiff::encoder::{DirectoryEncoder, Rational, TiffEncoder, TiffKind, TiffValue};
+use tiff::tags::{ResolutionUnit, Tag, Type};
+
+/// IFD0 tags that describe the image rather than its layout
+const DESCRIPTIVE_TAGS: [exif::Tag; 8] = [
+    exif::Tag::ImageDescription,
+    exif::Tag::Make,
+    exif::Tag::Model,
+    exif::Tag::Orientation,
+    exif::Tag::Software,
+    exif::Tag::DateTime,
+    exif::Tag::Artist,
+    exif::Tag::Copyright,
+];
+
+/// PNG text keywords with a TIFF tag of their own
+const TEXT_TAGS: [(&str, exif::Tag); 5] = [
+    ("Title", exif::Tag::ImageDescription),
+    ("Description", exif::Tag::ImageDescription),
+    ("Author", exif::Tag::Artist),
+    ("Copyright", exif::Tag::Copyright),
+    ("Software", exif::Tag::Software),
+];
+
+/// Resolution is kept to 1/100 pixel per inch
+const RESOLUTION_SCALE: f32 = 100.;
+
+/// 16-bit and floating point images are written at their own depth
+pub(super) fn write_tiff(
+    img: &DynamicImage,
+    compression: TiffCompression,
+    metadata: Option<&Metadata>,
+) -> Result<Vec<u8>> {
+    let fields = metadata
+        .and_then(|metadata| metadata.exif.clone())
+        .and_then(|exif| exif::Reader::new().read_raw(exif).ok())
+        .map(|exif| exif.fields().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let mut buf = Cursor::new(Vec::new());
+    let mut encoder = TiffEncoder::new(&mut buf)?.with_compression(compression.into());
+    let (width, height) = (img.width(), img.height());
+    match img {
+        DynamicImage::ImageRgba16(img) => {
+            write_image::<_, RGBA16>(&mut encoder, width, height, img, metadata, &fields)?
+        }
+        DynamicImage::ImageRgba32F(img) => {
+            write_image::<_, RGBA32Float>(&mut encoder, width, height, img, metadata, &fields)?
+        }
+        _ => {
+            let img = img.to_rgba8();
+            write_image::<_, RGBA8>(&mut encoder, width, height, &img, metadata, &fields)?
+        }
+    }
+    Ok(buf.into_inner())
+}
+
+fn write_image<W: Write + Seek, C: ColorType>(
+    encoder: &mut TiffEncoder<W>,
+    width: u32,
+    height: u32,
+    data: &[C::Inner],
+    metadata: Option<&Metadata>,
+    fields: &[exif::Field],
+) -> Result<()>
+where
+    [C::Inner]: TiffValue,
+{
+    // Sub-IFDs go first, the image directory points to them
+    let exif_ifd = write_sub_ifd(encoder, fields, exif::Context::Exif)?;
+    let gps_ifd = write_sub_ifd(encoder, fields, exif::Context::Gps)?;
+
+    let mut image = encoder.new_image::<C>(width, height)?;
+    let directory = image.encoder();
+    if let Some(offset) = exif_ifd {
+        directory.write_tag(Tag::ExifDirectory, offset)?;
+    }
+    if let Some(offset) = gps_ifd {
+        directory.write_tag(Tag::GpsDirectory, offset)?;
+    }
+
+    let descriptive = fields.iter().filter(|field| {
+        field.ifd_num == exif::In::PRIMARY && DESCRIPTIVE_TAGS.contains(&field.tag)
+    });
+    for field in descriptive {
+        write_field(directory, field)?;
+    }
+
+    let text = metadata
+        .map(|metadata| &metadata.text[..])
+        .unwrap_or_default();
+    for (keyword, text) in text {
+        let tag = TEXT_TAGS
+            .iter()
+            .find(|(text_keyword, _)| text_keyword == keyword)
+            .map(|&(_, tag)| tag);
+        let taken = |tag| fields.iter().any(|field| field.tag == tag);
+        if let Some(tag) = tag.filter(|&tag| !taken(tag) && text.is_ascii()) {
+            directory.write_tag(Tag::Unknown(tag.number()), text.as_str())?;
+        }
+    }
+
+    if let Some((x, y)) = metadata.and_then(|metadata| metadata.dpi) {
+        let rational = |dpi: f32| Rational {
+            n: (dpi * RESOLUTION_SCALE).round() as u32,
+            d: RESOLUTION_SCALE as u32,
+        };
+        image.resolution_unit(ResolutionUnit::Inch);
+        image.x_resolution(rational(x));
+        image.y_resolution(rational(y));
+    }
+
+    image.write_data(data)?;
+    Ok(())
+}
+
+/// Directory of the EXIF fields of `context`, `None` if there are none
+fn write_sub_ifd<W: Write + Seek, K: TiffKind>(
+    encoder: &mut TiffEncoder<W, K>,
+    fields: &[exif::Field],
+    context: exif::Context,
+) -> Result<Option<K::OffsetType>> {
+    let fields = fields
+        .iter()
+        .filter(|field| field.ifd_num == exif::In::PRIMARY && field.tag.context() == context)
+        // The interoperability IFD is not copied
+        .filter(|field| field.tag != exif::Tag::InteropIFDPointer)
+        .collect::<Vec<_>>();
+    if fields.is_empty() {
+        return Ok(None);
+    }
+
+    let mut directory = encoder.extra_directory()?;
+    for field in fields {
+        write_field(&mut directory, field)?;
+    }
+    Ok(Some(directory.finish_with_offsets()?.offset))
+}
+
+fn write_field<W: Write + Seek, K: TiffKind>(
+    directory: &mut DirectoryEncoder<W, K>,
+    field: &exif::Field,
+) -> Result<()> {
+    let tag = Tag::Unknown(field.tag.number());
+    match &field.value {
+        exif::Value::Byte(values) => directory.write_tag(tag, &values[..]),
+        exif::Value::Ascii(values) => {
+            match values.first().map(|text| String::from_utf8_lossy(text)) {
+                Some(text) if text.is_ascii() && !text.contains('\0') => {
+                    directory.write_tag(tag, &*text)
+                }
+                _ => Ok(()),
+            }
+        }
+        exif::Value::Short(values) => directory.write_tag(tag, &values[..]),
+        exif::Value::Long(values) => directory.write_tag(tag, &values[..]),
+        exif::Value::Rational(values) => directory.write_tag(tag, Rationals(values)),
+        exif::Value::SByte(values) => directory.write_tag(tag, &values[..]),
+        exif::Value::Undefined(values, _) => directory.write_tag(tag, Undefined(values)),
+        exif::Value::SShort(values) => directory.write_tag(tag, &values[..]),
+        exif::Value::SLong(values) => directory.write_tag(tag, &values[..]),
+        exif::Value::SRational(values) => directory.write_tag(tag, SRationals(values)),
+        exif::Value::Float(values) => directory.write_tag(tag, &values[..]),
+        exif::Value::Double(values) => directory.write_tag(tag, &values[..]),
+        exif::Value::Unknown(..) => Ok(()),
+    }
+    .map_err(IcuError::from)
+}
+
+/// EXIF `UNDEFINED` bytes, the tiff crate writes `[u8]` as `BYTE`
+struct Undefined<'a>(&'a [u8]);
+
+/// Any number of rationals, the tiff crate only writes single ones
+struct Rationals<'a>(&'a [exif::Rational]);
+
+struct SRationals<'a>(&'a [exif::SRational]);
+
+impl TiffValue for Undefined<'_> {
+    const BYTE_LEN: u8 = 1;
+    const FIELD_TYPE: Type = Type::UNDEFINED;
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.0)
+    }
+}
+
+impl TiffValue for Rationals<'_> {
+    const BYTE_LEN: u8 = 8;
+    const FIELD_TYPE: Type = Type::RATIONAL;
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        self.0
+            .iter()
+            .flat_map(|value| [value.num.to_ne_bytes(), value.denom.to_ne_bytes()])
+            .flatten()
+            .collect()
+    }
+}
+
+impl TiffValue for SRationals<'_> {
+    const BYTE_LEN: u8 = 8;
+    const FIELD_TYPE: Type = Type::SRATIONAL;
+
+    fn count(&self) -> usize {
+        self.0.len()
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        self.0
+            .iter()
+            .flat_map(|value| [value.num.to_ne_bytes(), value.denom.to_ne_bytes()])
+            .flatten()
+            .collect()
+    }
+}
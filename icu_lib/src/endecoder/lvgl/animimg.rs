@@ -0,0 +1,143 @@
+use crate::endecoder::lvgl::c_array::{c_identifier, write_include};
+use crate::endecoder::lvgl::LVGLVersion;
+use crate::error::{IcuError, Result};
+use std::fmt::Write;
+
+/// Sources `lv_animimg` V8 can take, its count is a `uint8_t`
+const MAX_SOURCES: usize = u8::MAX as usize;
+
+/// Where LVGL finds the frames of an animation
+pub enum FrameSource {
+    /// File names of `.bin` frames, read through the LVGL file system from `<NAME>_DIR`
+    Files(Vec<String>),
+    /// Names of the image descriptors of C array frames
+    Descriptors(Vec<String>),
+}
+
+impl FrameSource {
+    fn len(&self) -> usize {
+        match self {
+            FrameSource::Files(names) | FrameSource::Descriptors(names) => names.len(),
+        }
+    }
+}
+
+/// Render a C source with the frame list of an animation and a `<name>_create` function
+/// that plays it in an `lv_animimg` widget, forever.
+///
+/// `lv_animimg` shows every source for the same time, so frames that show longer are listed
+/// once per step of the greatest common divisor of `delays_ms`.
+pub fn to_animimg_source(
+    name: &str,
+    frames: &FrameSource,
+    delays_ms: &[u32],
+    version: LVGLVersion,
+) -> Result<String> {
+    if frames.len() != delays_ms.len() || frames.len() == 0 {
+        return Err(IcuError::Unsupported(
+            "every frame of the animation needs a delay".to_owned(),
+        ));
+    }
+    if version == LVGLVersion::V8 && frames.len() > MAX_SOURCES {
+        return Err(IcuError::Unsupported(format!(
+            "lv_animimg of LVGL V8 plays up to {MAX_SOURCES} frames, the animation has {}",
+            frames.len()
+        )));
+    }
+
+    let name = c_identifier(name);
+    let name_upper = name.to_uppercase();
+    let slots = frame_slots(delays_ms);
+    let duration_ms = delays_ms.iter().sum::<u32>();
+
+    let mut out = String::new();
+    write_include(&mut out);
+    let _ = writeln!(
+        out,
+        "/* {} frames, {duration_ms} ms per loop */",
+        delays_ms.len()
+    );
+    let _ = writeln!(out);
+
+    let entries = match frames {
+        FrameSource::Files(files) => {
+            let _ = writeln!(out, "#ifndef {name_upper}_DIR");
+            let _ = writeln!(out, "#define {name_upper}_DIR \"S:\"");
+            let _ = writeln!(out, "#endif");
+            files
+                .iter()
+                .map(|file| format!("{name_upper}_DIR \"{}\"", file.escape_default()))
+                .collect::<Vec<_>>()
+        }
+        FrameSource::Descriptors(descriptors) => {
+            let declare = match version {
+                LVGLVersion::V8 => "LV_IMG_DECLARE",
+                _ => "LV_IMAGE_DECLARE",
+            };
+            for descriptor in descriptors {
+                let _ = writeln!(out, "{declare}({});", c_identifier(descriptor));
+            }
+            descriptors
+                .iter()
+                .map(|descriptor| format!("&{}", c_identifier(descriptor)))
+                .collect()
+        }
+    };
+    let _ = writeln!(out);
+
+    let _ = writeln!(out, "static const void * {name}_frames[] = {{");
+    for slot in &slots {
+        let _ = writeln!(out, "  {},", entries[*slot]);
+    }
+    let _ = writeln!(out, "}};");
+    let _ = writeln!(out);
+
+    let frames_arg = match version {
+        LVGLVersion::V8 => format!("(lv_img_dsc_t **){name}_frames"),
+        _ => format!("{name}_frames"),
+    };
+    let _ = writeln!(out, "lv_obj_t * {name}_create(lv_obj_t * parent)");
+    let _ = writeln!(out, "{{");
+    let _ = writeln!(out, "  lv_obj_t * animimg = lv_animimg_create(parent);");
+    let _ = writeln!(
+        out,
+        "  lv_animimg_set_src(animimg, {frames_arg}, {});",
+        slots.len()
+    );
+    let _ = writeln!(out, "  lv_animimg_set_duration(animimg, {duration_ms});");
+    let _ = writeln!(
+        out,
+        "  lv_animimg_set_repeat_count(animimg, LV_ANIM_REPEAT_INFINITE);"
+    );
+    let _ = writeln!(out, "  lv_animimg_start(animimg);");
+    let _ = writeln!(out, "  return animimg;");
+    let _ = writeln!(out, "}}");
+
+    Ok(out)
+}
+
+/// Frame index of every source, frames are repeated to show as long as their delay.
+/// Animations that would need too many sources show every frame for the same time.
+fn frame_slots(delays_ms: &[u32]) -> Vec<usize> {
+    let step = delays_ms.iter().fold(0, |a, &b| gcd(a, b)).max(1);
+    let slots = delays_ms
+        .iter()
+        .enumerate()
+        .flat_map(|(index, delay)| std::iter::repeat_n(index, (delay / step).max(1) as usize))
+        .collect::<Vec<_>>();
+    if slots.len() > MAX_SOURCES {
+        log::warn!(
+            "The frame delays need {} sources, every frame shows for the same time",
+            slots.len()
+        );
+        return (0..delays_ms.len()).collect();
+    }
+    slots
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
@@ -0,0 +1,74 @@
+use crate::endecoder::lvgl::c_array::c_identifier;
+use crate::midata::Atlas;
+use std::fmt::Write;
+
+/// Names the header defines after the atlas prefix itself, no sprite may take them
+pub(crate) const RESERVED_SPRITE_IDS: [&str; 4] = ["ATLAS_H", "WIDTH", "HEIGHT", "COUNT"];
+
+/// Enum member of a sprite in the header, without the atlas prefix
+pub(crate) fn sprite_id(sprite: &str) -> String {
+    c_identifier(sprite).to_uppercase()
+}
+
+/// Render a C header with the sub-rectangle of every sprite of an atlas.
+///
+/// A sprite shows through `lv_image` clipping: an image of the whole atlas, sized to the
+/// sprite and offset by its `-x` and `-y`.
+pub fn to_atlas_header(name: &str, atlas: &Atlas) -> String {
+    let name = c_identifier(name);
+    let name_upper = name.to_uppercase();
+    let sprite_id = |sprite: &str| format!("{name_upper}_{}", sprite_id(sprite));
+
+    let mut out = String::new();
+    let _ = writeln!(out, "#ifndef {name_upper}_ATLAS_H");
+    let _ = writeln!(out, "#define {name_upper}_ATLAS_H");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "#include <stdint.h>");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "/* {} sprites in a {}x{} atlas */",
+        atlas.sprites.len(),
+        atlas.width(),
+        atlas.height()
+    );
+    let _ = writeln!(out, "#define {name_upper}_WIDTH {}", atlas.width());
+    let _ = writeln!(out, "#define {name_upper}_HEIGHT {}", atlas.height());
+    let _ = writeln!(out);
+
+    let _ = writeln!(out, "typedef enum {{");
+    for sprite in &atlas.sprites {
+        let _ = writeln!(out, "  {},", sprite_id(&sprite.name));
+    }
+    let _ = writeln!(out, "  {name_upper}_COUNT");
+    let _ = writeln!(out, "}} {name}_sprite_t;");
+    let _ = writeln!(out);
+
+    let _ = writeln!(out, "typedef struct {{");
+    for field in ["x", "y", "w", "h"] {
+        let _ = writeln!(out, "  uint16_t {field};");
+    }
+    let _ = writeln!(out, "}} {name}_rect_t;");
+    let _ = writeln!(out);
+
+    let _ = writeln!(
+        out,
+        "static const {name}_rect_t {name}_rects[{name_upper}_COUNT] = {{"
+    );
+    for sprite in &atlas.sprites {
+        let _ = writeln!(
+            out,
+            "  [{}] = {{ {}, {}, {}, {} }},",
+            sprite_id(&sprite.name),
+            sprite.x,
+            sprite.y,
+            sprite.width,
+            sprite.height
+        );
+    }
+    let _ = writeln!(out, "}};");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "#endif /* {name_upper}_ATLAS_H */");
+
+    out
+}
@@ -0,0 +1,551 @@
+use crate::endecoder::lvgl::{
+    has_flag, ColorFormat, Flags, HeaderFlag, ImageHeader, LVGLCArray, LVGLVersion, LVGL, LVGLV8,
+};
+use crate::endecoder::{EnDecoder, ImageInfo};
+use crate::error::{IcuError, Result};
+use crate::midata::MiData;
+use crate::EncoderParams;
+use modular_bitfield::Specifier;
+use serde_json::Value;
+use std::fmt::Write;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Render an encoded LVGL image (the content of a `.bin` file) as a C source file
+/// holding the pixel map and an `lv_image_dsc_t` (V9) or `lv_img_dsc_t` (V8) descriptor,
+/// the same layout LVGLImage.py generates.
+///
+/// Compressed images keep their compressed payload, LVGL decompresses it at runtime.
+pub fn to_c_array(data: &[u8], name: &str) -> Result<String> {
+    to_c_array_with(data, name, &LVGLV8::default())
+}
+
+/// [`to_c_array`] for V8 images whose TRUE_COLOR rows are laid out as `v8` says
+pub fn to_c_array_with(data: &[u8], name: &str, v8: &LVGLV8) -> Result<String> {
+    let header = ImageHeader::from_bytes(data)?;
+    let version = header.version();
+    if version == LVGLVersion::Unknown {
+        return Err(IcuError::UnknownFormat(
+            "not an LVGL image header".to_owned(),
+        ));
+    }
+
+    let body = &data[header.header_size()..];
+    let name = c_identifier(name);
+    let name_upper = name.to_uppercase();
+
+    let mut out = String::new();
+    write_include(&mut out);
+    let _ = writeln!(out, "#ifndef LV_ATTRIBUTE_MEM_ALIGN");
+    let _ = writeln!(out, "#define LV_ATTRIBUTE_MEM_ALIGN");
+    let _ = writeln!(out, "#endif");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "#ifndef LV_ATTRIBUTE_{name_upper}");
+    let _ = writeln!(out, "#define LV_ATTRIBUTE_{name_upper}");
+    let _ = writeln!(out, "#endif");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "static const");
+    let _ = writeln!(
+        out,
+        "LV_ATTRIBUTE_MEM_ALIGN LV_ATTRIBUTE_LARGE_CONST LV_ATTRIBUTE_{name_upper}"
+    );
+    let _ = writeln!(out, "uint8_t {name}_map[] = {{");
+    let stride = match version {
+        LVGLVersion::V8 => v8.get_stride_size(header.cf(), header.w() as u32),
+        _ => header.stride() as u32,
+    };
+    for line in split_lines(&header, stride, body) {
+        let _ = writeln!(out, "{}", format_bytes(line));
+    }
+    let _ = writeln!(out, "}};");
+    let _ = writeln!(out);
+
+    match version {
+        LVGLVersion::V9 => {
+            let _ = writeln!(out, "const lv_image_dsc_t {name} = {{");
+            let _ = writeln!(out, "  .header.magic = LV_IMAGE_HEADER_MAGIC,");
+            let _ = writeln!(out, "  .header.cf = {},", cf_name(header.cf(), version));
+            let _ = writeln!(out, "  .header.flags = {},", flags_expr(header.flags()));
+            let _ = writeln!(out, "  .header.w = {},", header.w());
+            let _ = writeln!(out, "  .header.h = {},", header.h());
+            let _ = writeln!(out, "  .header.stride = {},", header.stride());
+        }
+        LVGLVersion::V8 => {
+            let _ = writeln!(out, "const lv_img_dsc_t {name} = {{");
+            let _ = writeln!(out, "  .header.cf = {},", cf_name(header.cf(), version));
+            let _ = writeln!(out, "  .header.always_zero = 0,");
+            let _ = writeln!(out, "  .header.reserved = 0,");
+            let _ = writeln!(out, "  .header.w = {},", header.w());
+            let _ = writeln!(out, "  .header.h = {},", header.h());
+        }
+        LVGLVersion::Unknown => {}
+    }
+    let _ = writeln!(out, "  .data_size = sizeof({name}_map),");
+    let _ = writeln!(out, "  .data = {name}_map,");
+    let _ = writeln!(out, "}};");
+
+    Ok(out)
+}
+
+/// Include of `lvgl.h` that works in and out of the LVGL tree
+pub(super) fn write_include(out: &mut String) {
+    let _ = writeln!(out, "#if defined(LV_LVGL_H_INCLUDE_SIMPLE)");
+    let _ = writeln!(out, "#include \"lvgl.h\"");
+    let _ = writeln!(out, "#elif defined(LV_BUILD_TEST)");
+    let _ = writeln!(out, "#include \"../lvgl.h\"");
+    let _ = writeln!(out, "#else");
+    let _ = writeln!(out, "#include \"lvgl/lvgl.h\"");
+    let _ = writeln!(out, "#endif");
+    let _ = writeln!(out);
+}
+
+/// Turn an arbitrary file stem into a valid C identifier.
+pub fn c_identifier(name: &str) -> String {
+    let mut ident = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+
+    ident
+}
+
+/// Split the pixel map so that every image row lands on its own line.
+/// Palettes and compressed payloads fall back to a fixed line width.
+fn split_lines<'a>(header: &ImageHeader, stride: u32, body: &'a [u8]) -> Vec<&'a [u8]> {
+    let mut lines = Vec::new();
+
+    if has_flag(header.flags(), HeaderFlag::COMPRESSED) {
+        lines.extend(body.chunks(BYTES_PER_LINE));
+        return lines;
+    }
+
+    let cf = header.cf();
+    let palette_size = match cf {
+        ColorFormat::I1 | ColorFormat::I2 | ColorFormat::I4 | ColorFormat::I8 => {
+            (1usize << cf.get_bpp()) * ColorFormat::ARGB8888.get_size() as usize
+        }
+        _ => 0,
+    };
+    let palette_size = palette_size.min(body.len());
+    let (palette, pixels) = body.split_at(palette_size);
+    lines.extend(palette.chunks(BYTES_PER_LINE));
+
+    // Every plane row lands on its own line, whatever is left falls back to a fixed width
+    let mut rest = pixels;
+    for (row_size, rows) in cf.get_planes(header.w() as u32, header.h() as u32, stride) {
+        let row_size = (row_size as usize).max(1);
+        let (plane, remaining) = rest.split_at((row_size * rows as usize).min(rest.len()));
+        lines.extend(plane.chunks(row_size));
+        rest = remaining;
+    }
+    lines.extend(rest.chunks(BYTES_PER_LINE));
+
+    lines
+}
+
+fn format_bytes(line: &[u8]) -> String {
+    let mut s = String::with_capacity(line.len() * 6 + 2);
+    s.push_str("  ");
+    for byte in line {
+        let _ = write!(s, "0x{byte:02x},");
+    }
+    s
+}
+
+const CF_NAMES_V8: &[(ColorFormat, &str)] = &[
+    (ColorFormat::TrueColor, "LV_IMG_CF_TRUE_COLOR"),
+    (ColorFormat::TrueColorAlpha, "LV_IMG_CF_TRUE_COLOR_ALPHA"),
+    (
+        ColorFormat::TrueColorChromaKeyed,
+        "LV_IMG_CF_TRUE_COLOR_CHROMA_KEYED",
+    ),
+    (ColorFormat::I1, "LV_IMG_CF_INDEXED_1BIT"),
+    (ColorFormat::I2, "LV_IMG_CF_INDEXED_2BIT"),
+    (ColorFormat::I4, "LV_IMG_CF_INDEXED_4BIT"),
+    (ColorFormat::I8, "LV_IMG_CF_INDEXED_8BIT"),
+    (ColorFormat::A1, "LV_IMG_CF_ALPHA_1BIT"),
+    (ColorFormat::A2, "LV_IMG_CF_ALPHA_2BIT"),
+    (ColorFormat::A4, "LV_IMG_CF_ALPHA_4BIT"),
+    (ColorFormat::A8, "LV_IMG_CF_ALPHA_8BIT"),
+];
+
+const CF_NAMES_V9: &[(ColorFormat, &str)] = &[
+    (ColorFormat::L8, "LV_COLOR_FORMAT_L8"),
+    (ColorFormat::I1, "LV_COLOR_FORMAT_I1"),
+    (ColorFormat::I2, "LV_COLOR_FORMAT_I2"),
+    (ColorFormat::I4, "LV_COLOR_FORMAT_I4"),
+    (ColorFormat::I8, "LV_COLOR_FORMAT_I8"),
+    (ColorFormat::A1, "LV_COLOR_FORMAT_A1"),
+    (ColorFormat::A2, "LV_COLOR_FORMAT_A2"),
+    (ColorFormat::A4, "LV_COLOR_FORMAT_A4"),
+    (ColorFormat::A8, "LV_COLOR_FORMAT_A8"),
+    (ColorFormat::RGB565, "LV_COLOR_FORMAT_RGB565"),
+    (ColorFormat::RGB565A8, "LV_COLOR_FORMAT_RGB565A8"),
+    (ColorFormat::RGB565Swapped, "LV_COLOR_FORMAT_RGB565_SWAPPED"),
+    (ColorFormat::ARGB8565, "LV_COLOR_FORMAT_ARGB8565"),
+    (ColorFormat::AL88, "LV_COLOR_FORMAT_AL88"),
+    (ColorFormat::ARGB1555, "LV_COLOR_FORMAT_ARGB1555"),
+    (ColorFormat::ARGB4444, "LV_COLOR_FORMAT_ARGB4444"),
+    (ColorFormat::ARGB2222, "LV_COLOR_FORMAT_ARGB2222"),
+    (ColorFormat::RGB888, "LV_COLOR_FORMAT_RGB888"),
+    (ColorFormat::ARGB8888, "LV_COLOR_FORMAT_ARGB8888"),
+    (ColorFormat::XRGB8888, "LV_COLOR_FORMAT_XRGB8888"),
+    (ColorFormat::I420, "LV_COLOR_FORMAT_I420"),
+    (ColorFormat::I422, "LV_COLOR_FORMAT_I422"),
+    (ColorFormat::I444, "LV_COLOR_FORMAT_I444"),
+    (ColorFormat::NV21, "LV_COLOR_FORMAT_NV21"),
+    (ColorFormat::NV12, "LV_COLOR_FORMAT_NV12"),
+    (ColorFormat::YUY2, "LV_COLOR_FORMAT_YUY2"),
+    (ColorFormat::UYVY, "LV_COLOR_FORMAT_UYVY"),
+];
+
+const FLAG_NAMES: &[(HeaderFlag, &str)] = &[
+    (HeaderFlag::PREMULTIPLIED, "LV_IMAGE_FLAGS_PREMULTIPLIED"),
+    (HeaderFlag::MODIFIABLE, "LV_IMAGE_FLAGS_MODIFIABLE"),
+    (HeaderFlag::VECTORS, "LV_IMAGE_FLAGS_VECTORS"),
+    (HeaderFlag::COMPRESSED, "LV_IMAGE_FLAGS_COMPRESSED"),
+    (HeaderFlag::ALLOCATED, "LV_IMAGE_FLAGS_ALLOCATED"),
+];
+
+fn cf_names(version: LVGLVersion) -> &'static [(ColorFormat, &'static str)] {
+    match version {
+        LVGLVersion::V8 => CF_NAMES_V8,
+        _ => CF_NAMES_V9,
+    }
+}
+
+fn cf_name(cf: ColorFormat, version: LVGLVersion) -> String {
+    match cf_names(version).iter().find(|(c, _)| *c == cf) {
+        Some((_, name)) => (*name).to_owned(),
+        None => format!("0x{:02X}", cf as u8),
+    }
+}
+
+fn flags_expr(flags: Flags) -> String {
+    let mut expr = "0".to_owned();
+    let mut known = 0u16;
+    for &(flag, name) in FLAG_NAMES {
+        known |= flag as u16;
+        if has_flag(flags, flag) {
+            expr.push_str(" | ");
+            expr.push_str(name);
+        }
+    }
+
+    let rest = flags & !known;
+    if rest != 0 {
+        let _ = write!(expr, " | 0x{rest:04X}");
+    }
+
+    expr
+}
+
+/// An image descriptor and its pixel map parsed out of a C source file.
+#[derive(Debug)]
+struct CArraySource {
+    name: String,
+    version: LVGLVersion,
+    cf: ColorFormat,
+    flags: Flags,
+    w: u16,
+    h: u16,
+    stride: u16,
+    data: Vec<u8>,
+}
+
+impl CArraySource {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.contains(&0) {
+            return None;
+        }
+
+        let text = strip_comments(&String::from_utf8_lossy(data));
+        let (type_name, name, fields) = find_descriptor(&text)?;
+
+        let mut version = if type_name == "lv_img_dsc_t" {
+            LVGLVersion::V8
+        } else {
+            LVGLVersion::V9
+        };
+        let mut cf = None;
+        let mut flags = 0;
+        let mut w = None;
+        let mut h = None;
+        let mut stride = None;
+        let mut map_name = None;
+
+        for (key, value) in fields {
+            match key.as_str() {
+                "cf" => {
+                    let (format, format_version) = parse_cf(&value, version)?;
+                    if let Some(format_version) = format_version {
+                        version = format_version;
+                    }
+                    cf = Some(format);
+                }
+                "magic" => version = LVGLVersion::V9,
+                "flags" => flags = parse_flags(&value)?,
+                "w" => w = Some(parse_int(&value)? as u16),
+                "h" => h = Some(parse_int(&value)? as u16),
+                "stride" => {
+                    version = LVGLVersion::V9;
+                    stride = Some(parse_int(&value)? as u16);
+                }
+                "data" => map_name = Some(value.trim_start_matches('&').to_owned()),
+                _ => {}
+            }
+        }
+
+        let cf = cf?;
+        let w = w?;
+        let h = h?;
+        let stride = stride.unwrap_or_else(|| cf.get_stride_size(w as u32, 1) as u16);
+        let data = parse_map(&text, map_name.as_deref())?;
+
+        Some(Self {
+            name,
+            version,
+            cf,
+            flags,
+            w,
+            h,
+            stride,
+            data,
+        })
+    }
+
+    fn try_parse(data: &[u8]) -> Result<Self> {
+        Self::parse(data).ok_or(IcuError::UnknownFormat(
+            "unable to find an LVGL image descriptor in the C source".to_owned(),
+        ))
+    }
+
+    /// Rebuild the `.bin` representation so the regular LVGL decoder can take over.
+    fn to_bin(&self) -> Vec<u8> {
+        let mut bin = ImageHeader::new(
+            self.version,
+            self.cf,
+            self.flags,
+            self.w,
+            self.h,
+            self.stride,
+        )
+        .encode();
+        bin.extend_from_slice(&self.data);
+        bin
+    }
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("/*") {
+            rest = after.find("*/").map(|end| &after[end + 2..]).unwrap_or("");
+            out.push(' ');
+        } else if let Some(after) = rest.strip_prefix("//") {
+            rest = after.find('\n').map(|end| &after[end..]).unwrap_or("");
+        } else {
+            let c = rest.chars().next().unwrap();
+            out.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    out
+}
+
+/// Return the text between the brace at `open` and its matching closing brace.
+fn braced(text: &str, open: usize) -> Option<&str> {
+    let mut depth = 0;
+    for (i, c) in text[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[open + 1..open + i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn skip_identifier(text: &str) -> (&str, &str) {
+    let end = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(text.len());
+    text.split_at(end)
+}
+
+type Field = (String, String);
+
+/// Find `lv_image_dsc_t name = { ... }` (or the V8 `lv_img_dsc_t`) and split its
+/// designated initializers into `(field, value)` pairs, keeping only the last
+/// path segment of keys such as `.header.cf`.
+fn find_descriptor(text: &str) -> Option<(&'static str, String, Vec<Field>)> {
+    for type_name in ["lv_image_dsc_t", "lv_img_dsc_t"] {
+        for (pos, _) in text.match_indices(type_name) {
+            let rest = text[pos + type_name.len()..].trim_start();
+            let (name, rest) = skip_identifier(rest);
+            if name.is_empty() {
+                continue;
+            }
+            let Some(rest) = rest.trim_start().strip_prefix('=') else {
+                continue;
+            };
+            if !rest.trim_start().starts_with('{') {
+                continue;
+            }
+
+            let open = text.len() - rest.trim_start().len();
+            let body = braced(text, open)?;
+            let fields = body
+                .split([',', '{', '}'])
+                .filter_map(|field| {
+                    let (key, value) = field.trim().strip_prefix('.')?.split_once('=')?;
+                    let key = key.trim().rsplit('.').next()?.to_owned();
+                    Some((key, value.trim().to_owned()))
+                })
+                .collect();
+
+            return Some((type_name, name.to_owned(), fields));
+        }
+    }
+    None
+}
+
+fn parse_int(value: &str) -> Option<u32> {
+    let value = value.trim().trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+fn parse_cf(value: &str, version: LVGLVersion) -> Option<(ColorFormat, Option<LVGLVersion>)> {
+    if let Some(cf) = parse_int(value) {
+        return match version {
+            LVGLVersion::V8 => ColorFormat::from_v8_code(cf as u8),
+            _ => <ColorFormat as Specifier>::from_bytes(cf as u8).ok(),
+        }
+        .map(|cf| (cf, None));
+    }
+
+    [LVGLVersion::V8, LVGLVersion::V9]
+        .into_iter()
+        .find_map(|version| {
+            cf_names(version)
+                .iter()
+                .find(|(_, name)| *name == value)
+                .map(|(cf, _)| (*cf, Some(version)))
+        })
+}
+
+fn parse_flags(value: &str) -> Option<Flags> {
+    value.split('|').try_fold(0, |flags, term| {
+        let term = term.trim().trim_start_matches('(').trim_end_matches(')');
+        let flag = match parse_int(term) {
+            Some(flag) => flag as Flags,
+            None => FLAG_NAMES
+                .iter()
+                .find(|(_, name)| *name == term)
+                .map(|(flag, _)| *flag as Flags)?,
+        };
+        Some(flags | flag)
+    })
+}
+
+/// Parse the byte array the descriptor points to. Sources made by the old online
+/// converter hold one variant per `LV_COLOR_DEPTH` in `#if` blocks; the 32-bit
+/// variant is picked since that is what the V8 true color formats decode as.
+fn parse_map(text: &str, map_name: Option<&str>) -> Option<Vec<u8>> {
+    let declaration = match map_name {
+        Some(map_name) => text
+            .match_indices(map_name)
+            .map(|(pos, _)| pos)
+            .find(|&pos| {
+                let (ident, rest) = skip_identifier(&text[pos..]);
+                ident == map_name && rest.trim_start().starts_with('[')
+            })?,
+        None => text.find("[] = {").or_else(|| text.find("[]={"))?,
+    };
+    let open = declaration + text[declaration..].find('{')?;
+    let body = braced(text, open)?;
+
+    let conditions = body
+        .lines()
+        .map(str::trim)
+        .filter(|line| {
+            line.starts_with("#if") || line.starts_with("#elif") || line.starts_with("#else")
+        })
+        .collect::<Vec<_>>();
+    let selected = conditions
+        .iter()
+        .position(|line| line.replace(' ', "").contains("LV_COLOR_DEPTH==32"))
+        .unwrap_or(0);
+
+    let mut bytes = Vec::new();
+    let mut active = Vec::new();
+    let mut condition_index = 0;
+    for line in body.lines().map(str::trim) {
+        if line.starts_with("#if") {
+            active.push(condition_index == selected);
+            condition_index += 1;
+        } else if line.starts_with("#elif") || line.starts_with("#else") {
+            *active.last_mut()? = condition_index == selected;
+            condition_index += 1;
+        } else if line.starts_with("#endif") {
+            active.pop()?;
+        } else if line.starts_with('#') || !active.iter().all(|&a| a) {
+            continue;
+        } else {
+            for token in line.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                bytes.push(parse_int(token)? as u8);
+            }
+        }
+    }
+
+    Some(bytes)
+}
+
+impl EnDecoder for LVGLCArray {
+    fn can_decode(&self, data: &[u8]) -> bool {
+        CArraySource::parse(data).is_some()
+    }
+
+    /// Encode as an LVGL image and wrap it in a C source with `image` as the variable name.
+    /// Use [`to_c_array`] directly to pick another name.
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        let bin = LVGL {}.encode(data, encoder_params)?;
+        to_c_array(&bin, "image").map(String::into_bytes)
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        LVGL {}.decode(CArraySource::try_parse(&data)?.to_bin())
+    }
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        let source = CArraySource::try_parse(data)?;
+
+        let mut info = LVGL {}.info(&source.to_bin())?;
+        info.data_size = source.data.len() as u32;
+        info.format = format!("{} C Array", info.format);
+        if let Value::Object(ref mut map) = info.other_info {
+            map.insert("Variable Name".to_owned(), Value::from(source.name));
+        }
+
+        Ok(info)
+    }
+}
@@ -1,5 +1,10 @@
+use crate::endecoder::lvgl::yuv::{rgba8888_from_yuv, rgba8888_to_yuv};
 use crate::endecoder::lvgl::ColorFormat;
-use image::imageops;
+use crate::endecoder::utils::dither::{dither, DitherTarget};
+use crate::endecoder::utils::quantize::Palette;
+use crate::error::{IcuError, Result};
+use crate::midata::luma;
+use crate::EncoderParams;
 use image::RgbaImage;
 use std::iter;
 
@@ -9,13 +14,13 @@ pub fn rgba8888_to(
     width: u32,
     height: u32,
     stride: u32,
-    dither: Option<u32>,
-) -> Vec<u8> {
+    encoder_params: &EncoderParams,
+) -> Result<Vec<u8>> {
     let stride_bytes = stride as usize;
     let color_bytes = ColorFormat::ARGB8888.get_size() as usize;
     let width_bytes = width as usize * color_bytes;
 
-    match color_format {
+    Ok(match color_format {
         ColorFormat::RGB888 => data
             .chunks_exact(width_bytes)
             .flat_map(|row| {
@@ -46,37 +51,50 @@ pub fn rgba8888_to(
             })
             .copied()
             .collect(),
-        ColorFormat::RGB565 => data
-            .chunks_exact(width_bytes)
-            .flat_map(|row| {
-                row.chunks_exact(color_bytes)
-                    .flat_map(|chunk| {
-                        let r = (chunk[0] >> 3) as u16;
-                        let g = (chunk[1] >> 2) as u16;
-                        let b = (chunk[2] >> 3) as u16;
-                        let rgb = (r << 11) | (g << 5) | b;
-                        rgb.to_le_bytes()
-                    })
-                    .chain(iter::repeat(0))
-                    .take(stride_bytes)
-            })
-            .collect(),
-        ColorFormat::RGB565A8 => data
-            .chunks_exact(width_bytes)
-            .flat_map(|row| {
-                row.chunks_exact(color_bytes)
-                    .flat_map(|chunk| {
-                        let r = (chunk[0] >> 3) as u16;
-                        let g = (chunk[1] >> 2) as u16;
-                        let b = (chunk[2] >> 3) as u16;
-                        let rgb = (r << 11) | (g << 5) | b;
-                        rgb.to_le_bytes()
-                    })
-                    .chain(iter::repeat(0))
-                    .take(stride_bytes)
-            })
-            .chain(data.chunks_exact(color_bytes).map(|chunk| chunk[3]))
-            .collect(),
+        ColorFormat::RGB565 => pack_rows(data, width, stride_bytes, |pix| {
+            rgb565_from_rgba(pix).to_le_bytes()
+        }),
+        ColorFormat::RGB565A8 => pack_rows(data, width, stride_bytes, |pix| {
+            rgb565_from_rgba(pix).to_le_bytes()
+        })
+        .into_iter()
+        .chain(data.chunks_exact(color_bytes).map(|chunk| chunk[3]))
+        .collect(),
+        ColorFormat::RGB565Swapped => pack_rows(data, width, stride_bytes, |pix| {
+            rgb565_from_rgba(pix).to_be_bytes()
+        }),
+        ColorFormat::ARGB8565 => pack_rows(data, width, stride_bytes, |pix| {
+            let [lo, hi] = rgb565_from_rgba(pix).to_le_bytes();
+            [lo, hi, pix[3]]
+        }),
+        ColorFormat::AL88 => pack_rows(data, width, stride_bytes, |pix| [luma(pix), pix[3]]),
+        ColorFormat::ARGB1555 => pack_rows(data, width, stride_bytes, |pix| {
+            let argb = ((pix[3] as u16 >> 7) << 15)
+                | ((pix[0] as u16 >> 3) << 10)
+                | ((pix[1] as u16 >> 3) << 5)
+                | (pix[2] as u16 >> 3);
+            argb.to_le_bytes()
+        }),
+        ColorFormat::ARGB4444 => pack_rows(data, width, stride_bytes, |pix| {
+            let argb = ((pix[3] as u16 >> 4) << 12)
+                | ((pix[0] as u16 >> 4) << 8)
+                | ((pix[1] as u16 >> 4) << 4)
+                | (pix[2] as u16 >> 4);
+            argb.to_le_bytes()
+        }),
+        ColorFormat::ARGB2222 => pack_rows(data, width, stride_bytes, |pix| {
+            [(pix[3] >> 6) << 6 | (pix[0] >> 6) << 4 | (pix[1] >> 6) << 2 | pix[2] >> 6]
+        }),
+        ColorFormat::L16 => pack_rows(data, width, stride_bytes, |pix| {
+            let r = pix[0] as u32;
+            let g = pix[1] as u32;
+            let b = pix[2] as u32;
+            let a = pix[3] as u32;
+            (((((3 * r + b + 4 * g) * 0x101) >> 3) * a / 0xFF) as u16).to_le_bytes()
+        }),
+        ColorFormat::A16 => pack_rows(data, width, stride_bytes, |pix| {
+            (pix[3] as u16 * 0x101).to_le_bytes()
+        }),
         ColorFormat::A1 | ColorFormat::A2 | ColorFormat::A4 => {
             let bpp = color_format.get_bpp();
 
@@ -88,7 +106,7 @@ pub fn rgba8888_to(
                 let mut byte = &mut 0u8;
 
                 for i in 0..width as u16 {
-                    let alpha = alpha_iter.next().unwrap();
+                    let alpha = alpha_iter.next().unwrap_or_default();
                     if i % (8 / bpp) == 0 {
                         if let Some(next_byte) = iter.next() {
                             byte = next_byte;
@@ -114,16 +132,9 @@ pub fn rgba8888_to(
             argb_iter.collect()
         }
         ColorFormat::L8 => {
-            // (R+R+R+B+G+G+G+G) >> 3
             let argb_iter = data.chunks_exact(width_bytes).flat_map(|row| {
                 row.chunks_exact(color_bytes)
-                    .map(|chunk| {
-                        let r = chunk[0] as u16;
-                        let g = chunk[1] as u16;
-                        let b = chunk[2] as u16;
-                        let a = chunk[3] as u16;
-                        (((3 * r + b + 4 * g) >> 3) * a / 0xFF) as u8
-                    })
+                    .map(|chunk| (luma(chunk) as u16 * chunk[3] as u16 / 0xFF) as u8)
                     .chain(iter::repeat(0))
                     .take(stride_bytes)
             });
@@ -132,57 +143,39 @@ pub fn rgba8888_to(
         ColorFormat::I1 | ColorFormat::I2 | ColorFormat::I4 | ColorFormat::I8 => {
             let bpp = color_format.get_bpp();
             let color_map_size = 1 << bpp;
-            let nq = color_quant::NeuQuant::new(dither.unwrap_or(30) as i32, color_map_size, data);
+            let palette = Palette::for_params(
+                data,
+                color_map_size,
+                encoder_params,
+                encoder_params.dither.unwrap_or(30) as i32,
+            )?;
             let mut data = data.to_vec();
 
-            if dither.is_some() {
-                let mut rgba_image = RgbaImage::from_raw(width, height, data.to_vec()).unwrap();
-                imageops::dither(&mut rgba_image, &nq);
-                data = rgba_image.clone().into_raw();
+            if encoder_params.dither.is_some() {
+                if let Some(mut rgba_image) = RgbaImage::from_raw(width, height, data.clone()) {
+                    dither(
+                        &mut rgba_image,
+                        &DitherTarget::Palette(&palette),
+                        encoder_params.dither_algorithm,
+                        encoder_params.dither_strength,
+                    );
+                    data = rgba_image.into_raw();
+                }
             }
 
-            let color_map = rgba8888_to(
-                &nq.color_map_rgba(),
-                ColorFormat::ARGB8888,
-                color_map_size as u32,
-                1,
-                ColorFormat::ARGB8888.get_stride_size(color_map_size as u32, 1),
-                dither,
-            );
-
-            let mut indexes_iter = data.chunks(color_bytes).map(|pix| nq.index_of(pix) as u8);
-
-            if color_format == ColorFormat::I8 {
-                let indexes = indexes_iter.collect::<Vec<u8>>();
-                let argb_iter = indexes
-                    .chunks_exact(width as usize)
-                    .flat_map(|row| row.iter().chain(iter::repeat(&0)).take(stride_bytes));
-                color_map.iter().chain(argb_iter).copied().collect()
-            } else {
-                let mut indexes = vec![0; stride_bytes * height as usize];
-                indexes.chunks_exact_mut(stride_bytes).for_each(|row| {
-                    let mut iter = row.iter_mut();
-                    let mut byte = &mut 0u8;
-
-                    for i in 0..width as u16 {
-                        let alpha = indexes_iter.next().unwrap();
-                        if i % (8 / bpp) == 0 {
-                            if let Some(next_byte) = iter.next() {
-                                byte = next_byte;
-                            } else {
-                                break;
-                            }
-                        }
-                        *byte |= (alpha) << ((8 / bpp - 1 - i % (8 / bpp)) * bpp);
-                    }
-                });
-                color_map.iter().chain(indexes.iter()).copied().collect()
-            }
-        }
-        _ => {
-            unimplemented!()
+            let indexes = palette.indexes(&data);
+            indexes_to(
+                color_format,
+                palette.colors(),
+                &indexes,
+                width,
+                height,
+                stride,
+            )?
         }
-    }
+        _ if color_format.is_yuv() => rgba8888_to_yuv(data, color_format, width, height, stride),
+        _ => return Err(IcuError::UnsupportedColorFormat(color_format)),
+    })
 }
 
 pub fn rgba8888_from(
@@ -191,12 +184,12 @@ pub fn rgba8888_from(
     width: u32,
     height: u32,
     stride: u32,
-) -> Vec<u8> {
+) -> Result<Vec<u8>> {
     let stride_bytes = stride as usize;
     let color_bytes = color_format.get_size() as usize;
     let width_bytes = color_format.get_stride_size(width, 1) as usize;
 
-    match color_format {
+    Ok(match color_format {
         ColorFormat::RGB888 => data
             .chunks_exact(stride_bytes)
             .flat_map(|row| {
@@ -224,20 +217,10 @@ pub fn rgba8888_from(
             })
             .copied()
             .collect(),
-        ColorFormat::RGB565 => data
-            .chunks_exact(stride_bytes)
-            .flat_map(|row| {
-                row[..width_bytes]
-                    .chunks_exact(color_bytes)
-                    .flat_map(|rgb| {
-                        let rgb = u16::from_le_bytes([rgb[0], rgb[1]]);
-                        iter::once((((rgb >> 11) & 0x1F) as u8) << 3) // R
-                            .chain(iter::once((((rgb >> 5) & 0x3F) as u8) << 2)) // G
-                            .chain(iter::once(((rgb & 0x1F) as u8) << 3)) // B
-                            .chain(iter::once(0xFFu8))
-                    })
-            })
-            .collect(),
+        ColorFormat::RGB565 => unpack_rows(data, width, stride_bytes, |pix: [u8; 2]| {
+            let [r, g, b] = rgb565_to_rgb(u16::from_le_bytes(pix));
+            [r, g, b, 0xFF]
+        }),
         ColorFormat::RGB565A8 => data
             .chunks_exact(stride_bytes)
             .zip(data[(stride_bytes * height as usize)..].chunks_exact(width as usize))
@@ -246,14 +229,54 @@ pub fn rgba8888_from(
                     .chunks_exact(color_bytes)
                     .zip(row_alpha)
                     .flat_map(|(rgb, alpha)| {
-                        let rgb = u16::from_le_bytes([rgb[0], rgb[1]]);
-                        iter::once((((rgb >> 11) & 0x1F) as u8) << 3) // R
-                            .chain(iter::once((((rgb >> 5) & 0x3F) as u8) << 2)) // G
-                            .chain(iter::once(((rgb & 0x1F) as u8) << 3)) // B
-                            .chain(iter::once(*alpha))
+                        let [r, g, b] = rgb565_to_rgb(u16::from_le_bytes([rgb[0], rgb[1]]));
+                        [r, g, b, *alpha]
                     })
             })
             .collect(),
+        ColorFormat::RGB565Swapped => unpack_rows(data, width, stride_bytes, |pix: [u8; 2]| {
+            let [r, g, b] = rgb565_expand(u16::from_be_bytes(pix));
+            [r, g, b, 0xFF]
+        }),
+        ColorFormat::ARGB8565 => unpack_rows(data, width, stride_bytes, |pix: [u8; 3]| {
+            let [r, g, b] = rgb565_expand(u16::from_le_bytes([pix[0], pix[1]]));
+            [r, g, b, pix[2]]
+        }),
+        ColorFormat::AL88 => unpack_rows(data, width, stride_bytes, |[l, a]: [u8; 2]| [l, l, l, a]),
+        ColorFormat::ARGB1555 => unpack_rows(data, width, stride_bytes, |pix: [u8; 2]| {
+            let argb = u16::from_le_bytes(pix);
+            [
+                expand_bits(argb >> 10, 5),
+                expand_bits(argb >> 5, 5),
+                expand_bits(argb, 5),
+                expand_bits(argb >> 15, 1),
+            ]
+        }),
+        ColorFormat::ARGB4444 => unpack_rows(data, width, stride_bytes, |pix: [u8; 2]| {
+            let argb = u16::from_le_bytes(pix);
+            [
+                expand_bits(argb >> 8, 4),
+                expand_bits(argb >> 4, 4),
+                expand_bits(argb, 4),
+                expand_bits(argb >> 12, 4),
+            ]
+        }),
+        ColorFormat::ARGB2222 => unpack_rows(data, width, stride_bytes, |[argb]: [u8; 1]| {
+            let argb = argb as u16;
+            [
+                expand_bits(argb >> 4, 2),
+                expand_bits(argb >> 2, 2),
+                expand_bits(argb, 2),
+                expand_bits(argb >> 6, 2),
+            ]
+        }),
+        ColorFormat::L16 => unpack_rows(data, width, stride_bytes, |pix: [u8; 2]| {
+            let l = (u16::from_le_bytes(pix) >> 8) as u8;
+            [l, l, l, 0xFF]
+        }),
+        ColorFormat::A16 => unpack_rows(data, width, stride_bytes, |pix: [u8; 2]| {
+            [0, 0, 0, (u16::from_le_bytes(pix) >> 8) as u8]
+        }),
         ColorFormat::A1 | ColorFormat::A2 | ColorFormat::A4 => {
             let bpp = color_format.get_bpp() as u8;
 
@@ -287,51 +310,181 @@ pub fn rgba8888_from(
             argb_iter.collect()
         }
         ColorFormat::I1 | ColorFormat::I2 | ColorFormat::I4 | ColorFormat::I8 => {
-            let bpp = color_format.get_bpp() as u8;
-            let color_map_size = 1 << bpp;
-            let color_map_size_bytes = color_map_size * ColorFormat::ARGB8888.get_size() as usize;
-            let color_map = rgba8888_from(
-                &data[0..color_map_size_bytes],
-                ColorFormat::ARGB8888,
-                color_map_size as u32,
-                1,
-                ColorFormat::ARGB8888.get_stride_size(color_map_size as u32, 1),
-            );
+            let (colors, indexes) = indexes_from(data, color_format, width, height, stride)?;
+            indexes
+                .iter()
+                .flat_map(|&index| colors[index as usize])
+                .collect()
+        }
+        _ if color_format.is_yuv() => rgba8888_from_yuv(data, color_format, width, height, stride),
+        _ => return Err(IcuError::UnsupportedColorFormat(color_format)),
+    })
+}
 
-            if color_format == ColorFormat::I8 {
-                data[color_map_size_bytes..]
-                    .chunks_exact(stride_bytes)
-                    .flat_map(|row| {
-                        row.iter()
-                            .flat_map(|&index| {
-                                color_map.iter().skip(index as usize * 4).take(4).copied()
-                            })
-                            .take((width * ColorFormat::ARGB8888.get_size() as u32) as usize)
-                    })
-                    .collect()
-            } else {
-                data[color_map_size_bytes..]
-                    .chunks_exact(stride_bytes)
-                    .flat_map(|row| {
-                        row.iter()
-                            .flat_map(|alpha| {
-                                (0u8..8u8 / bpp)
-                                    .map(move |i| {
-                                        let alpha = *alpha;
-                                        (alpha >> ((8 / bpp - 1 - i) * bpp))
-                                            & ((1u16 << bpp) - 1) as u8
-                                    })
-                                    .flat_map(|index| {
-                                        color_map.iter().skip(index as usize * 4).take(4).copied()
-                                    })
-                            })
-                            .take((width * ColorFormat::ARGB8888.get_size() as u32) as usize)
-                    })
-                    .collect()
+/// Color map and index rows of the indexed color formats, `colors` is padded with transparent black
+pub(super) fn indexes_to(
+    color_format: ColorFormat,
+    colors: &[[u8; 4]],
+    indexes: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Result<Vec<u8>> {
+    let bpp = color_format.get_bpp() as usize;
+    let color_map_size = 1u32 << bpp;
+    let mut color_map = colors.concat();
+    color_map.resize(color_map_size as usize * 4, 0);
+
+    let mut data = rgba8888_to(
+        &color_map,
+        ColorFormat::ARGB8888,
+        color_map_size,
+        1,
+        ColorFormat::ARGB8888.get_stride_size(color_map_size, 1),
+        &EncoderParams::default(),
+    )?;
+
+    let mut rows = vec![0u8; stride as usize * height as usize];
+    if width > 0 {
+        for (row, indexes) in rows
+            .chunks_exact_mut(stride as usize)
+            .zip(indexes.chunks_exact(width as usize))
+        {
+            for (x, &index) in indexes.iter().enumerate() {
+                let bit = x * bpp;
+                row[bit / 8] |= index << (8 - bpp - bit % 8);
             }
         }
-        _ => {
-            unimplemented!()
-        }
     }
+    data.extend(rows);
+    Ok(data)
+}
+
+/// Color map and one index per pixel of the indexed color formats
+pub(super) fn indexes_from(
+    data: &[u8],
+    color_format: ColorFormat,
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Result<(Vec<[u8; 4]>, Vec<u8>)> {
+    let bpp = color_format.get_bpp() as usize;
+    let color_map_size = 1u32 << bpp;
+    let color_map_size_bytes = ColorFormat::ARGB8888.get_stride_size(color_map_size, 1) as usize;
+    let color_map = rgba8888_from(
+        data.get(..color_map_size_bytes)
+            .ok_or(IcuError::DataSizeMismatch {
+                expected: color_map_size_bytes,
+                actual: data.len(),
+            })?,
+        ColorFormat::ARGB8888,
+        color_map_size,
+        1,
+        color_map_size_bytes as u32,
+    )?;
+    let colors = color_map
+        .chunks_exact(4)
+        .map(|pix| [pix[0], pix[1], pix[2], pix[3]])
+        .collect();
+
+    let mask = ((1u16 << bpp) - 1) as u8;
+    let indexes = data[color_map_size_bytes..]
+        .chunks_exact(stride as usize)
+        .take(height as usize)
+        .flat_map(|row| {
+            (0..width as usize).map(move |x| {
+                let bit = x * bpp;
+                (row[bit / 8] >> (8 - bpp - bit % 8)) & mask
+            })
+        })
+        .collect();
+    Ok((colors, indexes))
+}
+
+/// Scale the color channels of RGBA pixels by their alpha
+pub fn premultiply(data: &mut [u8]) {
+    data.chunks_exact_mut(4).for_each(|pix| {
+        let a = pix[3] as u16;
+        pix[..3]
+            .iter_mut()
+            .for_each(|c| *c = ((*c as u16 * a + 127) / 0xFF) as u8);
+    });
+}
+
+/// Undo [`premultiply`], fully transparent pixels stay black
+pub fn unpremultiply(data: &mut [u8]) {
+    data.chunks_exact_mut(4).for_each(|pix| {
+        let a = pix[3] as u16;
+        if a != 0 {
+            pix[..3]
+                .iter_mut()
+                .for_each(|c| *c = ((*c as u16 * 0xFF + a / 2) / a).min(0xFF) as u8);
+        }
+    });
+}
+
+/// Pack every RGBA pixel into `N` bytes and pad each row up to `stride_bytes`
+fn pack_rows<const N: usize>(
+    data: &[u8],
+    width: u32,
+    stride_bytes: usize,
+    pack: impl Fn(&[u8]) -> [u8; N],
+) -> Vec<u8> {
+    let color_bytes = ColorFormat::ARGB8888.get_size() as usize;
+    data.chunks_exact(width as usize * color_bytes)
+        .flat_map(|row| {
+            row.chunks_exact(color_bytes)
+                .flat_map(&pack)
+                .chain(iter::repeat(0))
+                .take(stride_bytes)
+        })
+        .collect()
+}
+
+/// Unpack every `N` bytes pixel into RGBA, skipping the padding at the end of each row
+fn unpack_rows<const N: usize>(
+    data: &[u8],
+    width: u32,
+    stride_bytes: usize,
+    unpack: impl Fn([u8; N]) -> [u8; 4],
+) -> Vec<u8> {
+    data.chunks_exact(stride_bytes)
+        .flat_map(|row| {
+            row[..width as usize * N]
+                .chunks_exact(N)
+                .flat_map(|pix| unpack(pix.try_into().unwrap_or([0; N])))
+        })
+        .collect()
+}
+
+pub(super) fn rgb565_from_rgba(pix: &[u8]) -> u16 {
+    let r = (pix[0] >> 3) as u16;
+    let g = (pix[1] >> 2) as u16;
+    let b = (pix[2] >> 3) as u16;
+    (r << 11) | (g << 5) | b
+}
+
+pub(super) fn rgb565_to_rgb(rgb: u16) -> [u8; 3] {
+    [
+        (((rgb >> 11) & 0x1F) as u8) << 3,
+        (((rgb >> 5) & 0x3F) as u8) << 2,
+        ((rgb & 0x1F) as u8) << 3,
+    ]
+}
+
+/// Like [`rgb565_to_rgb`], but scaled up to the full range so that white stays white.
+/// The extended formats decode this way, RGB565 and RGB565A8 keep the plain shift
+/// their existing users compare against.
+pub(super) fn rgb565_expand(rgb: u16) -> [u8; 3] {
+    [
+        expand_bits(rgb >> 11, 5),
+        expand_bits(rgb >> 5, 6),
+        expand_bits(rgb, 5),
+    ]
+}
+
+/// Scale the lowest `bits` bits of `value` up to the full 0..=255 range
+pub(super) fn expand_bits(value: u16, bits: u32) -> u8 {
+    let max = (1u16 << bits) - 1;
+    ((value & max) as u32 * 0xFF / max as u32) as u8
 }
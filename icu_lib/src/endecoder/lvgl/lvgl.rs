@@ -1,15 +1,17 @@
-use crate::endecoder::lvgl::color_converter::{rgba8888_from, rgba8888_to};
+use crate::endecoder::lvgl::color_converter::{
+    indexes_from, indexes_to, premultiply, rgba8888_from, rgba8888_to, unpremultiply,
+};
 use crate::endecoder::lvgl::{
-    has_flag, with_flag, Compress, Flags, HeaderFlag, ImageCompressedHeader, ImageDescriptor,
-    ImageHeader, LVGLVersion, LVGL,
+    has_flag, header_bytes, with_flag, ColorFormat, Compress, Flags, HeaderFlag,
+    ImageCompressedHeader, ImageDescriptor, ImageHeader, LVGLVersion, LVGL, LVGLV8,
 };
+use crate::endecoder::utils::dither::{dither, dither_f32, DitherTarget};
 use crate::endecoder::{EnDecoder, ImageInfo};
-use crate::midata::MiData;
+use crate::error::{IcuError, Result};
+use crate::midata::{IndexedImage, MiData};
 use crate::EncoderParams;
-use image::imageops;
-use image::RgbaImage;
+use image::{Rgba, RgbaImage};
 use serde_json::{json, Value};
-use std::io::{Cursor, Write};
 
 impl EnDecoder for LVGL {
     fn can_decode(&self, data: &[u8]) -> bool {
@@ -18,174 +20,434 @@ impl EnDecoder for LVGL {
             return false;
         }
 
-        let header_data = &data[..header_size];
+        ImageHeader::from_bytes(data).is_ok_and(|header| {
+            header.version() != LVGLVersion::Unknown && header.cf().has_lvgl_code()
+        })
+    }
+
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        let v8 = LVGLV8 {
+            color_depth: encoder_params.color_depth,
+            color_16_swap: encoder_params.color_16_swap,
+        };
+        if !encoder_params.color_format.has_lvgl_code() {
+            return Err(IcuError::UnsupportedColorFormat(
+                encoder_params.color_format,
+            ));
+        }
+        encode_image(data, encoder_params, &v8)
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        decode_image(data, &LVGLV8::default())
+    }
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        image_info(data, &LVGLV8::default())
+    }
+}
+
+impl EnDecoder for LVGLV8 {
+    fn can_decode(&self, data: &[u8]) -> bool {
+        ImageHeader::from_bytes(data).is_ok_and(|header| header.version() == LVGLVersion::V8)
+    }
+
+    /// Always writes V8 images for this project, whatever the params say
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        encode_image(
+            data,
+            encoder_params.with_lvgl_version(LVGLVersion::V8),
+            self,
+        )
+    }
 
-        let header = ImageHeader::decode(Vec::from(header_data));
-        header.version() != LVGLVersion::Unknown
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        decode_image(data, self)
     }
 
-    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Vec<u8> {
-        let color_format = encoder_params.color_format;
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        image_info(data, self)
+    }
+}
+
+fn is_true_color(cf: ColorFormat) -> bool {
+    matches!(
+        cf,
+        ColorFormat::TrueColor | ColorFormat::TrueColorAlpha | ColorFormat::TrueColorChromaKeyed
+    )
+}
+
+/// Encode without checking that LVGL has a code for the color format,
+/// the header is only dropped by RAW images
+pub(crate) fn encode_image(
+    data: &MiData,
+    encoder_params: EncoderParams,
+    v8: &LVGLV8,
+) -> Result<Vec<u8>> {
+    let color_format = encoder_params.color_format;
+    let indexed = match data {
+        MiData::INDEXED(indexed) => Some(indexed),
+        _ => None,
+    };
+
+    match data.to_rgba() {
+        Some(img) => {
+            let max_size = match encoder_params.lvgl_version {
+                LVGLVersion::V8 => (1 << 11) - 1,
+                LVGLVersion::V9 => u16::MAX as u32,
+                LVGLVersion::Unknown => {
+                    return Err(IcuError::Unsupported(
+                        "an LVGL version is needed to encode LVGL images".to_owned(),
+                    ))
+                }
+            };
+            if img.width() == 0 || img.height() == 0 {
+                return Err(IcuError::InvalidGeometry(format!(
+                    "{}x{} image is empty",
+                    img.width(),
+                    img.height()
+                )));
+            }
+            if img.width() > max_size || img.height() > max_size {
+                return Err(IcuError::InvalidGeometry(format!(
+                    "{}x{} exceeds the {max_size}x{max_size} limit of LVGL {:?} images",
+                    img.width(),
+                    img.height(),
+                    encoder_params.lvgl_version
+                )));
+            }
+
+            let stride = match encoder_params.lvgl_version {
+                LVGLVersion::V8 => {
+                    if color_format.v8_code().is_none() {
+                        return Err(IcuError::UnsupportedColorFormat(color_format));
+                    }
+                    if encoder_params.stride_align > 1 {
+                        log::warn!("LVGL V8 images have no stride, ignoring the stride alignment");
+                    }
+                    v8.get_stride_size(color_format, img.width())
+                }
+                _ => color_format.get_stride_size(img.width(), encoder_params.stride_align),
+            };
+            if stride > u16::MAX as u32 {
+                return Err(IcuError::InvalidGeometry(format!(
+                    "stride {stride} of {color_format:?} does not fit in the header"
+                )));
+            }
+            let mut img_data = img.clone().into_owned();
 
-        match data {
-            MiData::RGBA(img) => {
-                let stride = color_format.get_stride_size(img.width(), encoder_params.stride_align);
-                let mut img_data = img.clone();
+            let premultiplied = encoder_params.premultiply
+                && match (encoder_params.lvgl_version, color_format) {
+                    (
+                        LVGLVersion::V9,
+                        ColorFormat::ARGB8888
+                        | ColorFormat::RGB565A8
+                        | ColorFormat::ARGB8565
+                        | ColorFormat::I1
+                        | ColorFormat::I2
+                        | ColorFormat::I4
+                        | ColorFormat::I8,
+                    ) => true,
+                    _ => {
+                        log::warn!(
+                                "Premultiplied alpha is not supported by LVGL {:?} {color_format:?} images, ignoring it",
+                                encoder_params.lvgl_version
+                            );
+                        false
+                    }
+                };
+            if premultiplied {
+                premultiply(img_data.as_mut());
+            }
 
-                if let Some(dither) = encoder_params.dither {
-                    let cmap = color_quant::NeuQuant::new(dither as i32, 256, img_data.as_mut());
-                    imageops::dither(&mut img_data, &cmap);
+            if encoder_params.dither.is_some() {
+                let v8_color_depth =
+                    (encoder_params.lvgl_version == LVGLVersion::V8).then_some(v8.color_depth);
+                if let Some(target) = DitherTarget::for_color_format(color_format, v8_color_depth) {
+                    if data.is_high_precision() {
+                        // Dither from the source bits rather than from the 8-bit copy
+                        let mut precise = data
+                            .to_rgba32f()
+                            .ok_or(IcuError::UnsupportedData)?
+                            .into_owned();
+                        if premultiplied {
+                            precise.pixels_mut().for_each(|Rgba([r, g, b, a])| {
+                                *r *= *a;
+                                *g *= *a;
+                                *b *= *a;
+                            });
+                        }
+                        img_data = dither_f32(
+                            &precise,
+                            &target,
+                            encoder_params.dither_algorithm,
+                            encoder_params.dither_strength,
+                        );
+                    } else {
+                        dither(
+                            &mut img_data,
+                            &target,
+                            encoder_params.dither_algorithm,
+                            encoder_params.dither_strength,
+                        );
+                    }
                 }
+            }
+
+            // Indexed sources that fit keep their palette order and indexes
+            let lossless_palette = indexed
+                .filter(|_| encoder_params.palette.is_none())
+                .filter(|_| {
+                    matches!(
+                        color_format,
+                        ColorFormat::I1 | ColorFormat::I2 | ColorFormat::I4 | ColorFormat::I8
+                    )
+                })
+                .and_then(|indexed| indexed.palette_within(1 << color_format.get_bpp()));
 
-                let mut img_data = rgba8888_to(
+            let mut img_data = if let Some(colors) = lossless_palette {
+                let mut colors = colors.to_vec();
+                if premultiplied {
+                    premultiply(colors.as_flattened_mut());
+                }
+                indexes_to(
+                    color_format,
+                    &colors,
+                    indexed.map_or(&[], |indexed| indexed.indexes()),
+                    img.width(),
+                    img.height(),
+                    stride,
+                )?
+            } else if encoder_params.lvgl_version == LVGLVersion::V8 && is_true_color(color_format)
+            {
+                v8.rgba8888_to_true_color(img_data.as_raw(), color_format)
+            } else {
+                rgba8888_to(
                     img_data.as_mut(),
                     color_format,
                     img.width(),
                     img.height(),
                     stride,
-                    encoder_params.dither,
-                );
-
-                let mut flags = Flags::from(0u16);
-
-                match encoder_params.compress {
-                    Compress::NONE => {}
-                    Compress::Rle => {
-                        use super::super::utils::rle::RleCoder;
-                        let blk_size = ((color_format.get_bpp() + 7) >> 3) as usize;
-                        let rle_coder = RleCoder::new().with_block_size(blk_size).unwrap();
-                        let mut compressed_data = match rle_coder.encode(&img_data) {
-                            Ok(data) => data,
-                            Err(err) => {
-                                log::error!("RLE encoding failed: {err:?}");
-                                return vec![];
-                            }
-                        };
-
-                        let image_compressed_header = ImageCompressedHeader::new()
-                            .with_method(encoder_params.compress)
-                            .with_compressed_size(compressed_data.len() as u32)
-                            .with_decompressed_size(img_data.len() as u32);
-                        let mut ich_vec = image_compressed_header.into_bytes().to_vec();
-                        ich_vec.append(&mut compressed_data);
-
-                        img_data = ich_vec;
-                        flags = with_flag(flags, HeaderFlag::COMPRESSED);
-                    }
-                    _ => {}
+                    &encoder_params,
+                )?
+            };
+
+            let mut flags = Flags::from(0u16);
+            if premultiplied {
+                flags = with_flag(flags, HeaderFlag::PREMULTIPLIED);
+            }
+
+            let compress = if encoder_params.lvgl_version == LVGLVersion::V9 {
+                encoder_params.compress
+            } else {
+                if encoder_params.compress != Compress::NONE {
+                    log::warn!(
+                            "Compression is only supported by LVGL V9 images, writing uncompressed data"
+                        );
                 }
+                Compress::NONE
+            };
 
-                let mut buf = Cursor::new(Vec::new());
-                buf.write_all(
-                    &ImageDescriptor::new(
-                        ImageHeader::new(
-                            encoder_params.lvgl_version,
-                            color_format,
-                            flags,
-                            img.width() as u16,
-                            img.height() as u16,
-                            stride as u16,
-                        ),
-                        img_data,
-                    )
-                    .encode(),
-                )
-                .unwrap();
+            let compressed_data = match compress {
+                Compress::NONE => None,
+                Compress::Rle => {
+                    use super::super::utils::rle::RleCoder;
+                    let blk_size = ((color_format.get_bpp() + 7) >> 3) as usize;
+                    let rle_coder = RleCoder::new().with_block_size(blk_size)?;
+                    Some(rle_coder.encode(&img_data)?)
+                }
+                // LVGL decodes with `LZ4_decompress_safe`, so write a raw LZ4 block
+                // without the frame header or the size prefix.
+                Compress::LZ4 => Some(lz4_flex::block::compress(&img_data)),
+            };
 
-                buf.into_inner()
+            if let Some(mut compressed_data) = compressed_data {
+                let image_compressed_header = ImageCompressedHeader::new()
+                    .with_method(compress)
+                    .with_compressed_size(compressed_data.len() as u32)
+                    .with_decompressed_size(img_data.len() as u32);
+                let mut ich_vec = image_compressed_header.into_bytes().to_vec();
+                ich_vec.append(&mut compressed_data);
+
+                img_data = ich_vec;
+                flags = with_flag(flags, HeaderFlag::COMPRESSED);
             }
-            _ => Vec::new(),
+
+            Ok(ImageDescriptor::new(
+                ImageHeader::new(
+                    encoder_params.lvgl_version,
+                    color_format,
+                    flags,
+                    img.width() as u16,
+                    img.height() as u16,
+                    stride as u16,
+                ),
+                img_data,
+            )
+            .encode())
         }
+        _ => Err(IcuError::UnsupportedData),
+    }
+}
+
+fn decode_image(data: Vec<u8>, v8: &LVGLV8) -> Result<MiData> {
+    log::trace!("Decoding image with data size: {}", data.len());
+    let img_desc = ImageDescriptor::decode_with(data, v8)?;
+    if !img_desc.header.cf().has_lvgl_code() {
+        return Err(IcuError::UnsupportedColorFormat(img_desc.header.cf()));
     }
+    decode_descriptor(img_desc, v8)
+}
 
-    fn decode(&self, data: Vec<u8>) -> MiData {
-        log::trace!("Decoding image with data size: {}", data.len());
-        let img_desc = ImageDescriptor::decode(data);
+/// Decode the pixels of a descriptor whatever its color format, RAW images
+/// build one with a made up header
+pub(crate) fn decode_descriptor(img_desc: ImageDescriptor, v8: &LVGLV8) -> Result<MiData> {
+    let header = &img_desc.header;
+    if header.w() == 0 || header.h() == 0 {
+        return Ok(MiData::RGBA(RgbaImage::new(
+            header.w() as u32,
+            header.h() as u32,
+        )));
+    }
 
-        if img_desc.data_size == 0 {
-            return MiData::RGBA(RgbaImage::new(0, 0));
+    log::trace!("Decoding image with color format: {:?}", header.cf());
+    log::trace!("Decoded image header: {:#?}", img_desc.header);
+    if matches!(
+        header.cf(),
+        ColorFormat::I1 | ColorFormat::I2 | ColorFormat::I4 | ColorFormat::I8
+    ) {
+        log::trace!("Keeping the color map and indexes");
+        let (mut colors, indexes) = indexes_from(
+            &img_desc.data,
+            header.cf(),
+            header.w() as u32,
+            header.h() as u32,
+            img_desc.stride,
+        )?;
+        if has_flag(header.flags(), HeaderFlag::PREMULTIPLIED) {
+            unpremultiply(colors.as_flattened_mut());
         }
+        return IndexedImage::new(header.w() as u32, header.h() as u32, colors, indexes)
+            .map(MiData::INDEXED);
+    }
 
-        let header = &img_desc.header;
+    if header.cf() == ColorFormat::L8 {
+        log::trace!("Keeping the luminance as grayscale");
+        let (width, height) = (header.w() as usize, header.h() as usize);
+        let gray = img_desc
+            .data
+            .chunks_exact(img_desc.stride as usize)
+            .take(height)
+            .flat_map(|row| row[..width].iter().flat_map(|&luma| [luma, 0xFF]))
+            .collect::<Vec<_>>();
+        let gray_size = gray.len();
+        return MiData::from_gray(width as u32, height as u32, gray).ok_or(
+            IcuError::DataSizeMismatch {
+                expected: width * height * 2,
+                actual: gray_size,
+            },
+        );
+    }
 
-        log::trace!("Decoding image with color format: {:?}", header.cf());
-        log::trace!("Decoded image header: {:#?}", img_desc.header);
-        log::trace!("Converting image data to RGBA");
+    log::trace!("Converting image data to RGBA");
 
-        // Convert image data to RGBA
-        let img_buffer = RgbaImage::from_vec(
+    // Convert image data to RGBA
+    let mut rgba = if header.version() == LVGLVersion::V8 && is_true_color(header.cf()) {
+        v8.rgba8888_from_true_color(
+            &img_desc.data,
+            header.cf(),
             header.w() as u32,
             header.h() as u32,
-            rgba8888_from(
-                img_desc.data.clone().as_mut(),
-                header.cf(),
-                header.w() as u32,
-                header.h() as u32,
-                header.stride() as u32,
-            ),
         )
-        .unwrap();
-
-        log::trace!("Converted image data to RGBA");
-        log::trace!(
-            "Decoded image with size: {}x{}",
-            img_buffer.width(),
-            img_buffer.height()
-        );
-        log::trace!("Creating MiData object with RGBA image data and returning it");
-
-        MiData::RGBA(img_buffer)
+    } else {
+        rgba8888_from(
+            &img_desc.data,
+            header.cf(),
+            header.w() as u32,
+            header.h() as u32,
+            img_desc.stride,
+        )?
+    };
+    if has_flag(header.flags(), HeaderFlag::PREMULTIPLIED) {
+        log::trace!("Reverting premultiplied alpha");
+        unpremultiply(&mut rgba);
     }
+    let rgba_size = rgba.len();
+    let img_buffer = RgbaImage::from_vec(header.w() as u32, header.h() as u32, rgba).ok_or(
+        IcuError::DataSizeMismatch {
+            expected: header.w() as usize * header.h() as usize * 4,
+            actual: rgba_size,
+        },
+    )?;
 
-    fn info(&self, data: &[u8]) -> ImageInfo {
-        let header_size = size_of::<ImageHeader>();
+    log::trace!("Converted image data to RGBA");
+    log::trace!(
+        "Decoded image with size: {}x{}",
+        img_buffer.width(),
+        img_buffer.height()
+    );
+    log::trace!("Creating MiData object with RGBA image data and returning it");
 
-        let header_data = &data[..header_size];
+    Ok(MiData::RGBA(img_buffer))
+}
 
-        let header = ImageHeader::decode(Vec::from(header_data));
+fn image_info(data: &[u8], v8: &LVGLV8) -> Result<ImageInfo> {
+    let header = ImageHeader::from_bytes(data)?;
+    if header.version() == LVGLVersion::Unknown {
+        return Err(IcuError::UnknownFormat(
+            "not an LVGL image header".to_owned(),
+        ));
+    }
 
-        let mut other_info = serde_json::Map::new();
+    let mut other_info = serde_json::Map::new();
 
-        other_info.insert(
-            "LVGL Version".to_string(),
-            Value::from(format!("{:#?}", header.version())),
-        );
-        other_info.insert(
-            "Color Format".to_string(),
-            Value::from(format!("{:#?}", header.cf())),
-        );
-        other_info.insert(
-            "Flags".to_string(),
-            Value::from(format!("{:#?}", header.flags())),
-        );
-        if header.version() == LVGLVersion::V9 {
+    other_info.insert(
+        "LVGL Version".to_string(),
+        Value::from(format!("{:#?}", header.version())),
+    );
+    other_info.insert(
+        "Color Format".to_string(),
+        Value::from(format!("{:#?}", header.cf())),
+    );
+    other_info.insert(
+        "Flags".to_string(),
+        Value::from(format!("{:#?}", header.flags())),
+    );
+    match header.version() {
+        LVGLVersion::V9 => {
             other_info.insert("Stride".to_string(), Value::from(header.stride()));
         }
-
-        // Deal Flag has Compressed
-        if has_flag(header.flags(), HeaderFlag::COMPRESSED) {
-            let data = &data[header.header_size()..];
-            let compressed_header = ImageCompressedHeader::from_bytes([
-                data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7], data[8],
-                data[9], data[10], data[11],
-            ]);
-
+        _ => {
             other_info.insert(
-                "Compressed Info".to_owned(),
-                json!({
-                    "Method": format!("{:#?}", compressed_header.method()),
-                    "Size": compressed_header.compressed_size(),
-                    "Decompressed Size": compressed_header.decompressed_size()
-                }),
+                "Color Depth".to_string(),
+                Value::from(v8.color_depth as u16),
             );
+            other_info.insert("Color 16 Swap".to_string(), Value::from(v8.color_16_swap));
         }
+    }
 
-        ImageInfo {
-            width: header.w() as u32,
-            height: header.h() as u32,
-            data_size: data.len() as u32,
-            format: format!("LVGL.{:?}({:?})", header.version(), header.cf()),
-            other_info: Value::from(other_info),
-        }
+    // Deal Flag has Compressed
+    if has_flag(header.flags(), HeaderFlag::COMPRESSED) {
+        let compressed_header =
+            ImageCompressedHeader::from_bytes(header_bytes(&data[header.header_size()..])?);
+
+        other_info.insert(
+            "Compressed Info".to_owned(),
+            json!({
+                "Method": compressed_header
+                    .method_or_err()
+                    .map_or("Unknown".to_owned(), |method| format!("{method:#?}")),
+                "Size": compressed_header.compressed_size(),
+                "Decompressed Size": compressed_header.decompressed_size()
+            }),
+        );
     }
+
+    Ok(ImageInfo {
+        width: header.w() as u32,
+        height: header.h() as u32,
+        data_size: data.len() as u32,
+        format: format!("LVGL.{:?}({:?})", header.version(), header.cf()),
+        other_info: Value::from(other_info),
+    })
 }
@@ -1,8 +1,16 @@
+use crate::error::{IcuError, Result};
 use modular_bitfield::prelude::*;
-use std::io::{Cursor, Write};
 
+pub mod animimg;
+pub mod atlas;
+pub mod c_array;
 mod color_converter;
+#[allow(clippy::module_inception)]
 mod lvgl;
+mod v8;
+mod yuv;
+
+pub(crate) use lvgl::{decode_descriptor, encode_image};
 
 #[derive(Specifier)]
 #[bits = 8]
@@ -25,9 +33,12 @@ pub enum ColorFormat {
     #[default]
     UNKNOWN = 0x00,
 
-    // V8 formats
+    // V8 formats, their pixels are `lv_color_t` of the project's `LV_COLOR_DEPTH`
     TrueColor = 0x04,
     TrueColorAlpha = 0x05,
+    // V8 headers store TRUE_COLOR_CHROMA_KEYED as 0x06 which V9 took for L8,
+    // so it gets a free code here and is mapped when V8 headers are read and written
+    TrueColorChromaKeyed = 0x03,
 
     // 1 byte (+alpha) formats
     L8 = 0x06,
@@ -39,7 +50,10 @@ pub enum ColorFormat {
 
     // 2 bytes (+alpha) formats
     RGB565 = 0x12,
+    ARGB8565 = 0x13,
     RGB565A8 = 0x14,
+    AL88 = 0x15,
+    RGB565Swapped = 0x1B,
 
     // 3 bytes formats
     RGB888 = 0x0F,
@@ -50,10 +64,49 @@ pub enum ColorFormat {
     A1 = 0x0B,
     A2 = 0x0C,
     A4 = 0x0D,
+    ARGB1555 = 0x16,
+    ARGB4444 = 0x17,
+    ARGB2222 = 0x18,
+
+    // 16 bits per channel formats, not part of `lv_color_format_t` yet,
+    // so LVGL images refuse them and they are only for headerless RAW images
+    L16 = 0x1C,
+    A16 = 0x1D,
+
+    // YUV formats, see https://wiki.videolan.org/YUV/
+    I420 = 0x20,
+    I422 = 0x21,
+    I444 = 0x22,
+    NV21 = 0x24,
+    NV12 = 0x25,
+    YUY2 = 0x26,
+    UYVY = 0x27,
 }
 
 pub struct LVGL {}
 
+/// `LV_COLOR_DEPTH` of an LVGL V8 project
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+#[repr(u16)]
+pub enum ColorDepth {
+    Depth8 = 8,
+    Depth16 = 16,
+    #[default]
+    Depth32 = 32,
+}
+
+/// LVGL V8 images for a project built with the given `LV_COLOR_DEPTH` and
+/// `LV_COLOR_16_SWAP`, which decide the layout of the TRUE_COLOR formats.
+/// [`LVGL`] decodes V8 images as the default 32 bit color depth.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct LVGLV8 {
+    pub color_depth: ColorDepth,
+    pub color_16_swap: bool,
+}
+
+/// LVGL images embedded in C sources as `lv_image_dsc_t` / `lv_img_dsc_t`
+pub struct LVGLCArray {}
+
 #[derive(Specifier)]
 #[bits = 16]
 #[derive(Copy, Clone, Debug)]
@@ -92,6 +145,7 @@ pub enum Compress {
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
 struct ImageCompressedHeader {
+    #[allow(unused)]
     method: Compress, /*Compression method, see `lv_image_compress_t`*/
 
     #[allow(unused)]
@@ -104,7 +158,8 @@ struct ImageCompressedHeader {
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
 pub struct ImageHeaderV8 {
-    cf: ColorFormat,
+    cf: B5,
+    always_zero: B3,
     reserved: B2,
     w: B11,
     h: B11,
@@ -132,13 +187,23 @@ pub struct ImageHeaderV9 {
     reserved_2: B16,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ImageHeader {
     Unknown,
     V8(ImageHeaderV8),
     V9(ImageHeaderV9),
 }
 
+/// Copy the first `N` bytes of `data`, failing if there are not enough of them
+fn header_bytes<const N: usize>(data: &[u8]) -> Result<[u8; N]> {
+    data.get(..N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(IcuError::TruncatedHeader {
+            expected: N,
+            actual: data.len(),
+        })
+}
+
 pub fn has_flag(flags: Flags, flag: HeaderFlag) -> bool {
     flags & flag as u16 != 0
 }
@@ -148,9 +213,17 @@ pub fn with_flag(flags: Flags, flag: HeaderFlag) -> Flags {
 }
 
 impl ImageHeader {
-    pub fn from_bytes(data: &[u8]) -> Self {
-        assert!(data.len() > 4, "Invalid data size");
-        let magic = data[0];
+    /// Parse the header at the start of `data`.
+    ///
+    /// Data that doesn't start with a known header gives [`ImageHeader::Unknown`],
+    /// data that is too short for the header it starts with is an error.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let Some(&magic) = data.first() else {
+            return Err(IcuError::TruncatedHeader {
+                expected: size_of::<ImageHeaderV8>(),
+                actual: 0,
+            });
+        };
 
         let mut version = LVGLVersion::Unknown;
 
@@ -162,26 +235,26 @@ impl ImageHeader {
 
         match version {
             LVGLVersion::V8 => {
-                let header = ImageHeaderV8::from_bytes([data[0], data[1], data[2], data[3]]);
+                let header = ImageHeaderV8::from_bytes(header_bytes(data)?);
                 log::trace!("Decoded image header: {header:#?}");
-                if header.cf_or_err().is_err() || header.reserved() != 0 {
-                    ImageHeader::Unknown
+                if ColorFormat::from_v8_code(header.cf()).is_none()
+                    || header.always_zero() != 0
+                    || header.reserved() != 0
+                {
+                    Ok(ImageHeader::Unknown)
                 } else {
-                    ImageHeader::V8(header)
+                    Ok(ImageHeader::V8(header))
                 }
             }
             LVGLVersion::V9 => {
-                let header = ImageHeaderV9::from_bytes([
-                    data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
-                    data[8], data[9], data[10], data[11],
-                ]);
+                let header = ImageHeaderV9::from_bytes(header_bytes(data)?);
                 if header.cf_or_err().is_err() || header.reserved_2() != 0 {
-                    ImageHeader::Unknown
+                    Ok(ImageHeader::Unknown)
                 } else {
-                    ImageHeader::V9(header)
+                    Ok(ImageHeader::V9(header))
                 }
             }
-            _ => ImageHeader::Unknown,
+            _ => Ok(ImageHeader::Unknown),
         }
     }
 
@@ -220,7 +293,9 @@ impl ImageHeader {
     pub fn cf(&self) -> ColorFormat {
         match self {
             ImageHeader::Unknown => ColorFormat::UNKNOWN,
-            ImageHeader::V8(header) => header.cf(),
+            ImageHeader::V8(header) => {
+                ColorFormat::from_v8_code(header.cf()).unwrap_or(ColorFormat::UNKNOWN)
+            }
             ImageHeader::V9(header) => header.cf(),
         }
     }
@@ -241,6 +316,8 @@ impl ImageHeader {
         }
     }
 
+    /// V8 headers have no stride, it is derived for the default 32 bit color depth,
+    /// see [`LVGLV8::get_stride_size`] for the others
     pub fn stride(&self) -> u16 {
         match self {
             ImageHeader::Unknown => 0,
@@ -260,9 +337,12 @@ impl ImageHeader {
         stride: u16,
     ) -> Self {
         match version {
-            LVGLVersion::V8 => {
-                ImageHeader::V8(ImageHeaderV8::new().with_cf(cf).with_w(w).with_h(h))
-            }
+            LVGLVersion::V8 => ImageHeader::V8(
+                ImageHeaderV8::new()
+                    .with_cf(cf.v8_code().unwrap_or_default())
+                    .with_w(w)
+                    .with_h(h),
+            ),
             LVGLVersion::V9 => ImageHeader::V9(
                 ImageHeaderV9::new()
                     .with_magic(0x19)
@@ -280,13 +360,13 @@ impl ImageHeader {
         self.into_bytes()
     }
 
-    pub fn decode(data: Vec<u8>) -> Self {
+    pub fn decode(data: Vec<u8>) -> Result<Self> {
         log::trace!("Decoding image header with data size: {}", data.len());
 
-        let header = ImageHeader::from_bytes(data.as_slice());
+        let header = ImageHeader::from_bytes(data.as_slice())?;
 
         log::trace!("Decoded image header: {header:#?}");
-        header
+        Ok(header)
     }
 }
 
@@ -294,6 +374,7 @@ impl ImageHeader {
 #[derive(Debug)]
 pub struct ImageDescriptor {
     header: ImageHeader,
+    stride: u32,
     data_size: u32,
     data: Vec<u8>,
 }
@@ -301,6 +382,7 @@ pub struct ImageDescriptor {
 impl ImageDescriptor {
     pub fn new(header: ImageHeader, data: Vec<u8>) -> Self {
         Self {
+            stride: header.stride() as u32,
             header,
             data_size: data.len() as u32,
             data,
@@ -308,112 +390,162 @@ impl ImageDescriptor {
     }
 
     pub fn encode(&self) -> Vec<u8> {
-        let mut buf = Cursor::new(Vec::new());
-        buf.write_all(self.header.encode().as_slice()).unwrap();
-        buf.write_all(self.data.as_slice()).unwrap();
-        buf.into_inner()
+        let mut buf = self.header.encode();
+        buf.extend_from_slice(&self.data);
+        buf
+    }
+
+    pub fn decode(data: Vec<u8>) -> Result<Self> {
+        Self::decode_with(data, &LVGLV8::default())
     }
 
-    pub fn decode(data: Vec<u8>) -> Self {
+    pub fn header(&self) -> &ImageHeader {
+        &self.header
+    }
+
+    /// The RGBA color map of the indexed color formats
+    pub fn palette(&self) -> Option<Vec<[u8; 4]>> {
+        let cf = self.header.cf();
+        if !matches!(
+            cf,
+            ColorFormat::I1 | ColorFormat::I2 | ColorFormat::I4 | ColorFormat::I8
+        ) {
+            return None;
+        }
+        let size = 1u32 << cf.get_bpp();
+        let map_size = ColorFormat::ARGB8888.get_stride_size(size, 1);
+        let mut rgba = color_converter::rgba8888_from(
+            self.data.get(..map_size as usize)?,
+            ColorFormat::ARGB8888,
+            size,
+            1,
+            map_size,
+        )
+        .ok()?;
+        if has_flag(self.header.flags(), HeaderFlag::PREMULTIPLIED) {
+            color_converter::unpremultiply(&mut rgba);
+        }
+        Some(
+            rgba.chunks_exact(4)
+                .map(|pix| [pix[0], pix[1], pix[2], pix[3]])
+                .collect(),
+        )
+    }
+
+    /// Decode with the V8 TRUE_COLOR formats laid out as `v8` says
+    pub fn decode_with(data: Vec<u8>, v8: &LVGLV8) -> Result<Self> {
         log::trace!("Decoding image descriptor with data size: {}", data.len());
 
-        let header = ImageHeader::decode(data.clone());
-        let header_size = header.header_size();
-        let data = data[header_size..].to_vec();
-        let data_size = data.len() as u32;
+        let mut header = ImageHeader::decode(data.clone())?;
+        let data = data[header.header_size()..].to_vec();
 
-        match header {
+        let stride = match &mut header {
             ImageHeader::V9(header) => {
-                let stride = if header.stride() == 0 {
+                if header.stride() == 0 {
                     let assuming_stride = header.cf().get_stride_size(header.w() as u32, 1);
                     log::error!("Invalid image header, stride is 0, assuming stride to be width * color_format.byte() = {assuming_stride}");
-                    assuming_stride
-                } else {
-                    header.stride() as u32
-                };
-
-                let mut idea_data_size = stride * header.h() as u32;
-                idea_data_size += match header.cf() {
-                    ColorFormat::I1 | ColorFormat::I2 | ColorFormat::I4 | ColorFormat::I8 => {
-                        (1u32 << header.cf().get_bpp()) * ColorFormat::ARGB8888.get_size() as u32
-                    }
-                    ColorFormat::RGB565A8 => header.w() as u32 * header.h() as u32,
-                    _ => 0,
-                };
-
-                if has_flag(header.flags(), HeaderFlag::COMPRESSED) {
-                    log::trace!("Dealing Compressed image");
-                    let compressed_header = ImageCompressedHeader::from_bytes([
-                        data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
-                        data[8], data[9], data[10], data[11],
-                    ]);
-                    let method = compressed_header.method();
-                    match method {
-                        Compress::Rle => {
-                            let blk_size = ((header.cf().get_bpp() + 7) >> 3) as usize;
-                            use super::utils::rle::RleCoder;
-                            let rle_coder = RleCoder::new().with_block_size(blk_size).unwrap();
-                            if compressed_header.compressed_size()
-                                != data_size - size_of::<ImageCompressedHeader>() as u32
-                            {
-                                log::error!(
-                                    "Compressed data size mismatch, but still try to decode. current: {} expected {}",
-                                    compressed_header.compressed_size(),
-                                    data_size - size_of::<ImageCompressedHeader>() as u32
-                                );
-                            }
-                            let decoded =
-                                rle_coder.decode(&data[size_of::<ImageCompressedHeader>()..]);
-                            match decoded {
-                                Ok(decoded) => {
-                                    return Self {
-                                        header: ImageHeader::V9(header),
-                                        data_size: decoded.len() as u32,
-                                        data: decoded,
-                                    };
-                                }
-                                Err(err) => {
-                                    log::error!("Failed to decode RLE data: {err:?}");
-                                }
-                            };
-                        }
-                        _ => {
-                            log::error!("Unsupported compression method {method:?}")
-                        }
-                    }
-                    return Self {
-                        header: ImageHeader::V9(header),
-                        data_size: 0,
-                        data: vec![],
-                    };
-                } else if idea_data_size != data_size {
-                    log::error!("Data size mismatch ideal_data_size: {idea_data_size}, data_size: {data_size}, {header:#?}");
+                    header.set_stride(assuming_stride as u16);
+                }
+                let (cf, w, stride) = (header.cf(), header.w() as u32, header.stride() as u32);
+                if stride < cf.get_stride_size(w, 1) {
+                    return Err(IcuError::InvalidGeometry(format!(
+                        "stride {stride} is too small for {w} pixels of {cf:?}"
+                    )));
                 }
+                stride
             }
-            ImageHeader::V8(_) => {}
+            ImageHeader::V8(_) => v8.get_stride_size(header.cf(), header.w() as u32),
             ImageHeader::Unknown => {
-                log::error!("Unknown image header format");
-
-                return Self {
-                    header,
-                    data_size: 0,
-                    data: vec![],
-                };
+                return Err(IcuError::UnknownFormat(
+                    "not an LVGL image header".to_owned(),
+                ))
             }
+        };
+
+        let cf = header.cf();
+        let (w, h) = (header.w() as u32, header.h() as u32);
+
+        let data = if has_flag(header.flags(), HeaderFlag::COMPRESSED) {
+            log::trace!("Dealing Compressed image");
+            Self::decompress(cf, &data)?
+        } else {
+            data
+        };
+
+        let idea_data_size = cf.get_data_size(w, h, stride) as usize;
+        if data.len() < idea_data_size {
+            return Err(IcuError::DataSizeMismatch {
+                expected: idea_data_size,
+                actual: data.len(),
+            });
+        } else if data.len() > idea_data_size {
+            log::warn!(
+                "Data size mismatch, ignoring trailing bytes. ideal_data_size: {idea_data_size}, data_size: {}",
+                data.len()
+            );
         }
 
-        log::trace!("Decoded image descriptor and returned data size: {data_size}");
+        log::trace!(
+            "Decoded image descriptor and returned data size: {}",
+            data.len()
+        );
 
-        Self {
+        Ok(Self {
             header,
-            data_size,
+            stride,
+            data_size: data.len() as u32,
             data,
+        })
+    }
+
+    fn decompress(cf: ColorFormat, data: &[u8]) -> Result<Vec<u8>> {
+        let compressed_header = ImageCompressedHeader::from_bytes(header_bytes(data)?);
+        let method = compressed_header.method_or_err().map_err(|_| {
+            IcuError::Compression(format!("unknown compression method {}", data[0] & 0x0F))
+        })?;
+        let payload = &data[size_of::<ImageCompressedHeader>()..];
+        let compressed_size = compressed_header.compressed_size() as usize;
+        let decompressed_size = compressed_header.decompressed_size() as usize;
+        if compressed_size != payload.len() {
+            log::error!(
+                "Compressed data size mismatch, but still try to decode. current: {} expected {}",
+                payload.len(),
+                compressed_size
+            );
         }
+
+        let decoded = match method {
+            Compress::Rle => {
+                let blk_size = ((cf.get_bpp() + 7) >> 3) as usize;
+                use super::utils::rle::RleCoder;
+                RleCoder::new().with_block_size(blk_size)?.decode(payload)?
+            }
+            Compress::LZ4 => lz4_flex::block::decompress(
+                &payload[..compressed_size.min(payload.len())],
+                decompressed_size,
+            )
+            .map_err(|err| IcuError::Compression(format!("LZ4 {err}")))?,
+            Compress::NONE => {
+                return Err(IcuError::Compression(
+                    "the compressed flag is set but the method is NONE".to_owned(),
+                ))
+            }
+        };
+
+        if decoded.len() != decompressed_size {
+            log::error!(
+                "Decompressed data size mismatch. current: {} expected {}",
+                decoded.len(),
+                decompressed_size
+            );
+        }
+
+        Ok(decoded)
     }
 }
 
 impl ColorFormat {
-    /// Get the number of bits per pixel
+    /// Get the number of bits per pixel, planar YUV formats report the bits of their Y plane
     pub fn get_bpp(&self) -> u16 {
         match self {
             ColorFormat::UNKNOWN => 0,
@@ -425,6 +557,14 @@ impl ColorFormat {
             ColorFormat::A8 => 8,
             ColorFormat::RGB565 => 16,
             ColorFormat::RGB565A8 => 16,
+            ColorFormat::RGB565Swapped => 16,
+            ColorFormat::ARGB8565 => 24,
+            ColorFormat::AL88 => 16,
+            ColorFormat::ARGB1555 => 16,
+            ColorFormat::ARGB4444 => 16,
+            ColorFormat::ARGB2222 => 8,
+            ColorFormat::L16 => 16,
+            ColorFormat::A16 => 16,
             ColorFormat::RGB888 => 24,
             ColorFormat::ARGB8888 => 32,
             ColorFormat::XRGB8888 => 32,
@@ -433,15 +573,123 @@ impl ColorFormat {
             ColorFormat::A4 => 4,
             ColorFormat::TrueColor => ColorFormat::XRGB8888.get_bpp(),
             ColorFormat::TrueColorAlpha => ColorFormat::ARGB8888.get_bpp(),
+            ColorFormat::TrueColorChromaKeyed => ColorFormat::XRGB8888.get_bpp(),
+            ColorFormat::I420 => 8,
+            ColorFormat::I422 => 8,
+            ColorFormat::I444 => 8,
+            ColorFormat::NV21 => 8,
+            ColorFormat::NV12 => 8,
+            ColorFormat::YUY2 => 16,
+            ColorFormat::UYVY => 16,
         }
     }
 
+    /// Code of the format in the V8 `lv_img_cf_t`, `None` if V8 doesn't have it
+    pub fn v8_code(&self) -> Option<u8> {
+        match self {
+            ColorFormat::TrueColorChromaKeyed => Some(0x06),
+            ColorFormat::TrueColor
+            | ColorFormat::TrueColorAlpha
+            | ColorFormat::I1
+            | ColorFormat::I2
+            | ColorFormat::I4
+            | ColorFormat::I8
+            | ColorFormat::A1
+            | ColorFormat::A2
+            | ColorFormat::A4
+            | ColorFormat::A8
+            | ColorFormat::RGB888
+            | ColorFormat::ARGB8888
+            | ColorFormat::XRGB8888
+            | ColorFormat::RGB565
+            | ColorFormat::ARGB8565
+            | ColorFormat::RGB565A8 => Some(*self as u8),
+            _ => None,
+        }
+    }
+
+    /// Whether LVGL defines a `lv_color_format_t` code for the format,
+    /// the ones it doesn't can only be used for headerless RAW images
+    pub fn has_lvgl_code(&self) -> bool {
+        !matches!(self, ColorFormat::L16 | ColorFormat::A16)
+    }
+
+    /// Format of a V8 `lv_img_cf_t` code, the inverse of [`ColorFormat::v8_code`]
+    pub fn from_v8_code(code: u8) -> Option<Self> {
+        match code {
+            0x06 => Some(ColorFormat::TrueColorChromaKeyed),
+            _ => <ColorFormat as Specifier>::from_bytes(code)
+                .ok()
+                .filter(|cf| cf.v8_code() == Some(code)),
+        }
+    }
+
+    pub fn is_yuv(&self) -> bool {
+        matches!(
+            self,
+            ColorFormat::I420
+                | ColorFormat::I422
+                | ColorFormat::I444
+                | ColorFormat::NV21
+                | ColorFormat::NV12
+                | ColorFormat::YUY2
+                | ColorFormat::UYVY
+        )
+    }
+
     pub fn get_size(&self) -> u16 {
         (self.get_bpp() + 7) >> 3
     }
 
     pub fn get_stride_size(&self, width: u32, align: u32) -> u32 {
+        let align = align.max(1);
+        // Packed YUV stores pixels in pairs sharing one U and V
+        let width = match self {
+            ColorFormat::YUY2 | ColorFormat::UYVY => width.next_multiple_of(2),
+            _ => width,
+        };
         let stride = (width * self.get_bpp() as u32 + 7) >> 3;
         (stride + align - 1) & !(align - 1)
     }
+
+    /// Get the row size and row count of every plane following the palette.
+    /// The first plane always uses the header stride.
+    pub fn get_planes(&self, width: u32, height: u32, stride: u32) -> Vec<(u32, u32)> {
+        let chroma_stride = stride.div_ceil(2);
+        let chroma_height = height.div_ceil(2);
+        match self {
+            ColorFormat::RGB565A8 => vec![(stride, height), (width, height)],
+            ColorFormat::I420 => vec![
+                (stride, height),
+                (chroma_stride, chroma_height),
+                (chroma_stride, chroma_height),
+            ],
+            ColorFormat::I422 => vec![
+                (stride, height),
+                (chroma_stride, height),
+                (chroma_stride, height),
+            ],
+            ColorFormat::I444 => vec![(stride, height); 3],
+            ColorFormat::NV12 | ColorFormat::NV21 => {
+                vec![(stride, height), (chroma_stride * 2, chroma_height)]
+            }
+            _ => vec![(stride, height)],
+        }
+    }
+
+    /// Get the size of the pixel data including the palette and all planes
+    pub fn get_data_size(&self, width: u32, height: u32, stride: u32) -> u32 {
+        let palette = match self {
+            ColorFormat::I1 | ColorFormat::I2 | ColorFormat::I4 | ColorFormat::I8 => {
+                (1u32 << self.get_bpp()) * ColorFormat::ARGB8888.get_size() as u32
+            }
+            _ => 0,
+        };
+        palette
+            + self
+                .get_planes(width, height, stride)
+                .iter()
+                .map(|(row_size, rows)| row_size * rows)
+                .sum::<u32>()
+    }
 }
@@ -0,0 +1,106 @@
+//! LVGL V8 TRUE_COLOR formats, stored as the `lv_color_t` of the project's `LV_COLOR_DEPTH`:
+//! RGB332 for 8 bits, RGB565 for 16 bits (big endian with `LV_COLOR_16_SWAP`) and
+//! BGRX8888 for 32 bits. TRUE_COLOR_ALPHA appends an alpha byte below 32 bits and uses
+//! the X byte at 32 bits, TRUE_COLOR_CHROMA_KEYED makes `LV_COLOR_CHROMA_KEY` transparent.
+
+use crate::endecoder::lvgl::color_converter::{expand_bits, rgb565_from_rgba, rgb565_to_rgb};
+use crate::endecoder::lvgl::{ColorDepth, ColorFormat, LVGLV8};
+
+/// Default `LV_COLOR_CHROMA_KEY` of `lv_conf.h`, pure green
+const CHROMA_KEY: [u8; 4] = [0x00, 0xFF, 0x00, 0xFF];
+
+impl LVGLV8 {
+    /// Get the number of bits per pixel of `cf` in this project
+    pub fn get_bpp(&self, cf: ColorFormat) -> u16 {
+        let depth = self.color_depth as u16;
+        match cf {
+            ColorFormat::TrueColor | ColorFormat::TrueColorChromaKeyed => depth,
+            ColorFormat::TrueColorAlpha if self.color_depth == ColorDepth::Depth32 => depth,
+            ColorFormat::TrueColorAlpha => depth + 8,
+            _ => cf.get_bpp(),
+        }
+    }
+
+    /// V8 images have no stride, rows are always packed
+    pub fn get_stride_size(&self, cf: ColorFormat, width: u32) -> u32 {
+        (width * self.get_bpp(cf) as u32 + 7) >> 3
+    }
+
+    fn pack_color(&self, pix: &[u8], out: &mut Vec<u8>) {
+        match self.color_depth {
+            ColorDepth::Depth8 => {
+                out.push((pix[0] & 0xE0) | ((pix[1] & 0xE0) >> 3) | (pix[2] >> 6))
+            }
+            ColorDepth::Depth16 if self.color_16_swap => {
+                out.extend(rgb565_from_rgba(pix).to_be_bytes())
+            }
+            ColorDepth::Depth16 => out.extend(rgb565_from_rgba(pix).to_le_bytes()),
+            ColorDepth::Depth32 => out.extend([pix[2], pix[1], pix[0], 0xFF]),
+        }
+    }
+
+    fn unpack_color(&self, color: &[u8]) -> [u8; 3] {
+        match self.color_depth {
+            ColorDepth::Depth8 => {
+                let c = color[0] as u16;
+                [
+                    expand_bits(c >> 5, 3),
+                    expand_bits(c >> 2, 3),
+                    expand_bits(c, 2),
+                ]
+            }
+            ColorDepth::Depth16 if self.color_16_swap => {
+                rgb565_to_rgb(u16::from_be_bytes([color[0], color[1]]))
+            }
+            ColorDepth::Depth16 => rgb565_to_rgb(u16::from_le_bytes([color[0], color[1]])),
+            ColorDepth::Depth32 => [color[2], color[1], color[0]],
+        }
+    }
+
+    pub(super) fn rgba8888_to_true_color(&self, data: &[u8], cf: ColorFormat) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() / 4 * (self.get_bpp(cf) as usize / 8));
+        for pix in data.chunks_exact(4) {
+            match cf {
+                ColorFormat::TrueColorAlpha => {
+                    self.pack_color(pix, &mut out);
+                    if self.color_depth == ColorDepth::Depth32 {
+                        out.pop();
+                    }
+                    out.push(pix[3]);
+                }
+                ColorFormat::TrueColorChromaKeyed if pix[3] < 0x80 => {
+                    self.pack_color(&CHROMA_KEY, &mut out)
+                }
+                _ => self.pack_color(pix, &mut out),
+            }
+        }
+        out
+    }
+
+    pub(super) fn rgba8888_from_true_color(
+        &self,
+        data: &[u8],
+        cf: ColorFormat,
+        width: u32,
+        height: u32,
+    ) -> Vec<u8> {
+        let color_size = self.color_depth as usize / 8;
+        let pixel_size = self.get_bpp(cf) as usize / 8;
+        let mut key = vec![];
+        self.pack_color(&CHROMA_KEY, &mut key);
+        let key = self.unpack_color(&key);
+
+        data.chunks_exact(pixel_size)
+            .take((width * height) as usize)
+            .flat_map(|pix| {
+                let [r, g, b] = self.unpack_color(&pix[..color_size]);
+                let a = match cf {
+                    ColorFormat::TrueColorAlpha => pix[pixel_size - 1],
+                    ColorFormat::TrueColorChromaKeyed if [r, g, b] == key => 0,
+                    _ => 0xFF,
+                };
+                [r, g, b, a]
+            })
+            .collect()
+    }
+}
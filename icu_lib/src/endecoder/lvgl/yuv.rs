@@ -0,0 +1,202 @@
+//! YUV color formats, converted with the full range BT.601 (JFIF) matrix.
+//!
+//! Planar and semi-planar formats store the Y plane first with the header stride,
+//! followed by the chroma planes described by [`ColorFormat::get_planes`].
+//! Chroma is subsampled by averaging the covered pixels, YUV has no alpha so it is ignored.
+
+use crate::endecoder::lvgl::ColorFormat;
+use std::iter;
+
+/// Horizontal and vertical chroma subsampling
+fn subsampling(cf: ColorFormat) -> (u32, u32) {
+    match cf {
+        ColorFormat::I420 | ColorFormat::NV12 | ColorFormat::NV21 => (2, 2),
+        ColorFormat::I422 | ColorFormat::YUY2 | ColorFormat::UYVY => (2, 1),
+        _ => (1, 1),
+    }
+}
+
+fn rgb_to_yuv(pix: &[u8]) -> [u8; 3] {
+    let r = pix[0] as i32;
+    let g = pix[1] as i32;
+    let b = pix[2] as i32;
+    let y = (77 * r + 150 * g + 29 * b + 128) >> 8;
+    let u = ((-43 * r - 85 * g + 128 * b + 128) >> 8) + 128;
+    let v = ((128 * r - 107 * g - 21 * b + 128) >> 8) + 128;
+    [y, u, v].map(|c| c.clamp(0, 0xFF) as u8)
+}
+
+fn yuv_to_rgba(y: u8, u: u8, v: u8) -> [u8; 4] {
+    let y = y as i32;
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+    let r = y + ((359 * e + 128) >> 8);
+    let g = y - ((88 * d + 183 * e + 128) >> 8);
+    let b = y + ((454 * d + 128) >> 8);
+    let [r, g, b] = [r, g, b].map(|c| c.clamp(0, 0xFF) as u8);
+    [r, g, b, 0xFF]
+}
+
+pub(super) fn rgba8888_to_yuv(
+    data: &[u8],
+    color_format: ColorFormat,
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Vec<u8> {
+    let (w, h) = (width as usize, height as usize);
+    let (sx, sy) = subsampling(color_format);
+    let (cw, ch) = (w.div_ceil(sx as usize), h.div_ceil(sy as usize));
+
+    let yuv = data.chunks_exact(4).map(rgb_to_yuv).collect::<Vec<_>>();
+
+    // Average the chroma of every `sx` x `sy` block
+    let mut u = vec![0u8; cw * ch];
+    let mut v = vec![0u8; cw * ch];
+    for cy in 0..ch {
+        for cx in 0..cw {
+            let (mut u_sum, mut v_sum, mut count) = (0u32, 0u32, 0u32);
+            for y in cy * sy as usize..((cy + 1) * sy as usize).min(h) {
+                for x in cx * sx as usize..((cx + 1) * sx as usize).min(w) {
+                    let [_, pu, pv] = yuv[y * w + x];
+                    u_sum += pu as u32;
+                    v_sum += pv as u32;
+                    count += 1;
+                }
+            }
+            u[cy * cw + cx] = ((u_sum + count / 2) / count) as u8;
+            v[cy * cw + cx] = ((v_sum + count / 2) / count) as u8;
+        }
+    }
+
+    let planes = color_format.get_planes(width, height, stride);
+    let pad_rows = |rows: Vec<Vec<u8>>, (row_size, _): (u32, u32)| {
+        rows.into_iter().flat_map(move |row| {
+            row.into_iter()
+                .chain(iter::repeat(0))
+                .take(row_size as usize)
+        })
+    };
+    let luma_rows = || {
+        yuv.chunks_exact(w)
+            .map(|row| row.iter().map(|[y, _, _]| *y).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+
+    match color_format {
+        ColorFormat::I420 | ColorFormat::I422 | ColorFormat::I444 => {
+            let chroma_rows = |plane: &[u8]| {
+                plane
+                    .chunks_exact(cw)
+                    .map(<[u8]>::to_vec)
+                    .collect::<Vec<_>>()
+            };
+            pad_rows(luma_rows(), planes[0])
+                .chain(pad_rows(chroma_rows(&u), planes[1]))
+                .chain(pad_rows(chroma_rows(&v), planes[2]))
+                .collect()
+        }
+        ColorFormat::NV12 | ColorFormat::NV21 => {
+            let (first, second) = if color_format == ColorFormat::NV12 {
+                (&u, &v)
+            } else {
+                (&v, &u)
+            };
+            let chroma_rows = first
+                .chunks_exact(cw)
+                .zip(second.chunks_exact(cw))
+                .map(|(a, b)| a.iter().zip(b).flat_map(|(a, b)| [*a, *b]).collect())
+                .collect::<Vec<_>>();
+            pad_rows(luma_rows(), planes[0])
+                .chain(pad_rows(chroma_rows, planes[1]))
+                .collect()
+        }
+        ColorFormat::YUY2 | ColorFormat::UYVY => {
+            let rows = luma_rows()
+                .into_iter()
+                .enumerate()
+                .map(|(y, luma)| {
+                    (0..cw)
+                        .flat_map(|cx| {
+                            let y0 = luma[cx * 2];
+                            let y1 = luma.get(cx * 2 + 1).copied().unwrap_or(y0);
+                            let (pu, pv) = (u[y * cw + cx], v[y * cw + cx]);
+                            if color_format == ColorFormat::YUY2 {
+                                [y0, pu, y1, pv]
+                            } else {
+                                [pu, y0, pv, y1]
+                            }
+                        })
+                        .collect()
+                })
+                .collect::<Vec<_>>();
+            pad_rows(rows, planes[0]).collect()
+        }
+        _ => vec![],
+    }
+}
+
+pub(super) fn rgba8888_from_yuv(
+    data: &[u8],
+    color_format: ColorFormat,
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Vec<u8> {
+    let (sx, sy) = (
+        subsampling(color_format).0 as usize,
+        subsampling(color_format).1 as usize,
+    );
+    let stride = stride as usize;
+
+    // Byte offset of every plane
+    let planes = color_format.get_planes(width, height, stride as u32);
+    let offsets = planes
+        .iter()
+        .scan(0usize, |offset, (row_size, rows)| {
+            let start = *offset;
+            *offset += (row_size * rows) as usize;
+            Some((start, *row_size as usize))
+        })
+        .collect::<Vec<_>>();
+
+    let pixel = |x: usize, y: usize| -> [u8; 4] {
+        let (cx, cy) = (x / sx, y / sy);
+        match color_format {
+            ColorFormat::I420 | ColorFormat::I422 | ColorFormat::I444 => {
+                let (u_start, u_stride) = offsets[1];
+                let (v_start, v_stride) = offsets[2];
+                yuv_to_rgba(
+                    data[y * stride + x],
+                    data[u_start + cy * u_stride + cx],
+                    data[v_start + cy * v_stride + cx],
+                )
+            }
+            ColorFormat::NV12 | ColorFormat::NV21 => {
+                let (uv_start, uv_stride) = offsets[1];
+                let first = data[uv_start + cy * uv_stride + cx * 2];
+                let second = data[uv_start + cy * uv_stride + cx * 2 + 1];
+                let (u, v) = if color_format == ColorFormat::NV12 {
+                    (first, second)
+                } else {
+                    (second, first)
+                };
+                yuv_to_rgba(data[y * stride + x], u, v)
+            }
+            ColorFormat::YUY2 | ColorFormat::UYVY => {
+                let block = &data[y * stride + cx * 4..][..4];
+                let [y0, u, y1, v] = if color_format == ColorFormat::YUY2 {
+                    [block[0], block[1], block[2], block[3]]
+                } else {
+                    [block[1], block[0], block[3], block[2]]
+                };
+                yuv_to_rgba(if x % 2 == 0 { y0 } else { y1 }, u, v)
+            }
+            _ => [0; 4],
+        }
+    };
+
+    (0..height as usize)
+        .flat_map(|y| (0..width as usize).flat_map(move |x| pixel(x, y)))
+        .collect()
+}
@@ -3,6 +3,8 @@ pub mod lvgl;
 pub mod raw;
 pub mod utils;
 
+use crate::endecoder::utils::color_space::{convert_color_space, ColorSpace};
+use crate::error::Result;
 use crate::midata::MiData;
 use crate::EncoderParams;
 use serde::Serialize;
@@ -19,17 +21,31 @@ pub struct ImageInfo {
 
 pub trait EnDecoder {
     fn can_decode(&self, data: &[u8]) -> bool;
-    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Vec<u8>;
-    fn decode(&self, data: Vec<u8>) -> MiData;
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>>;
 
-    fn info(&self, data: &[u8]) -> ImageInfo;
+    fn decode(&self, data: Vec<u8>) -> Result<MiData>;
+
+    /// Decode into the `color_space` of the panel.
+    /// Formats without color profiles are taken as sRGB.
+    fn decode_in(&self, data: Vec<u8>, color_space: ColorSpace) -> Result<MiData> {
+        let mid = self.decode(data)?;
+        match color_space {
+            ColorSpace::Srgb => Ok(mid),
+            _ => convert_color_space(mid, &ColorSpace::Srgb.profile(), color_space),
+        }
+    }
+
+    fn info(&self, data: &[u8]) -> Result<ImageInfo>;
 }
 
 pub fn find_endecoder(data: &[u8]) -> Option<&'static dyn EnDecoder> {
     let eds = vec![
         &common::PNG {} as &dyn EnDecoder,
         &common::AutoDetect {} as &dyn EnDecoder,
+        &lvgl::LVGLCArray {} as &dyn EnDecoder,
         &lvgl::LVGL {} as &dyn EnDecoder,
+        // TGA has no magic bytes, only guess it once nothing else matched
+        &common::TGA {} as &dyn EnDecoder,
     ];
 
     for ed in eds {
@@ -41,3 +57,15 @@ pub fn find_endecoder(data: &[u8]) -> Option<&'static dyn EnDecoder> {
 
     None
 }
+
+/// Like [`find_endecoder`], falling back to the file extension for formats without magic bytes,
+/// e.g. TGA files whose header doesn't look like one
+pub fn find_endecoder_with_extension(
+    data: &[u8],
+    extension: Option<&str>,
+) -> Option<&'static dyn EnDecoder> {
+    find_endecoder(data).or_else(|| match extension?.to_ascii_lowercase().as_str() {
+        "tga" | "icb" | "vda" | "vst" => Some(&common::TGA {} as &dyn EnDecoder),
+        _ => None,
+    })
+}
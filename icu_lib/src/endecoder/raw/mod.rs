@@ -1,23 +1,157 @@
+use crate::endecoder::lvgl::{
+    decode_descriptor, encode_image, ColorFormat, Compress, ImageDescriptor, ImageHeader,
+    ImageHeaderV9, LVGLVersion, LVGLV8,
+};
 use crate::endecoder::{EnDecoder, ImageInfo};
+use crate::error::{IcuError, Result};
 use crate::midata::MiData;
 use crate::EncoderParams;
+use serde_json::Value;
 
-pub struct RawImage {}
+/// Headerless pixel data, such as framebuffer dumps or DMA captures.
+///
+/// Raw data carries no geometry, so it has to be described up front.
+/// The pixel layout is the same as the data part of an LVGL image in the given color format.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RawImage {
+    pub width: u32,
+    pub height: u32,
+    pub color_format: ColorFormat,
+    /// Bytes per row, 0 means tightly packed rows
+    pub stride: u32,
+    /// Bytes to skip before the first pixel
+    pub offset: usize,
+}
+
+impl Default for RawImage {
+    fn default() -> Self {
+        Self::new(0, 0, ColorFormat::UNKNOWN)
+    }
+}
+
+impl RawImage {
+    pub const fn new(width: u32, height: u32, color_format: ColorFormat) -> Self {
+        Self {
+            width,
+            height,
+            color_format,
+            stride: 0,
+            offset: 0,
+        }
+    }
+
+    pub fn with_stride(mut self, stride: u32) -> Self {
+        self.stride = stride;
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn stride(&self) -> u32 {
+        if self.stride == 0 {
+            self.color_format.get_stride_size(self.width, 1)
+        } else {
+            self.stride
+        }
+    }
+
+    pub fn data_size(&self) -> usize {
+        self.color_format
+            .get_data_size(self.width, self.height, self.stride()) as usize
+    }
+}
 
 impl EnDecoder for RawImage {
-    fn can_decode(&self, _data: &[u8]) -> bool {
-        todo!()
+    fn can_decode(&self, data: &[u8]) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self.color_format != ColorFormat::UNKNOWN
+            && data.len() >= self.offset + self.data_size()
     }
 
-    fn encode(&self, _data: &MiData, _encoder_params: EncoderParams) -> Vec<u8> {
-        todo!()
+    fn encode(&self, data: &MiData, encoder_params: EncoderParams) -> Result<Vec<u8>> {
+        let color_format = if encoder_params.color_format == ColorFormat::UNKNOWN {
+            self.color_format
+        } else {
+            encoder_params.color_format
+        };
+
+        let encoded = encode_image(
+            data,
+            EncoderParams {
+                color_format,
+                compress: Compress::NONE,
+                lvgl_version: LVGLVersion::V9,
+                ..encoder_params
+            },
+            &LVGLV8::default(),
+        )?;
+
+        Ok(encoded[size_of::<ImageHeaderV9>()..].to_vec())
     }
 
-    fn decode(&self, _data: Vec<u8>) -> MiData {
-        todo!()
+    fn decode(&self, data: Vec<u8>) -> Result<MiData> {
+        if self.width > u16::MAX as u32 || self.height > u16::MAX as u32 {
+            return Err(IcuError::InvalidGeometry(format!(
+                "raw image size {}x{} is too large",
+                self.width, self.height
+            )));
+        }
+        if self.stride > u16::MAX as u32 {
+            return Err(IcuError::InvalidGeometry(format!(
+                "raw image stride {} is too large",
+                self.stride
+            )));
+        }
+
+        let data_size = self.data_size();
+        let mut pixels = data.get(self.offset..).unwrap_or_default().to_vec();
+        if pixels.len() < data_size {
+            log::warn!(
+                "Raw data is shorter than the given geometry, padding with zeros. current: {} expected {}",
+                pixels.len(),
+                data_size
+            );
+        }
+        pixels.resize(data_size, 0);
+
+        let mut bin = ImageHeader::new(
+            LVGLVersion::V9,
+            self.color_format,
+            0,
+            self.width as u16,
+            self.height as u16,
+            self.stride() as u16,
+        )
+        .encode();
+        bin.append(&mut pixels);
+
+        decode_descriptor(ImageDescriptor::decode(bin)?, &LVGLV8::default())
     }
 
-    fn info(&self, _data: &[u8]) -> ImageInfo {
-        todo!()
+    fn info(&self, data: &[u8]) -> Result<ImageInfo> {
+        let mut other_info = serde_json::Map::new();
+
+        other_info.insert(
+            "Color Format".to_string(),
+            Value::from(format!("{:#?}", self.color_format)),
+        );
+        other_info.insert("Stride".to_string(), Value::from(self.stride()));
+        other_info.insert("Offset".to_string(), Value::from(self.offset));
+        other_info.insert(
+            "Trailing Bytes".to_string(),
+            Value::from(data.len() as i64 - (self.offset + self.data_size()) as i64),
+        );
+
+        Ok(ImageInfo {
+            width: self.width,
+            height: self.height,
+            data_size: data.len() as u32,
+            format: format!("RAW({:?})", self.color_format),
+            other_info: Value::from(other_info),
+        })
     }
 }
@@ -0,0 +1,261 @@
+//! Color profiles embedded in common images, and the conversion to the color space of the panel.
+//!
+//! An ICC profile wins over the PNG `sRGB` chunk, which wins over the `gAMA` and `cHRM` chunks.
+//! Images without any of them are taken as sRGB.
+
+use crate::error::{IcuError, Result};
+use crate::midata::{IndexedImage, MiData};
+use image::{DynamicImage, ImageDecoder, ImageReader, Rgba32FImage};
+use moxcms::{
+    curve_from_gamma, Chromaticity, ColorPrimaries, ColorProfile, DataColorSpace, Layout,
+    ProfileText, TransformOptions, XyY,
+};
+use std::io::Cursor;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ColorSpace {
+    /// The color space of most panels
+    #[default]
+    Srgb,
+    DisplayP3,
+    AdobeRgb,
+    Bt2020,
+}
+
+impl ColorSpace {
+    pub fn profile(self) -> ColorProfile {
+        match self {
+            ColorSpace::Srgb => ColorProfile::new_srgb(),
+            ColorSpace::DisplayP3 => ColorProfile::new_display_p3(),
+            ColorSpace::AdobeRgb => ColorProfile::new_adobe_rgb(),
+            ColorSpace::Bt2020 => ColorProfile::new_bt2020(),
+        }
+    }
+}
+
+/// sRGB primaries without the transfer curve, the linear light of HDR and OpenEXR images
+pub fn linear_srgb() -> ColorProfile {
+    let mut profile = ColorProfile::new_srgb();
+    profile.cicp = None;
+    let curve = curve_from_gamma(1.);
+    profile.red_trc = Some(curve.clone());
+    profile.green_trc = Some(curve.clone());
+    profile.blue_trc = Some(curve);
+    profile
+}
+
+/// Color profile found in an image file
+pub struct EmbeddedProfile {
+    pub profile: ColorProfile,
+    /// Where the profile comes from, e.g. `Display P3 (ICC)` or `gamma 2.2 (gAMA)`
+    pub description: String,
+}
+
+impl EmbeddedProfile {
+    /// The profile of a common image, `None` if it has none
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if let Some(icc) = icc_profile(data) {
+            match ColorProfile::new_from_slice(&icc) {
+                Ok(profile) => {
+                    let name = profile
+                        .description
+                        .as_ref()
+                        .and_then(profile_text)
+                        .unwrap_or_else(|| "unnamed".to_owned());
+                    return Some(Self {
+                        profile,
+                        description: format!("{name} (ICC)"),
+                    });
+                }
+                Err(e) => log::warn!("Ignoring the unreadable ICC profile: {e}"),
+            }
+        }
+
+        if image::guess_format(data).ok() == Some(image::ImageFormat::Png) {
+            return png_chunks_profile(data);
+        }
+        None
+    }
+}
+
+fn icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    let mut decoder = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    decoder.icc_profile().ok().flatten()
+}
+
+fn profile_text(text: &ProfileText) -> Option<String> {
+    match text {
+        ProfileText::PlainString(text) => Some(text.clone()),
+        ProfileText::Localizable(texts) => texts.first().map(|text| text.value.clone()),
+        ProfileText::Description(text) => Some(text.ascii_string.clone()),
+    }
+    .map(|text| text.trim_end_matches('\0').to_owned())
+}
+
+/// `sRGB`, `gAMA` and `cHRM` chunks of a PNG
+fn png_chunks_profile(data: &[u8]) -> Option<EmbeddedProfile> {
+    let reader = png::Decoder::new(Cursor::new(data)).read_info().ok()?;
+    let info = reader.info();
+
+    if info.srgb.is_some() {
+        return Some(EmbeddedProfile {
+            profile: ColorProfile::new_srgb(),
+            description: "sRGB (sRGB chunk)".to_owned(),
+        });
+    }
+
+    let gamma = info.gama_chunk?.into_value();
+    // Browsers read a lone gAMA of 1/2.2 as sRGB, which is what the authors meant
+    if info.chrm_chunk.is_none() && (gamma - 1. / 2.2).abs() < 1e-4 {
+        return Some(EmbeddedProfile {
+            profile: ColorProfile::new_srgb(),
+            description: "sRGB (gAMA 1/2.2)".to_owned(),
+        });
+    }
+    if gamma <= 0. {
+        return None;
+    }
+
+    let mut profile = ColorProfile::new_srgb();
+    profile.cicp = None;
+    let curve = curve_from_gamma(1. / gamma);
+    profile.red_trc = Some(curve.clone());
+    profile.green_trc = Some(curve.clone());
+    profile.blue_trc = Some(curve);
+
+    let mut description = format!("gamma {:.2} (gAMA", 1. / gamma);
+    if let Some(chrm) = info.chrm_chunk {
+        let chromaticity =
+            |(x, y): (png::ScaledFloat, png::ScaledFloat)| (x.into_value(), y.into_value());
+        let (white_x, white_y) = chromaticity(chrm.white);
+        let [red, green, blue] = [chrm.red, chrm.green, chrm.blue].map(|xy| {
+            let (x, y) = chromaticity(xy);
+            Chromaticity::new(x, y)
+        });
+        profile.update_rgb_colorimetry(
+            XyY {
+                x: white_x as f64,
+                y: white_y as f64,
+                yb: 1.,
+            },
+            ColorPrimaries { red, green, blue },
+        );
+        description.push_str(", cHRM");
+    }
+    description.push(')');
+
+    Some(EmbeddedProfile {
+        profile,
+        description,
+    })
+}
+
+/// Convert `mid` from the profile embedded in `data` to `color_space`
+pub fn from_embedded_profile(data: &[u8], mid: MiData, color_space: ColorSpace) -> Result<MiData> {
+    match EmbeddedProfile::detect(data) {
+        Some(embedded) => convert_color_space(mid, &embedded.profile, color_space),
+        None if color_space == ColorSpace::Srgb => Ok(mid),
+        None => convert_color_space(mid, &ColorSpace::Srgb.profile(), color_space),
+    }
+}
+
+/// Convert the colors of `mid` from the `from` profile to `color_space`.
+/// Indexed images only convert their palette, grayscale images stay grayscale.
+pub fn convert_color_space(
+    mid: MiData,
+    from: &ColorProfile,
+    color_space: ColorSpace,
+) -> Result<MiData> {
+    convert_profile(mid, from, &color_space.profile())
+}
+
+/// Convert the colors of `mid` from the `from` profile to the `to` profile
+pub fn convert_profile(mid: MiData, from: &ColorProfile, to: &ColorProfile) -> Result<MiData> {
+    if matches!(mid, MiData::PATH) {
+        return Ok(mid);
+    }
+
+    let layout = match from.color_space {
+        DataColorSpace::Gray => Layout::GrayAlpha,
+        _ => Layout::Rgba,
+    };
+    let transform = from
+        .create_transform_f32(layout, to, Layout::Rgba, TransformOptions::default())
+        .map_err(|e| IcuError::Unsupported(format!("color profile: {e}")))?;
+    let apply = |colors: &[f32]| -> Result<Vec<f32>> {
+        let src = match layout {
+            Layout::GrayAlpha => colors
+                .chunks_exact(4)
+                .flat_map(|pix| [pix[0], pix[3]])
+                .collect(),
+            _ => colors.to_vec(),
+        };
+        let mut dst = vec![0.; colors.len()];
+        transform
+            .transform(&src, &mut dst)
+            .map_err(|e| IcuError::Unsupported(format!("color profile: {e}")))?;
+        dst.iter_mut().for_each(|c| *c = c.clamp(0., 1.));
+        Ok(dst)
+    };
+
+    // Profiles that match the target leave the pixels alone, rather than rounding them again
+    let probe = [0., 0.2, 0.5, 0.8, 1.]
+        .into_iter()
+        .flat_map(|v| {
+            [
+                [v, v, v, 1.],
+                [v, 0., 0., 1.],
+                [0., v, 0., 1.],
+                [0., 0., v, 1.],
+            ]
+        })
+        .map(|pix| match layout {
+            Layout::GrayAlpha => [pix[0], pix[0], pix[0], 1.],
+            _ => pix,
+        })
+        .collect::<Vec<_>>()
+        .concat();
+    let probed = apply(&probe)?;
+    if probe
+        .iter()
+        .zip(&probed)
+        .all(|(a, b)| (a - b).abs() < 1. / 2048.)
+    {
+        return Ok(mid);
+    }
+
+    if let MiData::INDEXED(img) = &mid {
+        let colors = img
+            .palette()
+            .concat()
+            .iter()
+            .map(|&c| c as f32 / 255.)
+            .collect::<Vec<_>>();
+        let palette = apply(&colors)?
+            .chunks_exact(4)
+            .map(|pix| {
+                pix.iter()
+                    .map(|c| (c * 255.).round() as u8)
+                    .collect::<Vec<_>>()
+            })
+            .map(|pix| [pix[0], pix[1], pix[2], pix[3]])
+            .collect();
+        return IndexedImage::new(img.width(), img.height(), palette, img.indexes().to_vec())
+            .map(MiData::INDEXED);
+    }
+
+    let img = mid.to_rgba32f().ok_or(IcuError::UnsupportedData)?;
+    let converted = Rgba32FImage::from_vec(img.width(), img.height(), apply(img.as_raw())?)
+        .ok_or(IcuError::UnsupportedData)?;
+    let converted = DynamicImage::ImageRgba32F(converted);
+    Ok(match mid {
+        MiData::RGBA(_) => MiData::RGBA(converted.into_rgba8()),
+        MiData::RGBA16(_) => MiData::RGBA16(converted.into_rgba16()),
+        MiData::GRAY(_) => MiData::GRAY(converted.into_luma_alpha8()),
+        _ => MiData::RGBA32F(converted.into_rgba32f()),
+    })
+}
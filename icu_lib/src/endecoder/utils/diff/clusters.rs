@@ -0,0 +1,100 @@
+//! Grouping of the differing pixels into connected regions.
+//!
+//! Pixels over the tolerance touching each other, diagonals included, belong to one cluster.
+
+use crate::endecoder::utils::diff::ImageDiffResult;
+use serde::Serialize;
+
+/// Connected region of pixels over the tolerance
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffCluster {
+    /// Left edge of the bounding box, in the coordinates of [`super::ImageDiffPixel::pos`]
+    pub x: u32,
+    /// Top edge of the bounding box
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_count: usize,
+    /// Largest channel difference of the pixels
+    pub max_diff: f32,
+    /// Mean of the largest channel difference of every pixel
+    pub mean_diff: f32,
+}
+
+impl DiffCluster {
+    fn new(x: u32, y: u32) -> Self {
+        Self {
+            x,
+            y,
+            width: 1,
+            height: 1,
+            pixel_count: 0,
+            max_diff: 0.,
+            mean_diff: 0.,
+        }
+    }
+
+    fn add(&mut self, x: u32, y: u32, diff: f32) {
+        let right = (self.x + self.width).max(x + 1);
+        let bottom = (self.y + self.height).max(y + 1);
+        self.x = self.x.min(x);
+        self.y = self.y.min(y);
+        self.width = right - self.x;
+        self.height = bottom - self.y;
+        self.pixel_count += 1;
+        self.max_diff = self.max_diff.max(diff);
+        // Running sum until the cluster is complete
+        self.mean_diff += diff;
+    }
+}
+
+impl ImageDiffResult {
+    /// Connected regions of the pixels [`Self::diff_filter`] yields, the largest first
+    pub fn clusters(&self, tolerance: f32) -> Vec<DiffCluster> {
+        let (width, height) = (self.size.0 as usize, self.size.1 as usize);
+        // Largest channel difference of every pixel over the tolerance
+        let mut mask: Vec<Option<f32>> = vec![None; width * height];
+        for pixel in self.diff_filter(tolerance) {
+            let (x, y) = (pixel.pos.0 as usize, pixel.pos.1 as usize);
+            if x < width && y < height {
+                let diff = pixel.diff.iter().fold(0f32, |max, d| max.max(d.abs()));
+                mask[y * width + x] = Some(diff);
+            }
+        }
+
+        let mut clusters = Vec::new();
+        let mut stack = Vec::new();
+        for start in 0..mask.len() {
+            if mask[start].is_none() {
+                continue;
+            }
+            let mut cluster = DiffCluster::new((start % width) as u32, (start / width) as u32);
+            stack.push(start);
+            while let Some(index) = stack.pop() {
+                let Some(diff) = mask[index].take() else {
+                    continue;
+                };
+                let (x, y) = (index % width, index / width);
+                cluster.add(x as u32, y as u32, diff);
+
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        let neighbour = ny * width + nx;
+                        if mask[neighbour].is_some() {
+                            stack.push(neighbour);
+                        }
+                    }
+                }
+            }
+            cluster.mean_diff /= cluster.pixel_count as f32;
+            clusters.push(cluster);
+        }
+
+        clusters.sort_by(|c1, c2| {
+            c2.pixel_count
+                .cmp(&c1.pixel_count)
+                .then(c2.max_diff.total_cmp(&c1.max_diff))
+        });
+        clusters
+    }
+}
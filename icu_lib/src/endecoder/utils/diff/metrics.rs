@@ -0,0 +1,325 @@
+//! Aggregate quality metrics of an image against a reference.
+//!
+//! MSE and PSNR are per RGBA channel. SSIM, MS-SSIM and CIEDE2000 compare the colors as they
+//! show over black, so differences hidden by transparency don't count.
+
+use crate::midata::MiData;
+use image::RgbaImage;
+use serde::Serialize;
+
+const MAX_VALUE: f64 = 255.;
+
+/// Gaussian window of SSIM, 11 × 11 with a sigma of 1.5
+const WINDOW_RADIUS: i64 = 5;
+const WINDOW_SIGMA: f64 = 1.5;
+const SSIM_C1: f64 = (0.01 * MAX_VALUE) * (0.01 * MAX_VALUE);
+const SSIM_C2: f64 = (0.03 * MAX_VALUE) * (0.03 * MAX_VALUE);
+
+/// Weights of the 5 scales of MS-SSIM, finest first
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// Smallest side a scale of MS-SSIM is computed on
+const MS_SSIM_MIN_SIZE: u32 = 11;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QualityMetrics {
+    /// Mean squared error of R, G, B and A
+    pub mse: [f64; 4],
+    /// Peak signal to noise ratio of R, G, B and A in dB, infinite for equal channels
+    pub psnr: [f64; 4],
+    /// PSNR of R, G and B together
+    pub psnr_rgb: f64,
+    /// Structural similarity of the luma, 1 for equal images
+    pub ssim: f64,
+    /// Multi-scale SSIM, over as many of its 5 scales as the image size allows
+    pub ms_ssim: f64,
+    /// CIEDE2000 color difference of the pixels
+    pub delta_e: DeltaEStats,
+}
+
+/// Distribution of a per pixel difference, e.g. `p95` is exceeded by 5% of the pixels
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct DeltaEStats {
+    pub mean: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// Metrics of `image` against `reference`, `None` if their sizes differ or they are empty
+pub fn quality_metrics(reference: &MiData, image: &MiData) -> Option<QualityMetrics> {
+    let reference = reference.to_rgba()?;
+    let image = image.to_rgba()?;
+    if reference.dimensions() != image.dimensions() || reference.is_empty() {
+        return None;
+    }
+
+    let count = (reference.width() * reference.height()) as f64;
+    let mut squared_error = [0.; 4];
+    for (p1, p2) in reference.pixels().zip(image.pixels()) {
+        for (channel, error) in squared_error.iter_mut().enumerate() {
+            *error += (p1[channel] as f64 - p2[channel] as f64).powi(2);
+        }
+    }
+    let mse = squared_error.map(|error| error / count);
+    let psnr_rgb = psnr(mse[..3].iter().sum::<f64>() / 3.);
+
+    let (luma1, luma2) = (Plane::luma(&reference), Plane::luma(&image));
+    let ssim = ssim_parts(&luma1, &luma2).0;
+    let ms_ssim = ms_ssim(luma1, luma2);
+
+    let mut delta_e = reference
+        .pixels()
+        .zip(image.pixels())
+        .map(|(p1, p2)| ciede2000(srgb_to_lab(shown(p1.0)), srgb_to_lab(shown(p2.0))))
+        .collect::<Vec<_>>();
+    delta_e.sort_by(f64::total_cmp);
+    let percentile = |q: f64| delta_e[((delta_e.len() - 1) as f64 * q).round() as usize];
+    let delta_e = DeltaEStats {
+        mean: delta_e.iter().sum::<f64>() / count,
+        p50: percentile(0.5),
+        p95: percentile(0.95),
+        p99: percentile(0.99),
+        max: percentile(1.),
+    };
+
+    Some(QualityMetrics {
+        mse,
+        psnr: mse.map(psnr),
+        psnr_rgb,
+        ssim,
+        ms_ssim,
+        delta_e,
+    })
+}
+
+fn psnr(mse: f64) -> f64 {
+    if mse == 0. {
+        return f64::INFINITY;
+    }
+    10. * (MAX_VALUE * MAX_VALUE / mse).log10()
+}
+
+/// RGB of a pixel over black
+fn shown(rgba: [u8; 4]) -> [f64; 3] {
+    let alpha = rgba[3] as f64 / MAX_VALUE;
+    [0, 1, 2].map(|channel| rgba[channel] as f64 * alpha)
+}
+
+/// CIELAB of an sRGB color of `0.0` to `255.0`, D65 white
+pub fn srgb_to_lab(rgb: [f64; 3]) -> [f64; 3] {
+    let [r, g, b] = rgb.map(|c| {
+        let c = c / MAX_VALUE;
+        match c <= 0.04045 {
+            true => c / 12.92,
+            false => ((c + 0.055) / 1.055).powf(2.4),
+        }
+    });
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883;
+
+    const DELTA: f64 = 6. / 29.;
+    let f = |t: f64| match t > DELTA.powi(3) {
+        true => t.cbrt(),
+        false => t / (3. * DELTA * DELTA) + 4. / 29.,
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz)]
+}
+
+/// CIEDE2000 color difference of two CIELAB colors, about 1 is just noticeable
+pub fn ciede2000(lab1: [f64; 3], lab2: [f64; 3]) -> f64 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+    let pow7 = |c: f64| c.powi(7);
+    const POW7_25: f64 = 6103515625.;
+
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.;
+    let g = 0.5 * (1. - (pow7(c_bar) / (pow7(c_bar) + POW7_25)).sqrt());
+    let (a1, a2) = ((1. + g) * a1, (1. + g) * a2);
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |a: f64, b: f64| match a == 0. && b == 0. {
+        true => 0.,
+        false => b.atan2(a).to_degrees().rem_euclid(360.),
+    };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let chroma_product = c1 * c2;
+    let delta_h = match h2 - h1 {
+        _ if chroma_product == 0. => 0.,
+        diff if diff > 180. => diff - 360.,
+        diff if diff < -180. => diff + 360.,
+        diff => diff,
+    };
+    let delta_h = 2. * chroma_product.sqrt() * (delta_h / 2.).to_radians().sin();
+
+    let l_bar = (l1 + l2) / 2.;
+    let c_bar = (c1 + c2) / 2.;
+    let h_bar = match h1 + h2 {
+        sum if chroma_product == 0. => sum,
+        sum if (h1 - h2).abs() <= 180. => sum / 2.,
+        sum if sum < 360. => (sum + 360.) / 2.,
+        sum => (sum - 360.) / 2.,
+    };
+
+    let cos = |degrees: f64| degrees.to_radians().cos();
+    let t = 1. - 0.17 * cos(h_bar - 30.) + 0.24 * cos(2. * h_bar) + 0.32 * cos(3. * h_bar + 6.)
+        - 0.20 * cos(4. * h_bar - 63.);
+    let delta_theta = 30. * (-((h_bar - 275.) / 25.).powi(2)).exp();
+    let r_c = 2. * (pow7(c_bar) / (pow7(c_bar) + POW7_25)).sqrt();
+    let s_l = 1. + 0.015 * (l_bar - 50.).powi(2) / (20. + (l_bar - 50.).powi(2)).sqrt();
+    let s_c = 1. + 0.045 * c_bar;
+    let s_h = 1. + 0.015 * c_bar * t;
+    let r_t = -(2. * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+/// One channel of an image in `f64`
+pub(super) struct Plane {
+    pub(super) width: usize,
+    pub(super) height: usize,
+    pub(super) data: Vec<f64>,
+}
+
+impl Plane {
+    /// BT.601 luma of the colors over black
+    pub(super) fn luma(img: &RgbaImage) -> Self {
+        let data = img
+            .pixels()
+            .map(|pixel| {
+                let [r, g, b] = shown(pixel.0);
+                0.299 * r + 0.587 * g + 0.114 * b
+            })
+            .collect();
+        Self {
+            width: img.width() as usize,
+            height: img.height() as usize,
+            data,
+        }
+    }
+
+    fn map2(&self, other: &Self, f: impl Fn(f64, f64) -> f64) -> Self {
+        Self {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(&a, &b)| f(a, b))
+                .collect(),
+        }
+    }
+
+    /// Gaussian blur with the SSIM window, the edges are repeated
+    fn blur(&self) -> Self {
+        let kernel = (-WINDOW_RADIUS..=WINDOW_RADIUS)
+            .map(|offset| (-((offset * offset) as f64) / (2. * WINDOW_SIGMA * WINDOW_SIGMA)).exp())
+            .collect::<Vec<_>>();
+        let sum = kernel.iter().sum::<f64>();
+        let kernel = kernel.iter().map(|weight| weight / sum).collect::<Vec<_>>();
+
+        let pass = |data: &[f64], horizontal: bool| {
+            let mut out = vec![0.; data.len()];
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    out[y * self.width + x] = kernel
+                        .iter()
+                        .enumerate()
+                        .map(|(index, weight)| {
+                            let offset = index as i64 - WINDOW_RADIUS;
+                            let (sx, sy) = match horizontal {
+                                true => (
+                                    (x as i64 + offset).clamp(0, self.width as i64 - 1),
+                                    y as i64,
+                                ),
+                                false => (
+                                    x as i64,
+                                    (y as i64 + offset).clamp(0, self.height as i64 - 1),
+                                ),
+                            };
+                            weight * data[sy as usize * self.width + sx as usize]
+                        })
+                        .sum();
+                }
+            }
+            out
+        };
+        let data = pass(&pass(&self.data, true), false);
+        Self {
+            width: self.width,
+            height: self.height,
+            data,
+        }
+    }
+
+    /// Half the size, every pixel the mean of 2 × 2
+    pub(super) fn downsample(&self) -> Self {
+        let (width, height) = (self.width / 2, self.height / 2);
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let at = |dx: usize, dy: usize| self.data[(2 * y + dy) * self.width + 2 * x + dx];
+                data.push((at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1)) / 4.);
+            }
+        }
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// Mean SSIM and mean contrast structure term of two planes
+fn ssim_parts(x: &Plane, y: &Plane) -> (f64, f64) {
+    let mu_x = x.blur();
+    let mu_y = y.blur();
+    let xx = x.map2(x, |a, b| a * b).blur();
+    let yy = y.map2(y, |a, b| a * b).blur();
+    let xy = x.map2(y, |a, b| a * b).blur();
+
+    let (mut ssim, mut cs) = (0., 0.);
+    for i in 0..x.data.len() {
+        let (mx, my) = (mu_x.data[i], mu_y.data[i]);
+        let sigma_xx = xx.data[i] - mx * mx;
+        let sigma_yy = yy.data[i] - my * my;
+        let sigma_xy = xy.data[i] - mx * my;
+        let contrast_structure = (2. * sigma_xy + SSIM_C2) / (sigma_xx + sigma_yy + SSIM_C2);
+        let luminance = (2. * mx * my + SSIM_C1) / (mx * mx + my * my + SSIM_C1);
+        ssim += luminance * contrast_structure;
+        cs += contrast_structure;
+    }
+    let count = x.data.len() as f64;
+    (ssim / count, cs / count)
+}
+
+fn ms_ssim(mut x: Plane, mut y: Plane) -> f64 {
+    let min_size = x.width.min(x.height) as u32;
+    let scales = (0..MS_SSIM_WEIGHTS.len() as u32)
+        .take_while(|&scale| min_size >> scale >= MS_SSIM_MIN_SIZE)
+        .count()
+        .max(1);
+    let weight_sum = MS_SSIM_WEIGHTS[..scales].iter().sum::<f64>();
+
+    let mut result = 1.;
+    for (scale, weight) in MS_SSIM_WEIGHTS[..scales].iter().enumerate() {
+        let (ssim, cs) = ssim_parts(&x, &y);
+        let term = match scale + 1 == scales {
+            true => ssim,
+            false => cs,
+        };
+        result *= term.max(0.).powf(weight / weight_sum);
+        if scale + 1 < scales {
+            x = x.downsample();
+            y = y.downsample();
+        }
+    }
+    result
+}
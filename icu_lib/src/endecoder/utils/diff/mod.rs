@@ -0,0 +1,321 @@
+use crate::midata::MiData;
+use image::{Pixel, Rgba, RgbaImage};
+
+pub mod clusters;
+pub mod metrics;
+pub mod registration;
+
+pub use clusters::DiffCluster;
+pub use metrics::{quality_metrics, DeltaEStats, QualityMetrics};
+pub use registration::find_offset;
+
+const RED: Rgba<u8> = Rgba([0xFF, 0x00, 0x00, 0xFF]);
+
+/// Which pixels of two images placed on each other are compared
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub enum DiffRegion {
+    /// Only where both images have pixels
+    #[default]
+    Overlap,
+    /// All pixels of both images, the ones only one image has are compared with transparent
+    Union,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DiffParams {
+    /// Position of the second image on the first one,
+    /// e.g. `(2, 0)` if the second one lost 2 columns on the left
+    pub offset: (i32, i32),
+    pub region: DiffRegion,
+}
+
+impl DiffParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_offset(mut self, x: i32, y: i32) -> Self {
+        self.offset = (x, y);
+        self
+    }
+
+    pub fn with_region(mut self, region: DiffRegion) -> Self {
+        self.region = region;
+        self
+    }
+}
+
+pub struct ImageDiffPixel {
+    pub pos: (u32, u32),
+    pub color_lhs: Rgba<u8>,
+    pub color_rhs: Rgba<u8>,
+    pub diff: [f32; 4],
+}
+
+impl ImageDiffPixel {
+    pub fn new(pos: (u32, u32), color_lhs: Rgba<u8>, color_rhs: Rgba<u8>) -> Self {
+        let mut s = Self {
+            pos,
+            color_lhs,
+            color_rhs,
+            diff: [0.; 4],
+        };
+
+        s.diff = s.calc_diff();
+        s
+    }
+
+    fn calc_diff(&self) -> [f32; 4] {
+        [
+            self.color_lhs[0] as f32 - self.color_rhs[0] as f32,
+            self.color_lhs[1] as f32 - self.color_rhs[1] as f32,
+            self.color_lhs[2] as f32 - self.color_rhs[2] as f32,
+            self.color_lhs[3] as f32 - self.color_rhs[3] as f32,
+        ]
+    }
+}
+
+pub struct ImageDiffResult {
+    size: (u32, u32),
+    diffs: Vec<ImageDiffPixel>,
+    min_diff: f32,
+    max_diff: f32,
+    origin: (i32, i32),
+}
+
+impl Default for ImageDiffResult {
+    fn default() -> Self {
+        Self::new((0, 0), Vec::new(), f32::MIN, f32::MAX)
+    }
+}
+
+impl ImageDiffResult {
+    pub fn new(size: (u32, u32), diffs: Vec<ImageDiffPixel>, min_diff: f32, max_diff: f32) -> Self {
+        Self {
+            size,
+            diffs,
+            min_diff,
+            max_diff,
+            origin: (0, 0),
+        }
+    }
+
+    pub fn with_origin(mut self, origin: (i32, i32)) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Top left of the compared region on the first image, the `pos` of the pixels is relative to it
+    pub fn origin(&self) -> (i32, i32) {
+        self.origin
+    }
+
+    pub fn diffs(&self) -> &Vec<ImageDiffPixel> {
+        &self.diffs
+    }
+
+    pub fn diff_filter(&self, tolerance: f32) -> impl Iterator<Item = &ImageDiffPixel> + use<'_> {
+        self.diffs
+            .iter()
+            .filter(move |x| x.diff.iter().any(|&d| d.abs() >= tolerance))
+    }
+
+    pub fn max_diff(&self) -> f32 {
+        self.max_diff
+    }
+
+    pub fn min_diff(&self) -> f32 {
+        self.min_diff
+    }
+
+    pub fn render_diff_mask(&self, tolerance: f32, color: Rgba<u8>) -> RgbaImage {
+        let (width, height) = self.size();
+        let mut img = RgbaImage::new(width, height);
+        for pixel in img.pixels_mut() {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+        for diff_pixel in self.diff_filter(tolerance) {
+            let (x, y) = diff_pixel.pos;
+            img.put_pixel(x, y, color);
+        }
+        img
+    }
+}
+
+pub fn blend_color32(
+    c1: &impl Pixel<Subpixel = u8>,
+    c2: &impl Pixel<Subpixel = u8>,
+    t: f32,
+) -> impl Pixel<Subpixel = u8> {
+    let t = t.clamp(0., 1.0);
+    let c1 = c1.to_rgba();
+    let c2 = c2.to_rgba();
+    Rgba::from([
+        (c1[0] as f32 * (1.0 - t) + c2[0] as f32 * t) as u8,
+        (c1[1] as f32 * (1.0 - t) + c2[1] as f32 * t) as u8,
+        (c1[2] as f32 * (1.0 - t) + c2[2] as f32 * t) as u8,
+        (c1[3] as f32 * (1.0 - t) + c2[3] as f32 * t) as u8,
+    ])
+}
+
+/// Largest difference of the RGBA channels, `0.0` to `1.0`.
+/// The same measure as the min and max diff of [`diff_image`], see [`metrics`] for perceptual ones.
+pub fn color_diff_f32(c1: &impl Pixel<Subpixel = u8>, c2: &impl Pixel<Subpixel = u8>) -> f32 {
+    let a32 = c1.to_rgba();
+    let b32 = c2.to_rgba();
+
+    let dr = a32[0].abs_diff(b32[0]);
+    let dg = a32[1].abs_diff(b32[1]);
+    let db = a32[2].abs_diff(b32[2]);
+    let da = a32[3].abs_diff(b32[3]);
+
+    dr.max(dg).max(db).max(da) as f32 / 255.0
+}
+
+/// Diff of two images of the same size
+pub fn diff_image(img1: &MiData, img2: &MiData) -> Option<ImageDiffResult> {
+    diff_image_with(img1, img2, &DiffParams::default())
+}
+
+/// Diff of the second image placed on the first one, `None` if the compared pixels are equal
+/// or the images don't overlap
+pub fn diff_image_with(
+    img1: &MiData,
+    img2: &MiData,
+    params: &DiffParams,
+) -> Option<ImageDiffResult> {
+    let (img1, img2, origin) = align(img1, img2, params)?;
+    diff_aligned(&img1, &img2, origin)
+}
+
+/// [`quality_metrics`] of the compared region of two images placed on each other
+pub fn quality_metrics_with(
+    reference: &MiData,
+    image: &MiData,
+    params: &DiffParams,
+) -> Option<QualityMetrics> {
+    let (reference, image, _) = align(reference, image, params)?;
+    quality_metrics(&MiData::RGBA(reference), &MiData::RGBA(image))
+}
+
+/// Both images on a canvas of the compared region, and the origin of that region on the first image
+fn align(
+    img1: &MiData,
+    img2: &MiData,
+    params: &DiffParams,
+) -> Option<(RgbaImage, RgbaImage, (i32, i32))> {
+    let (img1, img2) = (img1.to_rgba()?, img2.to_rgba()?);
+    let (dx, dy) = (params.offset.0 as i64, params.offset.1 as i64);
+    let (w1, h1) = (img1.width() as i64, img1.height() as i64);
+    let (w2, h2) = (img2.width() as i64, img2.height() as i64);
+    let (x0, y0, x1, y1) = match params.region {
+        DiffRegion::Overlap => (dx.max(0), dy.max(0), w1.min(dx + w2), h1.min(dy + h2)),
+        DiffRegion::Union => (dx.min(0), dy.min(0), w1.max(dx + w2), h1.max(dy + h2)),
+    };
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+
+    let canvas = |img: &RgbaImage, x: i64, y: i64| {
+        let mut canvas = RgbaImage::new((x1 - x0) as u32, (y1 - y0) as u32);
+        image::imageops::replace(&mut canvas, img, x, y);
+        canvas
+    };
+    Some((
+        canvas(&img1, -x0, -y0),
+        canvas(&img2, dx - x0, dy - y0),
+        (x0 as i32, y0 as i32),
+    ))
+}
+
+fn diff_aligned(img1: &RgbaImage, img2: &RgbaImage, origin: (i32, i32)) -> Option<ImageDiffResult> {
+    if img1 == img2 {
+        return None;
+    }
+
+    let mut diff_data = Vec::with_capacity(img1.pixels().len());
+    let mut min_diff = f32::MAX;
+    let mut max_diff = f32::MIN;
+    // First pass: find min/max diff (in absolute pixel diff)
+    for ((p1_i, p1), p2) in img1.pixels().enumerate().zip(img2.pixels()) {
+        let diff_pixel = ImageDiffPixel::new(
+            (p1_i as u32 % img1.width(), p1_i as u32 / img1.width()),
+            *p1,
+            *p2,
+        );
+        let diff = diff_pixel.diff;
+        let d = diff
+            .into_iter()
+            .map(f32::abs)
+            .reduce(f32::max)
+            .unwrap_or(0.);
+        if d < min_diff {
+            min_diff = d;
+        }
+        if d > max_diff {
+            max_diff = d;
+        }
+        diff_data.push(diff_pixel);
+    }
+    Some(
+        ImageDiffResult::new((img1.width(), img1.height()), diff_data, min_diff, max_diff)
+            .with_origin(origin),
+    )
+}
+
+pub fn blend_diff_image(
+    img1: &MiData,
+    img2: &MiData,
+    diff_blend: f32,
+    diff_tolerance: f32,
+    only_show_diff: bool,
+) -> Option<(MiData, ImageDiffResult)> {
+    blend_diff_image_with(
+        img1,
+        img2,
+        &DiffParams::default(),
+        diff_blend,
+        diff_tolerance,
+        only_show_diff,
+    )
+}
+
+/// [`blend_diff_image`] of the second image placed on the first one,
+/// the blended image covers the compared region
+pub fn blend_diff_image_with(
+    img1: &MiData,
+    img2: &MiData,
+    params: &DiffParams,
+    diff_blend: f32,
+    diff_tolerance: f32,
+    only_show_diff: bool,
+) -> Option<(MiData, ImageDiffResult)> {
+    let (img1, img2, origin) = align(img1, img2, params)?;
+    let diff_result = diff_aligned(&img1, &img2, origin)?;
+    let mut diff_mask = diff_result.render_diff_mask(diff_tolerance, RED);
+
+    if only_show_diff {
+        return Some((MiData::RGBA(diff_mask), diff_result));
+    }
+
+    for ((p1, p2), diff_pixel) in img1.pixels().zip(img2.pixels()).zip(diff_mask.pixels_mut()) {
+        let blended = blend_color32(p1, p2, diff_blend);
+        *diff_pixel = blended.to_rgba();
+    }
+
+    for pixel in diff_result.diff_filter(diff_tolerance) {
+        let (x, y) = pixel.pos;
+        let p = diff_mask.get_pixel(x, y);
+
+        let t = (diff_blend - 0.5).abs() / 0.5;
+
+        let blended = blend_color32(&RED, p, t).to_rgba();
+        diff_mask.put_pixel(x, y, blended);
+    }
+
+    Some((MiData::RGBA(diff_mask), diff_result))
+}
@@ -0,0 +1,92 @@
+//! Search of the offset that lines two images up best.
+//!
+//! The luma of both images is halved until it is small, every offset is tried on the
+//! smallest level and the best one is refined on the way back to the full size.
+
+use crate::endecoder::utils::diff::metrics::Plane;
+use crate::midata::MiData;
+
+/// Longest side of the level every offset is tried on
+const COARSEST_SIZE: usize = 32;
+
+/// Shortest side an image is still halved to
+const MIN_SIZE: usize = 4;
+
+/// Offsets around the doubled best offset tried on every finer level
+const REFINE_RADIUS: i64 = 2;
+
+/// Offset of `image` on `reference` with the least squared error of the luma, see [`super::DiffParams::offset`].
+/// At least half of the smaller image has to overlap, `None` for empty images.
+pub fn find_offset(reference: &MiData, image: &MiData) -> Option<(i32, i32)> {
+    let (reference, image) = (reference.to_rgba()?, image.to_rgba()?);
+    if reference.is_empty() || image.is_empty() {
+        return None;
+    }
+
+    let mut levels = vec![(Plane::luma(&reference), Plane::luma(&image))];
+    loop {
+        let (p1, p2) = levels.last()?;
+        let sides = [p1.width, p1.height, p2.width, p2.height];
+        let longest = sides.into_iter().max().unwrap_or(0);
+        let shortest = sides.into_iter().min().unwrap_or(0);
+        if longest <= COARSEST_SIZE || shortest < 2 * MIN_SIZE {
+            break;
+        }
+        let level = (p1.downsample(), p2.downsample());
+        levels.push(level);
+    }
+
+    let (p1, p2) = levels.last()?;
+    let candidates = (1 - p2.height as i64..p1.height as i64)
+        .flat_map(|dy| (1 - p2.width as i64..p1.width as i64).map(move |dx| (dx, dy)));
+    let mut best = best_offset(p1, p2, candidates)?;
+
+    for (p1, p2) in levels.iter().rev().skip(1) {
+        let center = (best.0 * 2, best.1 * 2);
+        let candidates = (-REFINE_RADIUS..=REFINE_RADIUS).flat_map(|dy| {
+            (-REFINE_RADIUS..=REFINE_RADIUS).map(move |dx| (center.0 + dx, center.1 + dy))
+        });
+        best = best_offset(p1, p2, candidates).unwrap_or(center);
+    }
+    Some((best.0 as i32, best.1 as i32))
+}
+
+/// The candidate with the least error, the one closest to no offset of equal ones
+fn best_offset(
+    p1: &Plane,
+    p2: &Plane,
+    candidates: impl Iterator<Item = (i64, i64)>,
+) -> Option<(i64, i64)> {
+    candidates
+        .filter_map(|offset| Some((mean_squared_error(p1, p2, offset)?, offset)))
+        .min_by(|(e1, o1), (e2, o2)| {
+            e1.total_cmp(e2)
+                .then((o1.0.abs() + o1.1.abs()).cmp(&(o2.0.abs() + o2.1.abs())))
+        })
+        .map(|(_, offset)| offset)
+}
+
+/// Error where `p2` placed at `offset` on `p1` overlaps it,
+/// `None` if less than half of the smaller plane overlaps
+fn mean_squared_error(p1: &Plane, p2: &Plane, (dx, dy): (i64, i64)) -> Option<f64> {
+    let x0 = dx.max(0);
+    let y0 = dy.max(0);
+    let x1 = (p1.width as i64).min(dx + p2.width as i64);
+    let y1 = (p1.height as i64).min(dy + p2.height as i64);
+    let overlap = (x1 - x0).max(0) * (y1 - y0).max(0);
+    let smaller = (p1.width * p1.height).min(p2.width * p2.height) as i64;
+    if overlap == 0 || overlap * 2 < smaller {
+        return None;
+    }
+
+    let mut error = 0.;
+    for y in y0..y1 {
+        let row1 = y as usize * p1.width;
+        let row2 = (y - dy) as usize * p2.width;
+        for x in x0..x1 {
+            let diff = p1.data[row1 + x as usize] - p2.data[row2 + (x - dx) as usize];
+            error += diff * diff;
+        }
+    }
+    Some(error / overlap as f64)
+}
@@ -0,0 +1,209 @@
+//! Dithering against the color depth of the target color format.
+//!
+//! Every pixel is rounded to a color the target format stores exactly, and the rounding error is either
+//! spread to the neighbors (Floyd–Steinberg, Atkinson) or hidden under an ordered Bayer threshold,
+//! so gradients don't band once the encoder drops the low bits.
+
+use crate::endecoder::lvgl::{ColorDepth, ColorFormat};
+use crate::endecoder::utils::quantize::Palette;
+use image::{Rgba32FImage, RgbaImage};
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum DitherAlgorithm {
+    /// Error diffusion to 4 neighbors, the smoothest result
+    #[default]
+    FloydSteinberg,
+    /// Error diffusion of 3/4 of the error to 6 neighbors, keeps more contrast
+    Atkinson,
+    /// Ordered 8x8 Bayer matrix, a stable pattern that compresses and animates well
+    Bayer,
+}
+
+/// Colors the encoder can store exactly
+pub enum DitherTarget<'a> {
+    /// Bits kept of the R, G, B and A channels
+    Channels([u8; 4]),
+    /// `L8` luminance, `(3R + 4G + B) / 8` scaled by alpha
+    Luma,
+    Palette(&'a Palette),
+}
+
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+const FLOYD_STEINBERG: &[(i64, i64, f32)] = &[
+    (1, 0, 7. / 16.),
+    (-1, 1, 3. / 16.),
+    (0, 1, 5. / 16.),
+    (1, 1, 1. / 16.),
+];
+
+const ATKINSON: &[(i64, i64, f32)] = &[
+    (1, 0, 1. / 8.),
+    (2, 0, 1. / 8.),
+    (-1, 1, 1. / 8.),
+    (0, 1, 1. / 8.),
+    (1, 1, 1. / 8.),
+    (0, 2, 1. / 8.),
+];
+
+impl DitherTarget<'_> {
+    /// The target of `color_format`, `None` if it keeps 8 bits per channel or isn't RGB.
+    /// `v8_color_depth` lays out the LVGL V8 TRUE_COLOR formats.
+    pub fn for_color_format(
+        color_format: ColorFormat,
+        v8_color_depth: Option<ColorDepth>,
+    ) -> Option<Self> {
+        let bits = match color_format {
+            ColorFormat::TrueColor | ColorFormat::TrueColorAlpha => match v8_color_depth? {
+                ColorDepth::Depth8 => [3, 3, 2, 8],
+                ColorDepth::Depth16 => [5, 6, 5, 8],
+                ColorDepth::Depth32 => return None,
+            },
+            ColorFormat::RGB565
+            | ColorFormat::RGB565Swapped
+            | ColorFormat::RGB565A8
+            | ColorFormat::ARGB8565 => [5, 6, 5, 8],
+            ColorFormat::ARGB1555 => [5, 5, 5, 1],
+            ColorFormat::ARGB4444 => [4, 4, 4, 4],
+            ColorFormat::ARGB2222 => [2, 2, 2, 2],
+            ColorFormat::A1 => [8, 8, 8, 1],
+            ColorFormat::A2 => [8, 8, 8, 2],
+            ColorFormat::A4 => [8, 8, 8, 4],
+            ColorFormat::L8 => return Some(Self::Luma),
+            _ => return None,
+        };
+        Some(Self::Channels(bits))
+    }
+
+    /// Amplitude of the Bayer threshold, one quantization step of every channel
+    fn spread(&self) -> [f32; 4] {
+        match self {
+            Self::Channels(bits) => bits.map(|bits| match bits {
+                8.. => 0.,
+                _ => 255. / ((1u32 << bits) - 1) as f32,
+            }),
+            Self::Luma => [1., 1., 1., 0.],
+            Self::Palette(palette) => {
+                let levels = (palette.colors().len() as f32).cbrt() - 1.;
+                let step = if levels > 0. { 255. / levels } else { 0. };
+                [step; 4]
+            }
+        }
+    }
+
+    /// The stored color closest to `color`, with the error left to diffuse
+    fn quantize(&self, color: [f32; 4]) -> ([u8; 4], [f32; 4]) {
+        let round = |value: f32| value.round().clamp(0., 255.) as u8;
+        let stored = match self {
+            Self::Channels(bits) => {
+                let mut stored = [0; 4];
+                for c in 0..4 {
+                    stored[c] = match bits[c] {
+                        8.. => round(color[c]),
+                        bits => {
+                            let max = ((1u32 << bits) - 1) as f32;
+                            round((color[c] * max / 255.).round() * 255. / max)
+                        }
+                    };
+                }
+                stored
+            }
+            Self::Luma => {
+                let luma = (3. * color[0] + 4. * color[1] + color[2]) / 8. * color[3] / 255.;
+                let stored = round(luma);
+                let error = luma - stored as f32;
+                return ([stored, stored, stored, 0xFF], [error, error, error, 0.]);
+            }
+            Self::Palette(palette) => {
+                let index = palette.index_of(&color.map(round)) as usize;
+                palette.colors().get(index).copied().unwrap_or_default()
+            }
+        };
+        let error = [0, 1, 2, 3].map(|c| color[c] - stored[c] as f32);
+        (stored, error)
+    }
+}
+
+/// Dither `img` in place so that every pixel is a color of `target`.
+/// `strength` from 0.0 to 1.0 scales the diffused error or the Bayer threshold.
+pub fn dither(
+    img: &mut RgbaImage,
+    target: &DitherTarget,
+    algorithm: DitherAlgorithm,
+    strength: f32,
+) {
+    let colors = img
+        .pixels()
+        .map(|pix| pix.0.map(|c| c as f32))
+        .collect::<Vec<_>>();
+    dither_colors(img, colors, target, algorithm, strength);
+}
+
+/// [`dither`] from a full precision image, so the bits below 8 are dithered too
+pub fn dither_f32(
+    img: &Rgba32FImage,
+    target: &DitherTarget,
+    algorithm: DitherAlgorithm,
+    strength: f32,
+) -> RgbaImage {
+    let mut dithered = RgbaImage::new(img.width(), img.height());
+    let colors = img
+        .pixels()
+        .map(|pix| pix.0.map(|c| c * 255.))
+        .collect::<Vec<_>>();
+    dither_colors(&mut dithered, colors, target, algorithm, strength);
+    dithered
+}
+
+fn dither_colors(
+    img: &mut RgbaImage,
+    mut colors: Vec<[f32; 4]>,
+    target: &DitherTarget,
+    algorithm: DitherAlgorithm,
+    strength: f32,
+) {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    let strength = strength.clamp(0., 1.);
+    let spread = target.spread();
+    let kernel = match algorithm {
+        DitherAlgorithm::FloydSteinberg => FLOYD_STEINBERG,
+        DitherAlgorithm::Atkinson => ATKINSON,
+        DitherAlgorithm::Bayer => &[],
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) as usize;
+            let mut color = colors[index].map(|c| c.clamp(0., 255.));
+            if algorithm == DitherAlgorithm::Bayer {
+                let threshold = (BAYER_8X8[(y % 8) as usize][(x % 8) as usize] as f32 + 0.5) / 64.;
+                for c in 0..4 {
+                    color[c] += (threshold - 0.5) * spread[c] * strength;
+                }
+            }
+
+            let (stored, error) = target.quantize(color);
+            img.get_pixel_mut(x as u32, y as u32).0 = stored;
+
+            for &(dx, dy, weight) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                let neighbor = &mut colors[(ny * width + nx) as usize];
+                for c in 0..4 {
+                    neighbor[c] += error[c] * weight * strength;
+                }
+            }
+        }
+    }
+}
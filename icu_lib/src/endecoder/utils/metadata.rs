@@ -0,0 +1,174 @@
+//! Metadata of common images that can be carried over to the converted image.
+//!
+//! EXIF is kept as the raw TIFF structure, PNG text chunks as keyword and text pairs,
+//! and the resolution in pixels per inch.
+
+use image::metadata::Orientation;
+use image::{ImageDecoder, ImageFormat, ImageReader};
+use std::io::Cursor;
+
+const INCH_PER_METER: f32 = 0.0254;
+const CM_PER_INCH: f32 = 2.54;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MetadataKind {
+    Exif,
+    /// PNG tEXt, zTXt and iTXt chunks
+    Text,
+    /// Pixels per inch
+    Dpi,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+    /// EXIF in TIFF layout, without the `Exif\0\0` prefix of JPEG
+    pub exif: Option<Vec<u8>>,
+    /// Keyword and text of the PNG text chunks
+    pub text: Vec<(String, String)>,
+    /// Horizontal and vertical pixels per inch
+    pub dpi: Option<(f32, f32)>,
+}
+
+impl Metadata {
+    /// Metadata of a common image, empty for other data
+    pub fn read(data: &[u8]) -> Self {
+        let format = image::guess_format(data).ok();
+        let exif = ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .ok()
+            .and_then(|reader| reader.into_decoder().ok())
+            .and_then(|mut decoder| decoder.exif_metadata().ok().flatten());
+
+        let mut metadata = Self {
+            exif,
+            ..Default::default()
+        };
+        match format {
+            Some(ImageFormat::Png) => metadata.read_png(data),
+            Some(ImageFormat::Jpeg) => metadata.dpi = jfif_dpi(data),
+            _ => {}
+        }
+
+        // TIFF keeps its resolution in the same tags as EXIF
+        if metadata.dpi.is_none() {
+            metadata.dpi = match format {
+                Some(ImageFormat::Tiff) => exif_dpi(data.to_vec()),
+                _ => metadata.exif.clone().and_then(exif_dpi),
+            };
+        }
+        metadata
+    }
+
+    fn read_png(&mut self, data: &[u8]) {
+        let Ok(mut reader) = png::Decoder::new(Cursor::new(data)).read_info() else {
+            return;
+        };
+        // Text chunks may follow the image data
+        if let Some(size) = reader.output_buffer_size() {
+            let mut buf = vec![0; size];
+            if reader.next_frame(&mut buf).is_ok() {
+                let _ = reader.finish();
+            }
+        }
+
+        let info = reader.info();
+        self.text = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()))
+            .chain(
+                info.compressed_latin1_text
+                    .iter()
+                    .filter_map(|chunk| Some((chunk.keyword.clone(), chunk.get_text().ok()?))),
+            )
+            .chain(
+                info.utf8_text
+                    .iter()
+                    .filter_map(|chunk| Some((chunk.keyword.clone(), chunk.get_text().ok()?))),
+            )
+            .collect();
+        self.dpi = info
+            .pixel_dims
+            .filter(|dims| dims.unit == png::Unit::Meter)
+            .map(|dims| {
+                (
+                    dims.xppu as f32 * INCH_PER_METER,
+                    dims.yppu as f32 * INCH_PER_METER,
+                )
+            });
+    }
+
+    /// Only the given kinds of metadata
+    pub fn keep(mut self, kinds: &[MetadataKind]) -> Self {
+        if !kinds.contains(&MetadataKind::Exif) {
+            self.exif = None;
+        }
+        if !kinds.contains(&MetadataKind::Text) {
+            self.text.clear();
+        }
+        if !kinds.contains(&MetadataKind::Dpi) {
+            self.dpi = None;
+        }
+        self
+    }
+
+    /// Resolution in the pixels per meter of PNG
+    pub fn pixels_per_meter(&self) -> Option<(u32, u32)> {
+        self.dpi.map(|(x, y)| {
+            (
+                (x / INCH_PER_METER).round() as u32,
+                (y / INCH_PER_METER).round() as u32,
+            )
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.text.is_empty() && self.dpi.is_none()
+    }
+
+    /// EXIF orientation, how the pixels have to be turned to show the image upright
+    pub fn orientation(&self) -> Option<Orientation> {
+        Orientation::from_exif_chunk(self.exif.as_ref()?)
+    }
+
+    /// Takes the EXIF orientation out, for when the pixels get turned upright.
+    /// The EXIF then says the image is upright, so viewers don't turn it once more.
+    pub fn take_orientation(&mut self) -> Option<Orientation> {
+        Orientation::remove_from_exif_chunk(self.exif.as_mut()?)
+    }
+}
+
+/// Density of the JFIF APP0 segment right after the start of image
+fn jfif_dpi(data: &[u8]) -> Option<(f32, f32)> {
+    let app0 = data.strip_prefix(b"\xFF\xD8\xFF\xE0")?.get(2..14)?;
+    let app0 = app0.strip_prefix(b"JFIF\0")?;
+    let density = |offset: usize| u16::from_be_bytes([app0[offset], app0[offset + 1]]) as f32;
+    let (x, y) = (density(3), density(5));
+    match app0[2] {
+        1 => Some((x, y)),
+        2 => Some((x * CM_PER_INCH, y * CM_PER_INCH)),
+        _ => None,
+    }
+}
+
+/// `XResolution`, `YResolution` and `ResolutionUnit` of EXIF or a TIFF file
+fn exif_dpi(exif: Vec<u8>) -> Option<(f32, f32)> {
+    let exif = exif::Reader::new().read_raw(exif).ok()?;
+    let resolution = |tag| match exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Rational(ref values) => values.first().map(|value| value.to_f64() as f32),
+        _ => None,
+    };
+    let (x, y) = (
+        resolution(exif::Tag::XResolution)?,
+        resolution(exif::Tag::YResolution)?,
+    );
+    let unit = exif
+        .get_field(exif::Tag::ResolutionUnit, exif::In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .unwrap_or(2);
+    match unit {
+        2 => Some((x, y)),
+        3 => Some((x * CM_PER_INCH, y * CM_PER_INCH)),
+        _ => None,
+    }
+}
@@ -1,2 +1,7 @@
+pub mod color_space;
 pub mod diff;
+pub mod dither;
+pub mod metadata;
+pub mod palette;
+pub mod quantize;
 pub mod rle;
@@ -0,0 +1,296 @@
+//! Palette files for the indexed color formats.
+//!
+//! Palettes are read from GIMP `.gpl`, Adobe `.act`, JASC `.pal` files or PNG swatches and written back
+//! to the same formats, colors are RGBA.
+
+use crate::endecoder::lvgl::ImageDescriptor;
+use crate::error::{IcuError, Result};
+use std::io::Cursor;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const GPL_SIGNATURE: &str = "GIMP Palette";
+const JASC_SIGNATURE: &str = "JASC-PAL";
+const ACT_SIZE: usize = 256 * 3;
+const ACT_NO_TRANSPARENT: u16 = 0xFFFF;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum PaletteFormat {
+    /// GIMP palette, text with one `R G B name` line per color
+    #[default]
+    Gpl,
+    /// Adobe color table, 256 RGB triples with an optional color count and transparent index
+    Act,
+    /// JASC (Paint Shop Pro) palette, text with one `R G B` line per color
+    Pal,
+    /// Indexed PNG with one pixel per color
+    Png,
+}
+
+impl PaletteFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "gpl" => Some(Self::Gpl),
+            "act" => Some(Self::Act),
+            "pal" => Some(Self::Pal),
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+
+    pub fn get_file_extension(&self) -> &'static str {
+        match self {
+            Self::Gpl => "gpl",
+            Self::Act => "act",
+            Self::Pal => "pal",
+            Self::Png => "png",
+        }
+    }
+
+    /// Guess the format from the content of a palette file
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(PNG_SIGNATURE) {
+            Some(Self::Png)
+        } else if data.starts_with(GPL_SIGNATURE.as_bytes()) {
+            Some(Self::Gpl)
+        } else if data.starts_with(JASC_SIGNATURE.as_bytes()) {
+            Some(Self::Pal)
+        } else if data.len() == ACT_SIZE || data.len() == ACT_SIZE + 4 {
+            Some(Self::Act)
+        } else {
+            None
+        }
+    }
+}
+
+/// Read the colors of a palette file of any [`PaletteFormat`]
+pub fn read_palette(data: &[u8]) -> Result<Vec<[u8; 4]>> {
+    let format = PaletteFormat::detect(data)
+        .ok_or_else(|| IcuError::UnknownFormat("not a GPL, ACT, PAL or PNG palette".to_owned()))?;
+    let colors = match format {
+        PaletteFormat::Gpl => read_gpl(data)?,
+        PaletteFormat::Act => read_act(data),
+        PaletteFormat::Pal => read_pal(data)?,
+        PaletteFormat::Png => read_png(data)?,
+    };
+    if colors.is_empty() || colors.len() > 256 {
+        return Err(IcuError::Unsupported(format!(
+            "palettes have 1 to 256 colors, got {}",
+            colors.len()
+        )));
+    }
+    Ok(colors)
+}
+
+pub fn write_palette(colors: &[[u8; 4]], format: PaletteFormat) -> Result<Vec<u8>> {
+    match format {
+        PaletteFormat::Gpl => Ok(write_gpl(colors)),
+        PaletteFormat::Act => write_act(colors),
+        PaletteFormat::Pal => Ok(write_pal(colors)),
+        PaletteFormat::Png => write_png(colors),
+    }
+}
+
+/// The palette of an indexed LVGL image or PNG.
+/// The unused entries padding LVGL color maps are transparent black, trailing ones collapse into one entry.
+pub fn extract_palette(data: &[u8]) -> Result<Vec<[u8; 4]>> {
+    let mut colors = if data.starts_with(PNG_SIGNATURE) {
+        let reader = png::Decoder::new(Cursor::new(data))
+            .read_info()
+            .map_err(|e| IcuError::UnknownFormat(format!("bad PNG: {e}")))?;
+        let info = reader.info();
+        if info.color_type != png::ColorType::Indexed {
+            return Err(IcuError::Unsupported(format!(
+                "{:?} PNG images have no palette",
+                info.color_type
+            )));
+        }
+        png_palette(info)
+    } else {
+        let descriptor = ImageDescriptor::decode(data.to_vec())?;
+        descriptor
+            .palette()
+            .ok_or(IcuError::UnsupportedColorFormat(descriptor.header().cf()))?
+    };
+
+    while colors.len() > 1 && colors[colors.len() - 2..] == [[0; 4], [0; 4]] {
+        colors.pop();
+    }
+    Ok(colors)
+}
+
+fn text_lines(data: &[u8]) -> Result<impl Iterator<Item = &str>> {
+    let text = std::str::from_utf8(data)
+        .map_err(|e| IcuError::UnknownFormat(format!("palette is not text: {e}")))?;
+    Ok(text.lines().map(str::trim).filter(|line| !line.is_empty()))
+}
+
+fn parse_channels<'a>(
+    mut values: impl Iterator<Item = &'a str>,
+    channels: usize,
+    line: &str,
+) -> Result<[u8; 4]> {
+    let mut color = [0xFF; 4];
+    for channel in color.iter_mut().take(channels) {
+        *channel = values
+            .next()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| IcuError::UnknownFormat(format!("bad palette color `{line}`")))?;
+    }
+    Ok(color)
+}
+
+fn read_gpl(data: &[u8]) -> Result<Vec<[u8; 4]>> {
+    let mut channels = 3;
+    let mut colors = vec![];
+    for line in text_lines(data)?.skip(1) {
+        if line.starts_with('#') || line.starts_with("Name:") || line.starts_with("Columns:") {
+            continue;
+        }
+        if let Some(value) = line.strip_prefix("Channels:") {
+            channels = if value.trim() == "RGBA" { 4 } else { 3 };
+            continue;
+        }
+        colors.push(parse_channels(line.split_whitespace(), channels, line)?);
+    }
+    Ok(colors)
+}
+
+fn write_gpl(colors: &[[u8; 4]]) -> Vec<u8> {
+    let alpha = colors.iter().any(|color| color[3] != 0xFF);
+    let mut text = format!("{GPL_SIGNATURE}\nName: icu\nColumns: 16\n");
+    if alpha {
+        text += "Channels: RGBA\n";
+    }
+    text += "#\n";
+    for (index, [r, g, b, a]) in colors.iter().enumerate() {
+        text += &if alpha {
+            format!("{r:3} {g:3} {b:3} {a:3}\tIndex {index}\n")
+        } else {
+            format!("{r:3} {g:3} {b:3}\tIndex {index}\n")
+        };
+    }
+    text.into_bytes()
+}
+
+fn read_pal(data: &[u8]) -> Result<Vec<[u8; 4]>> {
+    let mut lines = text_lines(data)?.skip(2);
+    let count: usize = lines
+        .next()
+        .and_then(|line| line.parse().ok())
+        .ok_or_else(|| IcuError::UnknownFormat("JASC palette without a color count".to_owned()))?;
+    lines
+        .take(count)
+        .map(|line| parse_channels(line.split_whitespace(), 3, line))
+        .collect()
+}
+
+fn write_pal(colors: &[[u8; 4]]) -> Vec<u8> {
+    let mut text = format!("{JASC_SIGNATURE}\r\n0100\r\n{}\r\n", colors.len());
+    for [r, g, b, _] in colors {
+        text += &format!("{r} {g} {b}\r\n");
+    }
+    text.into_bytes()
+}
+
+/// The optional footer holds the color count and the transparent index, both big endian
+fn read_act(data: &[u8]) -> Vec<[u8; 4]> {
+    let (count, transparent) = match data.get(ACT_SIZE..ACT_SIZE + 4) {
+        Some(footer) => (
+            u16::from_be_bytes([footer[0], footer[1]]) as usize,
+            u16::from_be_bytes([footer[2], footer[3]]),
+        ),
+        None => (256, ACT_NO_TRANSPARENT),
+    };
+    data[..ACT_SIZE]
+        .chunks_exact(3)
+        .take(if count == 0 { 256 } else { count })
+        .enumerate()
+        .map(|(index, rgb)| {
+            if index == transparent as usize {
+                [0; 4]
+            } else {
+                [rgb[0], rgb[1], rgb[2], 0xFF]
+            }
+        })
+        .collect()
+}
+
+fn write_act(colors: &[[u8; 4]]) -> Result<Vec<u8>> {
+    let mut translucent = colors
+        .iter()
+        .enumerate()
+        .filter(|(_, color)| color[3] != 0xFF);
+    let transparent =
+        match (translucent.next(), translucent.next()) {
+            (None, _) => ACT_NO_TRANSPARENT,
+            (Some((index, [.., 0])), None) => index as u16,
+            _ => return Err(IcuError::Unsupported(
+                "ACT palettes hold at most one color with alpha, and it must be fully transparent"
+                    .to_owned(),
+            )),
+        };
+
+    let mut data = colors
+        .iter()
+        .flat_map(|color| &color[..3])
+        .copied()
+        .collect::<Vec<_>>();
+    data.resize(ACT_SIZE, 0);
+    data.extend((colors.len() as u16).to_be_bytes());
+    data.extend(transparent.to_be_bytes());
+    Ok(data)
+}
+
+pub(crate) fn png_palette(info: &png::Info) -> Vec<[u8; 4]> {
+    let trns = info.trns.as_deref().unwrap_or_default();
+    info.palette
+        .as_deref()
+        .unwrap_or_default()
+        .chunks_exact(3)
+        .enumerate()
+        .map(|(index, rgb)| [rgb[0], rgb[1], rgb[2], *trns.get(index).unwrap_or(&0xFF)])
+        .collect()
+}
+
+/// Indexed PNGs give their palette, other PNGs every distinct color in reading order
+fn read_png(data: &[u8]) -> Result<Vec<[u8; 4]>> {
+    if let Ok(colors) = extract_palette(data) {
+        return Ok(colors);
+    }
+
+    let img = image::load_from_memory_with_format(data, image::ImageFormat::Png)?.to_rgba8();
+    let mut colors: Vec<[u8; 4]> = vec![];
+    for pix in img.pixels() {
+        if !colors.contains(&pix.0) {
+            if colors.len() == 256 {
+                return Err(IcuError::Unsupported(
+                    "PNG swatch has more than 256 colors".to_owned(),
+                ));
+            }
+            colors.push(pix.0);
+        }
+    }
+    Ok(colors)
+}
+
+fn write_png(colors: &[[u8; 4]]) -> Result<Vec<u8>> {
+    let mut buf = Cursor::new(Vec::new());
+    let mut encoder = png::Encoder::new(&mut buf, colors.len() as u32, 1);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        colors
+            .iter()
+            .flat_map(|color| &color[..3])
+            .copied()
+            .collect::<Vec<_>>(),
+    );
+    if colors.iter().any(|color| color[3] != 0xFF) {
+        encoder.set_trns(colors.iter().map(|color| color[3]).collect::<Vec<_>>());
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&(0..colors.len()).map(|i| i as u8).collect::<Vec<_>>())?;
+    writer.finish()?;
+    Ok(buf.into_inner())
+}
@@ -0,0 +1,328 @@
+//! Palette quantizers for the indexed color formats.
+//!
+//! Images whose colors fit in the palette always get the exact colors, whatever the quantizer.
+//! Fully transparent pixels share a single transparent entry and colors are compared
+//! premultiplied by alpha, so the hidden color of translucent pixels matters less.
+
+use crate::error::{IcuError, Result};
+use crate::EncoderParams;
+use image::imageops::ColorMap;
+use image::Rgba;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum Quantizer {
+    /// Only the exact colors, images with more colors than the palette are an error
+    Exact,
+    /// `color_quant::NeuQuant` neural network
+    #[default]
+    NeuQuant,
+    MedianCut,
+    Octree,
+    KMeans,
+}
+
+const KMEANS_ITERATIONS: usize = 16;
+
+/// Colors with their pixel count
+type Histogram = Vec<([u8; 4], u32)>;
+
+/// The colors of an indexed image
+pub struct Palette {
+    colors: Vec<[u8; 4]>,
+    /// Nearest palette entry of every color seen so far
+    cache: RefCell<HashMap<[u8; 4], u8>>,
+}
+
+/// Every color of `data` with its pixel count, in the order of first appearance
+fn histogram(data: &[u8]) -> Histogram {
+    let mut counts = HashMap::new();
+    let mut colors = vec![];
+    for pix in data.chunks_exact(4) {
+        let color = normalize([pix[0], pix[1], pix[2], pix[3]]);
+        let count = counts.entry(color).or_insert_with(|| {
+            colors.push(color);
+            0u32
+        });
+        *count += 1;
+    }
+    colors
+        .into_iter()
+        .map(|color| (color, counts[&color]))
+        .collect()
+}
+
+/// Fully transparent pixels all look the same
+fn normalize(color: [u8; 4]) -> [u8; 4] {
+    if color[3] == 0 {
+        [0; 4]
+    } else {
+        color
+    }
+}
+
+fn premultiplied(color: &[u8; 4]) -> [i32; 4] {
+    let a = color[3] as i32;
+    [
+        (color[0] as i32 * a + 127) / 0xFF,
+        (color[1] as i32 * a + 127) / 0xFF,
+        (color[2] as i32 * a + 127) / 0xFF,
+        a,
+    ]
+}
+
+fn distance(lhs: &[u8; 4], rhs: &[u8; 4]) -> i32 {
+    let (lhs, rhs) = (premultiplied(lhs), premultiplied(rhs));
+    lhs.iter().zip(rhs).map(|(l, r)| (l - r) * (l - r)).sum()
+}
+
+/// Count weighted mean of `colors`, with the color channels weighted by alpha
+fn mean(colors: &[([u8; 4], u32)]) -> [u8; 4] {
+    let mut sum = [0u64; 4];
+    let mut count = 0u64;
+    for (color, n) in colors {
+        let n = *n as u64;
+        let a = color[3] as u64;
+        for c in 0..3 {
+            sum[c] += color[c] as u64 * a * n;
+        }
+        sum[3] += a * n;
+        count += n;
+    }
+    if count == 0 || sum[3] == 0 {
+        return [0; 4];
+    }
+    [
+        ((sum[0] + sum[3] / 2) / sum[3]) as u8,
+        ((sum[1] + sum[3] / 2) / sum[3]) as u8,
+        ((sum[2] + sum[3] / 2) / sum[3]) as u8,
+        ((sum[3] + count / 2) / count) as u8,
+    ]
+}
+
+fn median_cut(mut histogram: Histogram, max_colors: usize) -> Vec<[u8; 4]> {
+    let range = |colors: &[([u8; 4], u32)], channel: usize| {
+        let (min, max) = colors.iter().fold((0xFF, 0), |(min, max), (color, _)| {
+            (color[channel].min(min), color[channel].max(max))
+        });
+        max.saturating_sub(min)
+    };
+
+    let mut boxes = vec![histogram.as_mut_slice()];
+    while boxes.len() < max_colors {
+        // Split the box with the widest channel at the median pixel
+        let Some((index, channel, _)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .flat_map(|(index, colors)| (0..4).map(move |c| (index, c, range(colors, c))))
+            .max_by_key(|(_, _, range)| *range)
+        else {
+            break;
+        };
+
+        let colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|(color, _)| color[channel]);
+        let half = colors.iter().map(|(_, n)| *n as u64).sum::<u64>() / 2;
+        let mut seen = 0u64;
+        let split = colors
+            .iter()
+            .position(|(_, n)| {
+                seen += *n as u64;
+                seen > half
+            })
+            .unwrap_or(0)
+            .clamp(1, colors.len() - 1);
+        let (lhs, rhs) = colors.split_at_mut(split);
+        boxes.push(lhs);
+        boxes.push(rhs);
+    }
+
+    boxes.iter().map(|colors| mean(colors)).collect()
+}
+
+/// Octree over the RGBA bits, the nodes with the fewest pixels are merged first
+fn octree(histogram: &[([u8; 4], u32)], max_colors: usize) -> Vec<[u8; 4]> {
+    let weight = |colors: &[([u8; 4], u32)]| colors.iter().map(|(_, n)| *n as u64).sum::<u64>();
+
+    // Leaves of the current level by their node key
+    let mut leaves: HashMap<[u8; 4], Histogram> = histogram
+        .iter()
+        .map(|&(color, n)| (color, vec![(color, n)]))
+        .collect();
+
+    while leaves.len() > max_colors {
+        let mut parents: HashMap<[u8; 4], Histogram> = HashMap::new();
+        let mut children: HashMap<[u8; 4], Vec<Histogram>> = HashMap::new();
+        for (key, colors) in leaves {
+            let parent = key.map(|c| c >> 1);
+            parents.entry(parent).or_default().extend(&colors);
+            children.entry(parent).or_default().push(colors);
+        }
+
+        if parents.len() > max_colors {
+            leaves = parents;
+            continue;
+        }
+
+        // Merging every node of this level leaves too few colors, only merge the lightest
+        let mut parents = parents.into_iter().collect::<Vec<_>>();
+        parents.sort_by_cached_key(|(key, colors)| (weight(colors), *key));
+        let mut count = children.values().map(Vec::len).sum::<usize>();
+        let mut nodes = vec![];
+        for (key, colors) in parents {
+            let node_children = children.remove(&key).unwrap_or_default();
+            if count > max_colors {
+                count -= node_children.len() - 1;
+                nodes.push(colors);
+            } else {
+                nodes.extend(node_children);
+            }
+        }
+        return nodes.iter().map(|colors| mean(colors)).collect();
+    }
+
+    let mut leaves = leaves.into_values().collect::<Vec<_>>();
+    leaves.sort_by_key(|colors| colors.first().map(|(color, _)| *color));
+    leaves.iter().map(|colors| mean(colors)).collect()
+}
+
+/// Lloyd's k-means, seeded with the median cut palette
+fn k_means(histogram: &[([u8; 4], u32)], max_colors: usize) -> Vec<[u8; 4]> {
+    let mut centers = median_cut(histogram.to_vec(), max_colors);
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut clusters = vec![vec![]; centers.len()];
+        for &(color, n) in histogram {
+            clusters[nearest(&centers, &color)].push((color, n));
+        }
+        let next = clusters
+            .iter()
+            .zip(&centers)
+            .map(|(cluster, center)| {
+                if cluster.is_empty() {
+                    *center
+                } else {
+                    mean(cluster)
+                }
+            })
+            .collect::<Vec<_>>();
+        if next == centers {
+            break;
+        }
+        centers = next;
+    }
+    centers
+}
+
+fn nearest(colors: &[[u8; 4]], color: &[u8; 4]) -> usize {
+    colors
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, entry)| distance(entry, color))
+        .map_or(0, |(index, _)| index)
+}
+
+impl Palette {
+    /// Pick at most `max_colors` colors for the RGBA `data`.
+    /// `sample_factor` is the NeuQuant sampling, 1 is the best and 30 the fastest.
+    pub fn new(
+        data: &[u8],
+        max_colors: usize,
+        quantizer: Quantizer,
+        sample_factor: i32,
+    ) -> Result<Self> {
+        let max_colors = max_colors.clamp(1, 256);
+        let histogram = histogram(data);
+
+        let colors = if histogram.len() <= max_colors {
+            histogram.into_iter().map(|(color, _)| color).collect()
+        } else {
+            match quantizer {
+                Quantizer::Exact => {
+                    return Err(IcuError::Unsupported(format!(
+                        "{} colors don't fit in a palette of {max_colors}",
+                        histogram.len()
+                    )))
+                }
+                Quantizer::NeuQuant => {
+                    let nq = color_quant::NeuQuant::new(sample_factor, max_colors, data);
+                    nq.color_map_rgba()
+                        .chunks_exact(4)
+                        .map(|c| normalize([c[0], c[1], c[2], c[3]]))
+                        .collect()
+                }
+                Quantizer::MedianCut => median_cut(histogram, max_colors),
+                Quantizer::Octree => octree(&histogram, max_colors),
+                Quantizer::KMeans => k_means(&histogram, max_colors),
+            }
+        };
+
+        Ok(Self::from_colors(colors))
+    }
+
+    /// The palette of `params`, or at most `max_colors` colors picked by its quantizer
+    pub fn for_params(
+        data: &[u8],
+        max_colors: usize,
+        params: &EncoderParams,
+        sample_factor: i32,
+    ) -> Result<Self> {
+        match &params.palette {
+            Some(colors) if colors.len() > max_colors => Err(IcuError::Unsupported(format!(
+                "palette of {} colors doesn't fit in {max_colors} entries",
+                colors.len()
+            ))),
+            Some(colors) => Ok(Self::from_colors(
+                colors.iter().copied().map(normalize).collect(),
+            )),
+            None => Self::new(data, max_colors, params.quantizer, sample_factor),
+        }
+    }
+
+    pub fn from_colors(colors: Vec<[u8; 4]>) -> Self {
+        Self {
+            colors,
+            cache: Default::default(),
+        }
+    }
+
+    pub fn colors(&self) -> &[[u8; 4]] {
+        &self.colors
+    }
+
+    /// The colors padded with transparent black up to `size` entries
+    pub fn to_rgba(&self, size: usize) -> Vec<u8> {
+        let mut rgba = self.colors.concat();
+        rgba.resize(size.max(self.colors.len()) * 4, 0);
+        rgba
+    }
+
+    /// Index of the palette entry closest to an RGBA pixel
+    pub fn index_of(&self, pix: &[u8]) -> u8 {
+        let color = normalize([pix[0], pix[1], pix[2], pix[3]]);
+        *self
+            .cache
+            .borrow_mut()
+            .entry(color)
+            .or_insert_with(|| nearest(&self.colors, &color) as u8)
+    }
+
+    /// Index of every pixel of the RGBA `data`
+    pub fn indexes(&self, data: &[u8]) -> Vec<u8> {
+        data.chunks_exact(4).map(|pix| self.index_of(pix)).collect()
+    }
+}
+
+impl ColorMap for Palette {
+    type Color = Rgba<u8>;
+
+    fn index_of(&self, color: &Rgba<u8>) -> usize {
+        Palette::index_of(self, &color.0) as usize
+    }
+
+    fn map_color(&self, color: &mut Rgba<u8>) {
+        let index = Palette::index_of(self, &color.0) as usize;
+        color.0 = self.colors.get(index).copied().unwrap_or_default();
+    }
+}
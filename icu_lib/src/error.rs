@@ -0,0 +1,108 @@
+use crate::endecoder::lvgl::ColorFormat;
+use crate::endecoder::utils::rle::RleError;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, IcuError>;
+
+/// Everything that can go wrong while encoding, decoding or inspecting an image
+#[derive(Debug)]
+pub enum IcuError {
+    /// The data ends before the header does
+    TruncatedHeader {
+        expected: usize,
+        actual: usize,
+    },
+    /// The data does not look like the format the endecoder handles
+    UnknownFormat(String),
+    /// The pixel data is shorter than the geometry in the header requires
+    DataSizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    /// Width, height or stride can not be represented or do not fit together
+    InvalidGeometry(String),
+    UnsupportedColorFormat(ColorFormat),
+    /// The endecoder can not handle this kind of `MiData`
+    UnsupportedData,
+    /// The endecoder can not handle the requested operation
+    Unsupported(String),
+    Compression(String),
+    Image(image::ImageError),
+    Png(png::EncodingError),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for IcuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IcuError::TruncatedHeader { expected, actual } => write!(
+                f,
+                "truncated header: expected {expected} bytes, got {actual}"
+            ),
+            IcuError::UnknownFormat(msg) => write!(f, "unknown format: {msg}"),
+            IcuError::DataSizeMismatch { expected, actual } => write!(
+                f,
+                "data size mismatch: expected {expected} bytes, got {actual}"
+            ),
+            IcuError::InvalidGeometry(msg) => write!(f, "invalid geometry: {msg}"),
+            IcuError::UnsupportedColorFormat(cf) => write!(f, "unsupported color format {cf:?}"),
+            IcuError::UnsupportedData => write!(f, "unsupported image data"),
+            IcuError::Unsupported(msg) => write!(f, "unsupported: {msg}"),
+            IcuError::Compression(msg) => write!(f, "compression error: {msg}"),
+            IcuError::Image(err) => write!(f, "image error: {err}"),
+            IcuError::Png(err) => write!(f, "png error: {err}"),
+            IcuError::Io(err) => write!(f, "io error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for IcuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IcuError::Image(err) => Some(err),
+            IcuError::Png(err) => Some(err),
+            IcuError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for IcuError {
+    fn from(err: image::ImageError) -> Self {
+        IcuError::Image(err)
+    }
+}
+
+impl From<png::EncodingError> for IcuError {
+    fn from(err: png::EncodingError) -> Self {
+        IcuError::Png(err)
+    }
+}
+
+impl From<std::io::Error> for IcuError {
+    fn from(err: std::io::Error) -> Self {
+        IcuError::Io(err)
+    }
+}
+
+impl From<tiff::TiffError> for IcuError {
+    fn from(err: tiff::TiffError) -> Self {
+        IcuError::Image(image::ImageError::Encoding(
+            image::error::EncodingError::new(image::ImageFormat::Tiff.into(), err),
+        ))
+    }
+}
+
+impl From<jpeg_encoder::EncodingError> for IcuError {
+    fn from(err: jpeg_encoder::EncodingError) -> Self {
+        IcuError::Image(image::ImageError::Encoding(
+            image::error::EncodingError::new(image::ImageFormat::Jpeg.into(), err),
+        ))
+    }
+}
+
+impl From<RleError> for IcuError {
+    fn from(err: RleError) -> Self {
+        IcuError::Compression(format!("RLE {err:?}"))
+    }
+}
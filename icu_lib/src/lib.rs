@@ -1,16 +1,52 @@
+use crate::endecoder::common::options::{
+    ChromaSubsampling, PngCompression, PngFilter, TiffCompression,
+};
 use crate::endecoder::lvgl;
+use crate::endecoder::utils::dither::DitherAlgorithm;
+use crate::endecoder::utils::metadata::Metadata;
+use crate::endecoder::utils::quantize::Quantizer;
 
 pub mod endecoder;
+pub mod error;
 pub mod midata;
+
+pub use error::IcuError;
 type RawImageHeader = lvgl::ImageHeader;
 
+#[derive(Clone)]
 pub struct EncoderParams {
     pub color_format: lvgl::ColorFormat,
     pub stride_align: u32,
+    /// Dither against the color depth of the color format, also the NeuQuant sample factor from 1 to 30
     pub dither: Option<u32>,
+    pub dither_algorithm: DitherAlgorithm,
+    /// Share of the quantization error that gets dithered, 0.0 to 1.0
+    pub dither_strength: f32,
+    /// Picks the palette of the indexed color formats
+    pub quantizer: Quantizer,
+    /// Fixed RGBA palette of the indexed color formats, pixels map to the nearest entry
+    pub palette: Option<Vec<[u8; 4]>>,
     pub compress: lvgl::Compress,
     pub lvgl_version: lvgl::LVGLVersion,
+    /// Premultiply RGB by alpha, only for LVGL V9 formats with an alpha channel or a palette
+    pub premultiply: bool,
+    /// `LV_COLOR_DEPTH` of the LVGL V8 project, decides the layout of the V8 TRUE_COLOR formats
+    pub color_depth: lvgl::ColorDepth,
+    /// `LV_COLOR_16_SWAP` of the LVGL V8 project, for the 16 bit color depth
+    pub color_16_swap: bool,
     pub raw_image_header: Option<RawImageHeader>,
+    /// Metadata written by the PNG, JPEG, WEBP and TIFF encoders,
+    /// [`Decoded::encode_into`](crate::midata::Decoded::encode_into) passes that of the image
+    pub metadata: Option<Metadata>,
+    /// JPEG quality, 1 to 100
+    pub quality: u8,
+    pub jpeg_subsampling: ChromaSubsampling,
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    /// Near-lossless level of WEBP, 0 to 100, the colors are rounded before the lossless
+    /// encoding, the smaller the coarser, 100 keeps them exact
+    pub webp_near_lossless: u8,
+    pub tiff_compression: TiffCompression,
 }
 
 impl Default for EncoderParams {
@@ -19,9 +55,23 @@ impl Default for EncoderParams {
             color_format: Default::default(),
             stride_align: 1,
             dither: None,
+            dither_algorithm: Default::default(),
+            dither_strength: 1.0,
+            quantizer: Default::default(),
+            palette: None,
             compress: Default::default(),
             lvgl_version: lvgl::LVGLVersion::Unknown,
+            premultiply: false,
+            color_depth: Default::default(),
+            color_16_swap: false,
             raw_image_header: Default::default(),
+            metadata: None,
+            quality: 75,
+            jpeg_subsampling: Default::default(),
+            png_compression: Default::default(),
+            png_filter: Default::default(),
+            webp_near_lossless: 100,
+            tiff_compression: Default::default(),
         }
     }
 }
@@ -46,6 +96,26 @@ impl EncoderParams {
         self
     }
 
+    pub fn with_dither_algorithm(mut self, dither_algorithm: DitherAlgorithm) -> Self {
+        self.dither_algorithm = dither_algorithm;
+        self
+    }
+
+    pub fn with_dither_strength(mut self, dither_strength: f32) -> Self {
+        self.dither_strength = dither_strength;
+        self
+    }
+
+    pub fn with_quantizer(mut self, quantizer: Quantizer) -> Self {
+        self.quantizer = quantizer;
+        self
+    }
+
+    pub fn with_palette(mut self, palette: Vec<[u8; 4]>) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
     pub fn with_compress(mut self, compress: lvgl::Compress) -> Self {
         self.compress = compress;
         self
@@ -56,8 +126,58 @@ impl EncoderParams {
         self
     }
 
+    pub fn with_premultiply(mut self, premultiply: bool) -> Self {
+        self.premultiply = premultiply;
+        self
+    }
+
+    pub fn with_color_depth(mut self, color_depth: lvgl::ColorDepth) -> Self {
+        self.color_depth = color_depth;
+        self
+    }
+
+    pub fn with_color_16_swap(mut self, color_16_swap: bool) -> Self {
+        self.color_16_swap = color_16_swap;
+        self
+    }
+
     pub fn with_raw_image_header(mut self, raw_image_header: RawImageHeader) -> Self {
         self.raw_image_header = Some(raw_image_header);
         self
     }
+
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = quality;
+        self
+    }
+
+    pub fn with_jpeg_subsampling(mut self, jpeg_subsampling: ChromaSubsampling) -> Self {
+        self.jpeg_subsampling = jpeg_subsampling;
+        self
+    }
+
+    pub fn with_png_compression(mut self, png_compression: PngCompression) -> Self {
+        self.png_compression = png_compression;
+        self
+    }
+
+    pub fn with_png_filter(mut self, png_filter: PngFilter) -> Self {
+        self.png_filter = png_filter;
+        self
+    }
+
+    pub fn with_webp_near_lossless(mut self, webp_near_lossless: u8) -> Self {
+        self.webp_near_lossless = webp_near_lossless;
+        self
+    }
+
+    pub fn with_tiff_compression(mut self, tiff_compression: TiffCompression) -> Self {
+        self.tiff_compression = tiff_compression;
+        self
+    }
 }
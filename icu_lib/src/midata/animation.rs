@@ -0,0 +1,183 @@
+//! Frames of animated GIF, APNG and WebP images.
+//!
+//! Every frame is composited onto the full canvas, so the frames can be shown or encoded on their own.
+
+use crate::endecoder::utils::color_space::{convert_color_space, ColorSpace, EmbeddedProfile};
+use crate::endecoder::utils::metadata::Metadata;
+use crate::error::{IcuError, Result};
+use crate::midata::decoded::DecodeParams;
+use crate::midata::transform::TransformParams;
+use crate::midata::MiData;
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, ImageFormat, RgbaImage};
+use std::io::Cursor;
+
+/// Browsers play GIF frames of 10 ms and less at 100 ms, most GIFs are made for that
+const MIN_GIF_DELAY_MS: u32 = 10;
+const DEFAULT_GIF_DELAY_MS: u32 = 100;
+
+#[derive(Clone)]
+pub struct Frame {
+    pub image: MiData,
+    /// How long the frame shows
+    pub delay_ms: u32,
+}
+
+#[derive(Clone)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    /// Metadata of the source, for the encoders of the frames
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum StripDirection {
+    /// Frames side by side, left to right
+    #[default]
+    Horizontal,
+    /// Frames stacked, top to bottom
+    Vertical,
+}
+
+impl Animation {
+    /// The frames of an animated GIF, APNG or WebP, `None` for still images and other formats
+    pub fn decode(data: &[u8]) -> Result<Option<Self>> {
+        Self::decode_in(data, ColorSpace::Srgb)
+    }
+
+    /// [`Animation::decode`] into the `color_space` of the panel
+    pub fn decode_in(data: &[u8], color_space: ColorSpace) -> Result<Option<Self>> {
+        Self::decode_with(data, DecodeParams::new().with_color_space(color_space))
+    }
+
+    /// [`Animation::decode`] with the options of [`Decoded::decode_from`](crate::midata::Decoded::decode_from)
+    pub fn decode_with(data: &[u8], params: DecodeParams) -> Result<Option<Self>> {
+        let color_space = params.color_space;
+        let format = image::guess_format(data).ok();
+        let frames = match format {
+            Some(ImageFormat::Gif) => GifDecoder::new(Cursor::new(data))?.into_frames(),
+            Some(ImageFormat::Png) => {
+                let decoder = PngDecoder::new(Cursor::new(data))?;
+                if !decoder.is_apng()? {
+                    return Ok(None);
+                }
+                decoder.apng()?.into_frames()
+            }
+            Some(ImageFormat::WebP) => {
+                let decoder = WebPDecoder::new(Cursor::new(data))?;
+                if !decoder.has_animation() {
+                    return Ok(None);
+                }
+                decoder.into_frames()
+            }
+            _ => return Ok(None),
+        };
+
+        let profile = EmbeddedProfile::detect(data)
+            .map(|embedded| embedded.profile)
+            .unwrap_or_else(|| ColorSpace::Srgb.profile());
+        let frames = frames
+            .map(|frame| {
+                let frame = frame?;
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                let mut delay_ms = numer / denom.max(1);
+                if format == Some(ImageFormat::Gif) && delay_ms <= MIN_GIF_DELAY_MS {
+                    delay_ms = DEFAULT_GIF_DELAY_MS;
+                }
+                let image =
+                    convert_color_space(MiData::RGBA(frame.into_buffer()), &profile, color_space)?;
+                Ok(Frame { image, delay_ms })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // GIFs of a single frame are still images
+        if frames.len() < 2 {
+            return Ok(None);
+        }
+        let (metadata, orientation) = params.read_metadata(data);
+        let animation = Self { frames, metadata };
+        match orientation {
+            Some(orientation) => animation.transform(&orientation).map(Some),
+            None => Ok(Some(animation)),
+        }
+    }
+
+    /// Whether `data` is an animated GIF, APNG or WebP, without decoding more than two frames
+    pub fn is_animated(data: &[u8]) -> bool {
+        let animated = || -> Result<bool> {
+            Ok(match image::guess_format(data).ok() {
+                Some(ImageFormat::Gif) => {
+                    GifDecoder::new(Cursor::new(data))?
+                        .into_frames()
+                        .take(2)
+                        .count()
+                        == 2
+                }
+                Some(ImageFormat::Png) => PngDecoder::new(Cursor::new(data))?.is_apng()?,
+                Some(ImageFormat::WebP) => WebPDecoder::new(Cursor::new(data))?.has_animation(),
+                _ => false,
+            })
+        };
+        animated().unwrap_or(false)
+    }
+
+    /// How long one loop plays
+    pub fn duration_ms(&self) -> u32 {
+        self.frames.iter().map(|frame| frame.delay_ms).sum()
+    }
+
+    pub fn delays_ms(&self) -> Vec<u32> {
+        self.frames.iter().map(|frame| frame.delay_ms).collect()
+    }
+
+    /// Apply the same transform to every frame
+    pub fn transform(self, params: &TransformParams) -> Result<Self> {
+        let frames = self
+            .frames
+            .into_iter()
+            .map(|frame| {
+                Ok(Frame {
+                    image: frame.image.transform(params)?,
+                    delay_ms: frame.delay_ms,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            frames,
+            metadata: self.metadata,
+        })
+    }
+
+    /// All frames in one image, e.g. a sprite sheet that LVGL shows a part of at a time
+    pub fn sprite_strip(&self, direction: StripDirection) -> Result<MiData> {
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| frame.image.to_rgba().ok_or(IcuError::UnsupportedData))
+            .collect::<Result<Vec<_>>>()?;
+        let (width, height) = frames
+            .first()
+            .map(|frame| frame.dimensions())
+            .ok_or(IcuError::UnsupportedData)?;
+        let count = frames.len() as u32;
+
+        let mut strip = match direction {
+            StripDirection::Horizontal => RgbaImage::new(width * count, height),
+            StripDirection::Vertical => RgbaImage::new(width, height * count),
+        };
+        for (index, frame) in frames.iter().enumerate() {
+            let offset = index as i64;
+            match direction {
+                StripDirection::Horizontal => {
+                    image::imageops::replace(&mut strip, &**frame, offset * width as i64, 0)
+                }
+                StripDirection::Vertical => {
+                    image::imageops::replace(&mut strip, &**frame, 0, offset * height as i64)
+                }
+            }
+        }
+        Ok(MiData::RGBA(strip))
+    }
+}
@@ -0,0 +1,295 @@
+//! Packing of many small images into one atlas image.
+//!
+//! Sprites are placed tallest first with a skyline bottom-left packer, every sprite on the
+//! lowest spot it fits, so rows of similar icons end up next to each other.
+
+use crate::endecoder::lvgl::atlas::{sprite_id, RESERVED_SPRITE_IDS};
+use crate::error::{IcuError, Result};
+use crate::midata::MiData;
+use image::RgbaImage;
+use serde::Serialize;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasParams {
+    /// Transparent pixels between the sprites
+    pub padding: u32,
+    /// The left edge of every sprite is a multiple of it, e.g. 8 for byte aligned sprites in I1
+    pub align: u32,
+    /// Width of the atlas, the square root of the sprite area if `None`
+    pub max_width: Option<u32>,
+}
+
+impl Default for AtlasParams {
+    fn default() -> Self {
+        Self {
+            padding: 0,
+            align: 1,
+            max_width: None,
+        }
+    }
+}
+
+impl AtlasParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn with_align(mut self, align: u32) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: u32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+}
+
+/// Sub-rectangle of one image in the atlas
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AtlasSprite {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Clone)]
+pub struct Atlas {
+    pub image: MiData,
+    /// In the order of the packed images
+    pub sprites: Vec<AtlasSprite>,
+}
+
+/// Table of an atlas as written to JSON
+#[derive(Serialize)]
+struct AtlasTable<'a> {
+    image: &'a str,
+    width: u32,
+    height: u32,
+    sprites: &'a [AtlasSprite],
+}
+
+impl Atlas {
+    /// Pack named images into one RGBA atlas
+    pub fn pack(images: &[(String, MiData)], params: &AtlasParams) -> Result<Self> {
+        if images.is_empty() {
+            return Err(IcuError::Unsupported(
+                "an atlas needs at least one image".to_owned(),
+            ));
+        }
+        // The names become enum members of the C header, so they must differ as identifiers
+        for (index, (name, _)) in images.iter().enumerate() {
+            let id = sprite_id(name);
+            if RESERVED_SPRITE_IDS.contains(&id.as_str()) {
+                return Err(IcuError::Unsupported(format!(
+                    "<{name}> becomes {id} which the atlas header defines itself"
+                )));
+            }
+            if let Some((other, _)) = images[..index]
+                .iter()
+                .find(|(other, _)| sprite_id(other) == id)
+            {
+                return Err(IcuError::Unsupported(format!(
+                    "<{other}> and <{name}> both become {id} in the atlas header"
+                )));
+            }
+        }
+
+        let pixels = images
+            .iter()
+            .map(|(_, image)| image.to_rgba().ok_or(IcuError::UnsupportedData))
+            .collect::<Result<Vec<_>>>()?;
+        let padding = params.padding;
+        let align = params.align.max(1);
+
+        let width = match params.max_width {
+            Some(max_width) => max_width,
+            None => {
+                let area = pixels
+                    .iter()
+                    .map(|image| {
+                        (image.width() as f64 + padding as f64)
+                            * (image.height() as f64 + padding as f64)
+                    })
+                    .sum::<f64>();
+                let widest = pixels.iter().map(|image| image.width()).max().unwrap_or(0);
+                (area.sqrt().ceil() as u32).max(widest)
+            }
+        };
+        if let Some((name, image)) = images
+            .iter()
+            .zip(&pixels)
+            .find(|(_, image)| image.width() > width)
+            .map(|((name, _), image)| (name, image))
+        {
+            return Err(IcuError::Unsupported(format!(
+                "<{name}> is {} pixels wide, the atlas only {width}",
+                image.width()
+            )));
+        }
+
+        let mut order = (0..pixels.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| {
+            let image = &pixels[index];
+            (
+                std::cmp::Reverse(image.height()),
+                std::cmp::Reverse(image.width()),
+            )
+        });
+
+        // The padding after the last column may hang over the right edge
+        let mut skyline = Skyline::new(width + padding);
+        let mut positions = vec![(0, 0); pixels.len()];
+        for index in order {
+            let (cell_width, cell_height) = (
+                pixels[index].width() + padding,
+                pixels[index].height() + padding,
+            );
+            let (x, y) = skyline
+                .find(cell_width, align)
+                .ok_or(IcuError::UnsupportedData)?;
+            skyline.add(x, y + cell_height, cell_width);
+            positions[index] = (x, y);
+        }
+
+        let sprites = images
+            .iter()
+            .zip(&pixels)
+            .zip(&positions)
+            .map(|(((name, _), image), &(x, y))| AtlasSprite {
+                name: name.clone(),
+                x,
+                y,
+                width: image.width(),
+                height: image.height(),
+            })
+            .collect::<Vec<_>>();
+        let (atlas_width, atlas_height) = extent(&sprites);
+        let mut atlas = RgbaImage::new(atlas_width, atlas_height);
+        for (sprite, image) in sprites.iter().zip(&pixels) {
+            image::imageops::replace(&mut atlas, &**image, sprite.x as i64, sprite.y as i64);
+        }
+
+        Ok(Self {
+            image: MiData::RGBA(atlas),
+            sprites,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        extent(&self.sprites).0
+    }
+
+    pub fn height(&self) -> u32 {
+        extent(&self.sprites).1
+    }
+
+    /// The sprite table as JSON, `image` names the encoded atlas
+    pub fn to_json(&self, image: &str) -> String {
+        let table = AtlasTable {
+            image,
+            width: self.width(),
+            height: self.height(),
+            sprites: &self.sprites,
+        };
+        serde_json::to_string_pretty(&table).unwrap_or_default()
+    }
+}
+
+/// Width and height that hold all sprites
+fn extent(sprites: &[AtlasSprite]) -> (u32, u32) {
+    sprites.iter().fold((0, 0), |(width, height), sprite| {
+        (
+            width.max(sprite.x + sprite.width),
+            height.max(sprite.y + sprite.height),
+        )
+    })
+}
+
+/// Top edge of the packed sprites over a run of columns
+struct Segment {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+struct Skyline {
+    width: u32,
+    /// Left to right, covering the whole width
+    segments: Vec<Segment>,
+}
+
+impl Skyline {
+    fn new(width: u32) -> Self {
+        Self {
+            width,
+            segments: vec![Segment { x: 0, y: 0, width }],
+        }
+    }
+
+    /// Lowest spot for a cell `width` wide whose left edge is a multiple of `align`,
+    /// the leftmost of equally low ones
+    fn find(&self, width: u32, align: u32) -> Option<(u32, u32)> {
+        let mut best: Option<(u32, u32)> = None;
+        for segment in &self.segments {
+            let x = segment.x.next_multiple_of(align);
+            if x + width > self.width {
+                continue;
+            }
+            let y = self
+                .segments
+                .iter()
+                .filter(|other| other.x < x + width && other.x + other.width > x)
+                .map(|other| other.y)
+                .max()
+                .unwrap_or(0);
+            if best.is_none_or(|(best_x, best_y)| (y, x) < (best_y, best_x)) {
+                best = Some((x, y));
+            }
+        }
+        best
+    }
+
+    /// Raise the columns `x..x + width` to `top`
+    fn add(&mut self, x: u32, top: u32, width: u32) {
+        let end = x + width;
+        let mut segments = Vec::with_capacity(self.segments.len() + 2);
+        for segment in self.segments.drain(..) {
+            let segment_end = segment.x + segment.width;
+            if segment.x < x {
+                segments.push(Segment {
+                    width: segment_end.min(x) - segment.x,
+                    ..segment
+                });
+            }
+            if segment_end > end {
+                let start = segment.x.max(end);
+                segments.push(Segment {
+                    x: start,
+                    y: segment.y,
+                    width: segment_end - start,
+                });
+            }
+        }
+        segments.push(Segment { x, y: top, width });
+        segments.sort_by_key(|segment| segment.x);
+
+        // Neighbours of the same height are one segment
+        self.segments = segments
+            .into_iter()
+            .fold(Vec::new(), |mut merged, segment| {
+                match merged.last_mut() {
+                    Some(last) if last.y == segment.y => last.width += segment.width,
+                    _ => merged.push(segment),
+                }
+                merged
+            });
+    }
+}
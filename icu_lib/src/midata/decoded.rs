@@ -0,0 +1,87 @@
+//! Decoded images together with the metadata of their source.
+//!
+//! The metadata travels with the pixels from the decoder to the encoder,
+//! see [`Decoded::encode_into`].
+
+use crate::endecoder::utils::color_space::ColorSpace;
+use crate::endecoder::utils::metadata::Metadata;
+use crate::endecoder::EnDecoder;
+use crate::error::Result;
+use crate::midata::transform::TransformParams;
+use crate::midata::MiData;
+use crate::EncoderParams;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct DecodeParams {
+    /// Color space of the panel, see [`EnDecoder::decode_in`]
+    pub color_space: ColorSpace,
+    /// Turn the image upright as its EXIF orientation says.
+    /// The orientation is taken out of the metadata, so viewers don't turn it once more.
+    pub auto_orient: bool,
+}
+
+impl DecodeParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    pub fn with_auto_orient(mut self, auto_orient: bool) -> Self {
+        self.auto_orient = auto_orient;
+        self
+    }
+
+    /// Metadata of `data` and the transform that turns its pixels upright
+    pub(crate) fn read_metadata(&self, data: &[u8]) -> (Metadata, Option<TransformParams>) {
+        let mut metadata = Metadata::read(data);
+        let orientation = match self.auto_orient {
+            true => metadata.take_orientation(),
+            false => None,
+        };
+        let orientation =
+            orientation.map(|orientation| TransformParams::new().with_orientation(orientation));
+        (metadata, orientation)
+    }
+}
+
+#[derive(Clone)]
+pub struct Decoded {
+    pub image: MiData,
+    /// Metadata of the source, written by the PNG, JPEG, WEBP and TIFF encoders
+    pub metadata: Metadata,
+}
+
+impl Decoded {
+    pub fn decode_from(ed: &dyn EnDecoder, data: Vec<u8>, params: DecodeParams) -> Result<Self> {
+        let (metadata, orientation) = params.read_metadata(&data);
+        let mut image = ed.decode_in(data, params.color_space)?;
+        if let Some(orientation) = &orientation {
+            image = image.transform(orientation)?;
+        }
+        Ok(Self { image, metadata })
+    }
+
+    pub fn transform(self, params: &TransformParams) -> Result<Self> {
+        Ok(Self {
+            image: self.image.transform(params)?,
+            metadata: self.metadata,
+        })
+    }
+
+    /// Encode the image with its metadata, unless `encoder_params` already carry some
+    pub fn encode_into(
+        &self,
+        ed: &dyn EnDecoder,
+        encoder_params: EncoderParams,
+    ) -> Result<Vec<u8>> {
+        let encoder_params = match encoder_params.metadata {
+            Some(_) => encoder_params,
+            None => encoder_params.with_metadata(self.metadata.clone()),
+        };
+        self.image.encode_into(ed, encoder_params)
+    }
+}
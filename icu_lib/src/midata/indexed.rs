@@ -0,0 +1,76 @@
+use crate::error::{IcuError, Result};
+use image::RgbaImage;
+
+/// Pixels as indexes into a palette of RGBA colors, kept apart so that
+/// indexed formats can be converted into each other without requantizing
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedImage {
+    width: u32,
+    height: u32,
+    palette: Vec<[u8; 4]>,
+    /// One palette index per pixel, row by row
+    indexes: Vec<u8>,
+}
+
+impl IndexedImage {
+    pub fn new(width: u32, height: u32, palette: Vec<[u8; 4]>, indexes: Vec<u8>) -> Result<Self> {
+        if palette.is_empty() || palette.len() > 256 {
+            return Err(IcuError::Unsupported(format!(
+                "palettes have 1 to 256 colors, got {}",
+                palette.len()
+            )));
+        }
+        let expected = width as usize * height as usize;
+        if indexes.len() != expected {
+            return Err(IcuError::DataSizeMismatch {
+                expected,
+                actual: indexes.len(),
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            palette,
+            indexes,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn palette(&self) -> &[[u8; 4]] {
+        &self.palette
+    }
+
+    pub fn indexes(&self) -> &[u8] {
+        &self.indexes
+    }
+
+    /// The first `max_colors` palette entries, `None` if a pixel uses a later one
+    pub fn palette_within(&self, max_colors: usize) -> Option<&[[u8; 4]]> {
+        self.indexes
+            .iter()
+            .all(|&index| (index as usize) < max_colors)
+            .then(|| &self.palette[..self.palette.len().min(max_colors)])
+    }
+
+    /// Indexes past the end of the palette are transparent black
+    pub fn to_rgba(&self) -> RgbaImage {
+        let data = self
+            .indexes
+            .iter()
+            .flat_map(|&index| {
+                self.palette
+                    .get(index as usize)
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect();
+        RgbaImage::from_raw(self.width, self.height, data).unwrap_or_default()
+    }
+}
@@ -1,19 +1,45 @@
 use crate::endecoder::EnDecoder;
+use crate::error::Result;
 use crate::EncoderParams;
-use image::{GrayAlphaImage, RgbaImage};
+use image::{DynamicImage, GrayAlphaImage, ImageBuffer, LumaA, Rgba, Rgba32FImage, RgbaImage};
+use std::borrow::Cow;
 
+pub mod animation;
+pub mod atlas;
+pub mod decoded;
+pub mod indexed;
+pub mod transform;
+
+pub use animation::{Animation, Frame};
+pub use atlas::{Atlas, AtlasParams, AtlasSprite};
+pub use decoded::{DecodeParams, Decoded};
+pub use indexed::IndexedImage;
+
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+#[derive(Clone)]
 pub enum MiData {
     RGBA(RgbaImage),
+    /// 16-bit sources such as PNG and TIFF, grayscale ones included
+    RGBA16(Rgba16Image),
+    /// Floating point sources, `0.0` to `1.0`
+    RGBA32F(Rgba32FImage),
     GRAY(GrayAlphaImage),
+    /// Palette and index buffer of indexed PNG and LVGL I1 to I8 images
+    INDEXED(IndexedImage),
     PATH,
 }
 
 impl MiData {
-    pub fn decode_from(ed: &dyn EnDecoder, data: Vec<u8>) -> Self {
+    pub fn decode_from(ed: &dyn EnDecoder, data: Vec<u8>) -> Result<Self> {
         ed.decode(data)
     }
 
-    pub fn encode_into(&self, ed: &dyn EnDecoder, encoder_params: EncoderParams) -> Vec<u8> {
+    pub fn encode_into(
+        &self,
+        ed: &dyn EnDecoder,
+        encoder_params: EncoderParams,
+    ) -> Result<Vec<u8>> {
         ed.encode(self, encoder_params)
     }
 }
@@ -22,4 +48,114 @@ impl MiData {
     pub fn from_rgba(w: u32, h: u32, data: Vec<u8>) -> Option<Self> {
         Some(MiData::RGBA(RgbaImage::from_vec(w, h, data)?))
     }
+
+    pub fn from_gray(w: u32, h: u32, data: Vec<u8>) -> Option<Self> {
+        Some(MiData::GRAY(GrayAlphaImage::from_vec(w, h, data)?))
+    }
+
+    /// RGBA view of the image, borrowed when it already is RGBA
+    pub fn to_rgba(&self) -> Option<Cow<'_, RgbaImage>> {
+        match self {
+            MiData::RGBA(img) => Some(Cow::Borrowed(img)),
+            MiData::RGBA16(img) => Some(Cow::Owned(
+                DynamicImage::ImageRgba16(img.clone()).into_rgba8(),
+            )),
+            MiData::RGBA32F(img) => Some(Cow::Owned(
+                DynamicImage::ImageRgba32F(img.clone()).into_rgba8(),
+            )),
+            MiData::GRAY(img) => Some(Cow::Owned(
+                DynamicImage::ImageLumaA8(img.clone()).into_rgba8(),
+            )),
+            MiData::INDEXED(img) => Some(Cow::Owned(img.to_rgba())),
+            MiData::PATH => None,
+        }
+    }
+
+    /// Full precision RGBA view of the image, for the steps that should not work on 8 bits
+    pub fn to_rgba32f(&self) -> Option<Cow<'_, Rgba32FImage>> {
+        match self {
+            MiData::RGBA32F(img) => Some(Cow::Borrowed(img)),
+            MiData::RGBA16(img) => Some(Cow::Owned(
+                DynamicImage::ImageRgba16(img.clone()).into_rgba32f(),
+            )),
+            _ => Some(Cow::Owned(
+                DynamicImage::ImageRgba8(self.to_rgba()?.into_owned()).into_rgba32f(),
+            )),
+        }
+    }
+
+    /// 16-bit RGBA view of the image, borrowed when it already is 16-bit
+    pub fn to_rgba16(&self) -> Option<Cow<'_, Rgba16Image>> {
+        match self {
+            MiData::RGBA16(img) => Some(Cow::Borrowed(img)),
+            MiData::RGBA32F(img) => Some(Cow::Owned(
+                DynamicImage::ImageRgba32F(img.clone()).into_rgba16(),
+            )),
+            _ => Some(Cow::Owned(
+                DynamicImage::ImageRgba8(self.to_rgba()?.into_owned()).into_rgba16(),
+            )),
+        }
+    }
+
+    /// Whether the image keeps more than 8 bits per channel
+    pub fn is_high_precision(&self) -> bool {
+        matches!(self, MiData::RGBA16(_) | MiData::RGBA32F(_))
+    }
+
+    /// Grayscale view of the image, borrowed when it already is GRAY.
+    /// Colors take the [`luma`] of the LVGL L8 format.
+    pub fn to_gray(&self) -> Option<Cow<'_, GrayAlphaImage>> {
+        match self {
+            MiData::GRAY(img) => Some(Cow::Borrowed(img)),
+            _ => {
+                let img = self.to_rgba()?;
+                Some(Cow::Owned(GrayAlphaImage::from_fn(
+                    img.width(),
+                    img.height(),
+                    |x, y| {
+                        let pix = img.get_pixel(x, y);
+                        LumaA([luma(&pix.0), pix[3]])
+                    },
+                )))
+            }
+        }
+    }
+
+    pub fn into_rgba(self) -> Option<RgbaImage> {
+        match self {
+            MiData::RGBA(img) => Some(img),
+            MiData::RGBA16(img) => Some(DynamicImage::ImageRgba16(img).into_rgba8()),
+            MiData::RGBA32F(img) => Some(DynamicImage::ImageRgba32F(img).into_rgba8()),
+            MiData::GRAY(img) => Some(DynamicImage::ImageLumaA8(img).into_rgba8()),
+            MiData::INDEXED(img) => Some(img.to_rgba()),
+            MiData::PATH => None,
+        }
+    }
+}
+
+/// Luminance of an RGB(A) pixel as LVGL computes it for L8 and AL88, (R+R+R+B+G+G+G+G) >> 3
+pub fn luma(pix: &[u8]) -> u8 {
+    let r = pix[0] as u16;
+    let g = pix[1] as u16;
+    let b = pix[2] as u16;
+    ((3 * r + b + 4 * g) >> 3) as u8
+}
+
+/// Keeps grayscale and high bit depth sources, everything else becomes 8-bit RGBA
+impl From<DynamicImage> for MiData {
+    fn from(img: DynamicImage) -> Self {
+        match img {
+            DynamicImage::ImageLuma8(_) | DynamicImage::ImageLumaA8(_) => {
+                MiData::GRAY(img.into_luma_alpha8())
+            }
+            DynamicImage::ImageLuma16(_)
+            | DynamicImage::ImageLumaA16(_)
+            | DynamicImage::ImageRgb16(_)
+            | DynamicImage::ImageRgba16(_) => MiData::RGBA16(img.into_rgba16()),
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                MiData::RGBA32F(img.into_rgba32f())
+            }
+            _ => MiData::RGBA(img.into_rgba8()),
+        }
+    }
 }
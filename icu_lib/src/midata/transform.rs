@@ -0,0 +1,327 @@
+//! Geometry transforms applied to [`MiData`] before encoding.
+//!
+//! [`TransformParams`] always runs its steps in the same order:
+//! crop, resize, rotate, flip and finally pad to the target canvas.
+
+use crate::error::{IcuError, Result};
+use crate::midata::{IndexedImage, MiData};
+use image::metadata::Orientation;
+use image::{
+    imageops, DynamicImage, GrayImage, ImageBuffer, Luma, Pixel, Rgba, Rgba32FImage, RgbaImage,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+impl From<ResizeFilter> for imageops::FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => imageops::FilterType::Nearest,
+            ResizeFilter::Triangle => imageops::FilterType::Triangle,
+            ResizeFilter::CatmullRom => imageops::FilterType::CatmullRom,
+            ResizeFilter::Gaussian => imageops::FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => imageops::FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Clockwise rotation
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Rotation {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TransformParams {
+    pub crop: Option<Rect>,
+    /// Target size, a 0 side follows the aspect ratio of the other one
+    pub resize: Option<(u32, u32)>,
+    pub resize_filter: ResizeFilter,
+    pub rotation: Option<Rotation>,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    /// Canvas size, the image is centered on it
+    pub pad: Option<(u32, u32)>,
+    /// RGBA color of the padded area
+    pub pad_color: [u8; 4],
+}
+
+impl TransformParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_crop(mut self, crop: Rect) -> Self {
+        self.crop = Some(crop);
+        self
+    }
+
+    pub fn with_resize(mut self, width: u32, height: u32) -> Self {
+        self.resize = Some((width, height));
+        self
+    }
+
+    pub fn with_resize_filter(mut self, resize_filter: ResizeFilter) -> Self {
+        self.resize_filter = resize_filter;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = Some(rotation);
+        self
+    }
+
+    pub fn with_flip_horizontal(mut self, flip_horizontal: bool) -> Self {
+        self.flip_horizontal = flip_horizontal;
+        self
+    }
+
+    pub fn with_flip_vertical(mut self, flip_vertical: bool) -> Self {
+        self.flip_vertical = flip_vertical;
+        self
+    }
+
+    pub fn with_pad(mut self, width: u32, height: u32) -> Self {
+        self.pad = Some((width, height));
+        self
+    }
+
+    pub fn with_pad_color(mut self, pad_color: [u8; 4]) -> Self {
+        self.pad_color = pad_color;
+        self
+    }
+
+    /// Turn the image upright as its EXIF orientation says
+    pub fn with_orientation(mut self, orientation: Orientation) -> Self {
+        let (rotation, flip_horizontal, flip_vertical) = match orientation {
+            Orientation::NoTransforms => (None, false, false),
+            Orientation::Rotate90 => (Some(Rotation::Rotate90), false, false),
+            Orientation::Rotate180 => (Some(Rotation::Rotate180), false, false),
+            Orientation::Rotate270 => (Some(Rotation::Rotate270), false, false),
+            Orientation::FlipHorizontal => (None, true, false),
+            Orientation::FlipVertical => (None, false, true),
+            Orientation::Rotate90FlipH => (Some(Rotation::Rotate90), true, false),
+            Orientation::Rotate270FlipH => (Some(Rotation::Rotate270), true, false),
+        };
+        self.rotation = rotation;
+        self.flip_horizontal = flip_horizontal;
+        self.flip_vertical = flip_vertical;
+        self
+    }
+
+    /// Whether applying the params leaves every image untouched
+    pub fn is_identity(&self) -> bool {
+        self.crop.is_none()
+            && self.resize.is_none()
+            && self.rotation.is_none()
+            && !self.flip_horizontal
+            && !self.flip_vertical
+            && self.pad.is_none()
+    }
+
+    pub fn apply(&self, img: RgbaImage) -> Result<RgbaImage> {
+        self.apply_with(img, Rgba(self.pad_color), |img, width, height| {
+            if self.resize_filter == ResizeFilter::Nearest {
+                return imageops::resize(img, width, height, imageops::FilterType::Nearest);
+            }
+            let precise = DynamicImage::ImageRgba8(img.clone()).into_rgba32f();
+            DynamicImage::ImageRgba32F(resize(&precise, width, height, self.resize_filter))
+                .into_rgba8()
+        })
+    }
+
+    /// [`TransformParams::apply`] at full precision, for the 16-bit and floating point images
+    pub fn apply_f32(&self, img: Rgba32FImage) -> Result<Rgba32FImage> {
+        let pad_color = Rgba(self.pad_color.map(|c| c as f32 / 255.));
+        self.apply_with(img, pad_color, |img, width, height| {
+            resize(img, width, height, self.resize_filter)
+        })
+    }
+
+    /// [`TransformParams::apply`] on the indexes, keeping the palette.
+    /// `None` when the result needs colors the palette doesn't have: a resize filter that blends
+    /// pixels, or a pad color missing from the palette
+    pub fn apply_indexed(&self, img: &IndexedImage) -> Result<Option<IndexedImage>> {
+        if self.resize.is_some() && self.resize_filter != ResizeFilter::Nearest {
+            return Ok(None);
+        }
+        let pad_index = match self.pad {
+            None => 0,
+            // Any fully transparent entry stands in for a transparent pad color
+            Some(_) => match img.palette().iter().position(|&color| {
+                color == self.pad_color || (color[3] == 0 && self.pad_color[3] == 0)
+            }) {
+                Some(index) => index as u8,
+                None => return Ok(None),
+            },
+        };
+
+        let indexes = GrayImage::from_raw(img.width(), img.height(), img.indexes().to_vec())
+            .ok_or(IcuError::UnsupportedData)?;
+        let indexes = self.apply_with(indexes, Luma([pad_index]), |img, width, height| {
+            imageops::resize(img, width, height, imageops::FilterType::Nearest)
+        })?;
+        let (width, height) = indexes.dimensions();
+        IndexedImage::new(width, height, img.palette().to_vec(), indexes.into_raw()).map(Some)
+    }
+
+    fn apply_with<P: Pixel + 'static>(
+        &self,
+        img: ImageBuffer<P, Vec<P::Subpixel>>,
+        pad_color: P,
+        resize: impl Fn(&ImageBuffer<P, Vec<P::Subpixel>>, u32, u32) -> ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> Result<ImageBuffer<P, Vec<P::Subpixel>>> {
+        let mut img = img;
+
+        if let Some(rect) = self.crop {
+            if rect.width == 0
+                || rect.height == 0
+                || rect
+                    .x
+                    .checked_add(rect.width)
+                    .is_none_or(|x| x > img.width())
+                || rect
+                    .y
+                    .checked_add(rect.height)
+                    .is_none_or(|y| y > img.height())
+            {
+                return Err(IcuError::InvalidGeometry(format!(
+                    "crop {}x{}+{}+{} is outside of the {}x{} image",
+                    rect.width,
+                    rect.height,
+                    rect.x,
+                    rect.y,
+                    img.width(),
+                    img.height()
+                )));
+            }
+            img = imageops::crop_imm(&img, rect.x, rect.y, rect.width, rect.height).to_image();
+        }
+
+        if let Some((width, height)) = self.resize {
+            let (width, height) = match (width, height) {
+                (0, 0) => {
+                    return Err(IcuError::InvalidGeometry(
+                        "resize needs at least a width or a height".to_owned(),
+                    ))
+                }
+                (0, height) => (scale(img.width(), height, img.height()), height),
+                (width, 0) => (width, scale(img.height(), width, img.width())),
+                size => size,
+            };
+            img = resize(&img, width, height);
+        }
+
+        img = match self.rotation {
+            None => img,
+            Some(Rotation::Rotate90) => imageops::rotate90(&img),
+            Some(Rotation::Rotate180) => imageops::rotate180(&img),
+            Some(Rotation::Rotate270) => imageops::rotate270(&img),
+        };
+
+        if self.flip_horizontal {
+            imageops::flip_horizontal_in_place(&mut img);
+        }
+        if self.flip_vertical {
+            imageops::flip_vertical_in_place(&mut img);
+        }
+
+        if let Some((width, height)) = self.pad {
+            if width < img.width() || height < img.height() {
+                return Err(IcuError::InvalidGeometry(format!(
+                    "{}x{} image does not fit in the {width}x{height} canvas",
+                    img.width(),
+                    img.height()
+                )));
+            }
+            let mut canvas = ImageBuffer::from_pixel(width, height, pad_color);
+            let x = (width - img.width()) / 2;
+            let y = (height - img.height()) / 2;
+            imageops::replace(&mut canvas, &img, x as i64, y as i64);
+            img = canvas;
+        }
+
+        Ok(img)
+    }
+}
+
+/// `size * numerator / denominator` rounded, at least 1
+fn scale(size: u32, numerator: u32, denominator: u32) -> u32 {
+    ((size as u64 * numerator as u64 + denominator as u64 / 2) / denominator as u64).max(1) as u32
+}
+
+/// Resample with the color channels premultiplied by alpha,
+/// so transparent pixels don't bleed their hidden color into the edges.
+/// The colors stay gamma encoded sRGB, they are not converted to linear light.
+fn resize(img: &Rgba32FImage, width: u32, height: u32, filter: ResizeFilter) -> Rgba32FImage {
+    if filter == ResizeFilter::Nearest {
+        return imageops::resize(img, width, height, filter.into());
+    }
+
+    let mut premultiplied = img.clone();
+    premultiplied.pixels_mut().for_each(|Rgba([r, g, b, a])| {
+        *r *= *a;
+        *g *= *a;
+        *b *= *a;
+    });
+
+    let mut resized = imageops::resize(&premultiplied, width, height, filter.into());
+    resized.pixels_mut().for_each(|Rgba([r, g, b, a])| {
+        *a = a.clamp(0.0, 1.0);
+        if *a > 0.0 {
+            *r = (*r / *a).clamp(0.0, 1.0);
+            *g = (*g / *a).clamp(0.0, 1.0);
+            *b = (*b / *a).clamp(0.0, 1.0);
+        }
+    });
+
+    resized
+}
+
+impl MiData {
+    pub fn transform(self, params: &TransformParams) -> Result<Self> {
+        if params.is_identity() {
+            return Ok(self);
+        }
+
+        match self {
+            MiData::RGBA(img) => Ok(MiData::RGBA(params.apply(img)?)),
+            MiData::RGBA16(img) => {
+                let img = params.apply_f32(DynamicImage::ImageRgba16(img).into_rgba32f())?;
+                Ok(MiData::RGBA16(
+                    DynamicImage::ImageRgba32F(img).into_rgba16(),
+                ))
+            }
+            MiData::RGBA32F(img) => Ok(MiData::RGBA32F(params.apply_f32(img)?)),
+            MiData::GRAY(img) => {
+                let img = params.apply(DynamicImage::ImageLumaA8(img).into_rgba8())?;
+                Ok(MiData::GRAY(
+                    DynamicImage::ImageRgba8(img).into_luma_alpha8(),
+                ))
+            }
+            MiData::INDEXED(img) => match params.apply_indexed(&img)? {
+                Some(indexed) => Ok(MiData::INDEXED(indexed)),
+                // Resampling or padding makes new colors, so the palette is dropped
+                None => Ok(MiData::RGBA(params.apply(img.to_rgba())?)),
+            },
+            MiData::PATH => Err(IcuError::UnsupportedData),
+        }
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use icu_lib::endecoder::{common, lvgl};
+    use icu_lib::endecoder::{common, lvgl, EnDecoder};
     use icu_lib::midata::MiData;
     use icu_lib::EncoderParams;
     use std::fs;
@@ -11,20 +11,22 @@ mod tests {
     macro_rules! test_encode_decode {
         ($data:expr, $cf:tt) => {{
             let data = ($data).clone();
-            let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(data));
-            let data = mid.encode_into(
-                &lvgl::LVGL {},
-                EncoderParams {
-                    color_format: lvgl::ColorFormat::$cf,
-                    stride_align: 256,
-                    lvgl_version: lvgl::LVGLVersion::V9,
-                    ..Default::default()
-                },
-            );
+            let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(data)).unwrap();
+            let data = mid
+                .encode_into(
+                    &lvgl::LVGL {},
+                    EncoderParams {
+                        color_format: lvgl::ColorFormat::$cf,
+                        stride_align: 256,
+                        lvgl_version: lvgl::LVGLVersion::V9,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
             fs::write("./res/img_0.bin", data).expect("Unable to write file");
 
             let data = fs::read("./res/img_0.bin").expect("Unable to read file");
-            MiData::decode_from(&lvgl::LVGL {}, data);
+            MiData::decode_from(&lvgl::LVGL {}, data).unwrap();
         }};
     }
 
@@ -49,12 +51,1775 @@ mod tests {
         test_encode_decode!(DATA, I8);
 
         let data = fs::read("./res/img_0.bin").expect("Unable to read file");
-        let mid = MiData::decode_from(&lvgl::LVGL {}, data);
-        let data = mid.encode_into(&common::PNG {}, Default::default());
+        let mid = MiData::decode_from(&lvgl::LVGL {}, data).unwrap();
+        let data = mid
+            .encode_into(&common::PNG {}, Default::default())
+            .unwrap();
         fs::write("img_0_after.png", data).expect("Unable to write file");
 
         // delete png file and bin file
         fs::remove_file("img_0_after.png").expect("Unable to delete file");
         fs::remove_file("./res/img_0.bin").expect("Unable to delete file");
     }
+
+    #[test]
+    fn compressed_round_trip() {
+        let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(DATA)).unwrap();
+
+        let encode = |compress| {
+            mid.encode_into(
+                &lvgl::LVGL {},
+                EncoderParams {
+                    color_format: lvgl::ColorFormat::ARGB8888,
+                    lvgl_version: lvgl::LVGLVersion::V9,
+                    compress,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+        };
+
+        let plain = encode(lvgl::Compress::NONE);
+        let plain = match MiData::decode_from(&lvgl::LVGL {}, plain).unwrap() {
+            MiData::RGBA(img) => img,
+            _ => panic!("LVGL decoder must produce RGBA"),
+        };
+
+        for compress in [lvgl::Compress::Rle, lvgl::Compress::LZ4] {
+            let data = encode(compress);
+            let info = lvgl::LVGL {}.info(&data).unwrap();
+            assert_eq!(
+                info.other_info["Compressed Info"]["Method"],
+                format!("{compress:#?}")
+            );
+
+            match MiData::decode_from(&lvgl::LVGL {}, data).unwrap() {
+                MiData::RGBA(img) => assert_eq!(img, plain, "{compress:?} round trip"),
+                _ => panic!("LVGL decoder must produce RGBA"),
+            }
+        }
+    }
+
+    #[test]
+    fn c_array_round_trip() {
+        let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(DATA)).unwrap();
+        let bin = mid
+            .encode_into(
+                &lvgl::LVGL {},
+                EncoderParams {
+                    color_format: lvgl::ColorFormat::I4,
+                    stride_align: 4,
+                    lvgl_version: lvgl::LVGLVersion::V9,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let source = lvgl::c_array::to_c_array(&bin, "img-0").unwrap();
+        assert!(source.contains("const lv_image_dsc_t img_0 = {"));
+        assert!(lvgl::LVGLCArray {}.can_decode(source.as_bytes()));
+
+        match (
+            MiData::decode_from(&lvgl::LVGL {}, bin)
+                .unwrap()
+                .into_rgba(),
+            MiData::decode_from(&lvgl::LVGLCArray {}, source.into_bytes())
+                .unwrap()
+                .into_rgba(),
+        ) {
+            (Some(expected), Some(img)) => assert_eq!(img, expected),
+            _ => panic!("LVGL decoders must produce images"),
+        }
+    }
+
+    #[test]
+    fn c_array_legacy_v8_source() {
+        const SOURCE: &str = r#"
+#include "lvgl/lvgl.h"
+
+const LV_ATTRIBUTE_MEM_ALIGN LV_ATTRIBUTE_LARGE_CONST uint8_t dot_map[] = {
+#if LV_COLOR_DEPTH == 1 || LV_COLOR_DEPTH == 8
+  /*Pixel format: Blue: 2 bit, Green: 3 bit, Red: 3 bit, Alpha 8 bit */
+  0xe0, 0xff, 0x1c, 0x80,
+#endif
+#if LV_COLOR_DEPTH == 16 && LV_COLOR_16_SWAP == 0
+  0x00, 0xf8, 0xff, 0xe0, 0x07, 0x80,
+#endif
+#if LV_COLOR_DEPTH == 32
+  /*Pixel format: Blue: 8 bit, Green: 8 bit, Red: 8 bit, Alpha: 8 bit*/
+  0x00, 0x00, 0xff, 0xff, 0x00, 0xff, 0x00, 0x80,
+#endif
+};
+
+const lv_img_dsc_t dot = {
+  .header.cf = LV_IMG_CF_TRUE_COLOR_ALPHA,
+  .header.always_zero = 0,
+  .header.reserved = 0,
+  .header.w = 2,
+  .header.h = 1,
+  .data_size = 2 * LV_IMG_PX_SIZE_ALPHA_BYTE,
+  .data = dot_map,
+};
+"#;
+
+        let info = lvgl::LVGLCArray {}.info(SOURCE.as_bytes()).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(info.other_info["Variable Name"], "dot");
+
+        match MiData::decode_from(&lvgl::LVGLCArray {}, SOURCE.as_bytes().to_vec()).unwrap() {
+            MiData::RGBA(img) => {
+                assert_eq!(img.into_raw(), [0xff, 0, 0, 0xff, 0, 0xff, 0, 0x80])
+            }
+            _ => panic!("LVGL decoders must produce RGBA"),
+        }
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        use icu_lib::endecoder::raw::RawImage;
+
+        let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(DATA)).unwrap();
+        let (width, height) = match &mid {
+            MiData::RGBA(img) => img.dimensions(),
+            _ => panic!("AutoDetect must produce RGBA"),
+        };
+        let params = || EncoderParams {
+            color_format: lvgl::ColorFormat::RGB565A8,
+            stride_align: 64,
+            lvgl_version: lvgl::LVGLVersion::V9,
+            ..Default::default()
+        };
+
+        let raw = RawImage::new(width, height, lvgl::ColorFormat::RGB565A8)
+            .with_stride(lvgl::ColorFormat::RGB565A8.get_stride_size(width, 64))
+            .with_offset(3);
+        let mut data = vec![0xAA; 3];
+        data.extend(mid.encode_into(&raw, params()).unwrap());
+        assert_eq!(data.len(), 3 + raw.data_size());
+        assert!(raw.can_decode(&data));
+
+        let bin = mid.encode_into(&lvgl::LVGL {}, params()).unwrap();
+        match (
+            MiData::decode_from(&lvgl::LVGL {}, bin).unwrap(),
+            MiData::decode_from(&raw, data).unwrap(),
+        ) {
+            (MiData::RGBA(expected), MiData::RGBA(img)) => assert_eq!(img, expected),
+            _ => panic!("Decoders must produce RGBA"),
+        }
+    }
+
+    #[test]
+    fn l16_and_a16_are_raw_only() {
+        use icu_lib::endecoder::raw::RawImage;
+        use icu_lib::IcuError;
+
+        let white = MiData::from_rgba(1, 1, vec![0xFF; 4]).unwrap();
+        for (color_format, expected) in [
+            (lvgl::ColorFormat::L16, [0xFF, 0xFF, 0xFF, 0xFF]),
+            (lvgl::ColorFormat::A16, [0, 0, 0, 0xFF]),
+        ] {
+            let params = || EncoderParams {
+                color_format,
+                lvgl_version: lvgl::LVGLVersion::V9,
+                ..Default::default()
+            };
+            // LVGL has no header code for them
+            assert!(matches!(
+                white.encode_into(&lvgl::LVGL {}, params()),
+                Err(IcuError::UnsupportedColorFormat(_))
+            ));
+
+            let mut bin =
+                lvgl::ImageHeader::new(lvgl::LVGLVersion::V9, color_format, 0, 1, 1, 2).encode();
+            bin.extend([0xFF; 2]);
+            assert!(!lvgl::LVGL {}.can_decode(&bin));
+            assert!(MiData::decode_from(&lvgl::LVGL {}, bin).is_err());
+
+            let raw = RawImage::new(1, 1, color_format);
+            let data = white.encode_into(&raw, params()).unwrap();
+            match MiData::decode_from(&raw, data).unwrap() {
+                MiData::RGBA(img) => assert_eq!(img.into_raw(), expected, "{color_format:?}"),
+                _ => panic!("RAW decoder must produce RGBA"),
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_data_is_an_error() {
+        use icu_lib::IcuError;
+
+        let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(DATA)).unwrap();
+        let bin = mid
+            .encode_into(
+                &lvgl::LVGL {},
+                EncoderParams {
+                    color_format: lvgl::ColorFormat::RGB565A8,
+                    lvgl_version: lvgl::LVGLVersion::V9,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        assert!(matches!(
+            lvgl::LVGL {}.info(&bin[..5]),
+            Err(IcuError::TruncatedHeader {
+                expected: 12,
+                actual: 5
+            })
+        ));
+        assert!(matches!(
+            MiData::decode_from(&lvgl::LVGL {}, bin[..bin.len() - 1].to_vec()),
+            Err(IcuError::DataSizeMismatch { .. })
+        ));
+        assert!(matches!(
+            MiData::decode_from(&common::PNG {}, DATA[..DATA.len() / 2].to_vec()),
+            Err(IcuError::Image(_))
+        ));
+        assert!(matches!(
+            mid.encode_into(
+                &common::PNG {},
+                EncoderParams::new().with_color_format(lvgl::ColorFormat::RGB565)
+            ),
+            Err(IcuError::UnsupportedColorFormat(lvgl::ColorFormat::RGB565))
+        ));
+    }
+
+    #[test]
+    fn extended_color_formats_round_trip() {
+        use lvgl::ColorFormat;
+
+        let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(DATA)).unwrap();
+        let round_trip = |mid: &MiData, color_format| {
+            let data = mid
+                .encode_into(
+                    &lvgl::LVGL {},
+                    EncoderParams {
+                        color_format,
+                        stride_align: 4,
+                        lvgl_version: lvgl::LVGLVersion::V9,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            MiData::decode_from(&lvgl::LVGL {}, data).unwrap()
+        };
+        let white = MiData::from_rgba(1, 1, vec![0xFF; 4]).unwrap();
+
+        for color_format in [
+            ColorFormat::RGB565Swapped,
+            ColorFormat::ARGB8565,
+            ColorFormat::AL88,
+            ColorFormat::ARGB1555,
+            ColorFormat::ARGB4444,
+            ColorFormat::ARGB2222,
+        ] {
+            // A second round trip must not lose anything more
+            let once = round_trip(&mid, color_format);
+            match (&once, round_trip(&once, color_format)) {
+                (MiData::RGBA(once), MiData::RGBA(twice)) => {
+                    assert_eq!(once, &twice, "{color_format:?} round trip")
+                }
+                _ => panic!("LVGL decoder must produce RGBA"),
+            }
+
+            match round_trip(&white, color_format) {
+                MiData::RGBA(img) => assert_eq!(img.into_raw(), [0xFF; 4], "{color_format:?}"),
+                _ => panic!("LVGL decoder must produce RGBA"),
+            }
+        }
+    }
+
+    #[test]
+    fn yuv_color_formats_round_trip() {
+        use lvgl::ColorFormat;
+
+        let mid = MiData::decode_from(&common::AutoDetect {}, Vec::from(DATA)).unwrap();
+        // Drop the alpha channel, YUV can't keep it
+        let original = match &mid {
+            MiData::RGBA(img) => img
+                .pixels()
+                .flat_map(|p| [p[0], p[1], p[2]])
+                .collect::<Vec<_>>(),
+            _ => panic!("AutoDetect must produce RGBA"),
+        };
+
+        for color_format in [
+            ColorFormat::I420,
+            ColorFormat::I422,
+            ColorFormat::I444,
+            ColorFormat::NV12,
+            ColorFormat::NV21,
+            ColorFormat::YUY2,
+            ColorFormat::UYVY,
+        ] {
+            let bin = mid
+                .encode_into(
+                    &lvgl::LVGL {},
+                    EncoderParams {
+                        color_format,
+                        stride_align: 8,
+                        lvgl_version: lvgl::LVGLVersion::V9,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+
+            let source = lvgl::c_array::to_c_array(&bin, "yuv").unwrap();
+            let from_source =
+                MiData::decode_from(&lvgl::LVGLCArray {}, source.into_bytes()).unwrap();
+
+            match (
+                MiData::decode_from(&lvgl::LVGL {}, bin).unwrap(),
+                from_source,
+            ) {
+                (MiData::RGBA(img), MiData::RGBA(from_source)) => {
+                    assert_eq!(img, from_source, "{color_format:?} C array");
+
+                    let decoded = img.pixels().flat_map(|p| [p[0], p[1], p[2]]);
+                    let error = original
+                        .iter()
+                        .zip(decoded)
+                        .map(|(a, b)| a.abs_diff(b) as u64)
+                        .sum::<u64>() as f64
+                        / original.len() as f64;
+                    assert!(error < 8.0, "{color_format:?} mean error {error}");
+                }
+                _ => panic!("LVGL decoders must produce RGBA"),
+            }
+        }
+
+        // Odd sizes need rounded up chroma planes
+        let odd = MiData::from_rgba(3, 3, vec![0x80; 3 * 3 * 4]).unwrap();
+        for color_format in [ColorFormat::I420, ColorFormat::NV21, ColorFormat::YUY2] {
+            let bin = odd
+                .encode_into(
+                    &lvgl::LVGL {},
+                    EncoderParams::new()
+                        .with_color_format(color_format)
+                        .with_lvgl_version(lvgl::LVGLVersion::V9),
+                )
+                .unwrap();
+            match MiData::decode_from(&lvgl::LVGL {}, bin).unwrap() {
+                MiData::RGBA(img) => assert_eq!(img.dimensions(), (3, 3)),
+                _ => panic!("LVGL decoder must produce RGBA"),
+            }
+        }
+    }
+
+    #[test]
+    fn premultiplied_round_trip() {
+        let mid =
+            MiData::from_rgba(2, 1, vec![0xFF, 0x80, 0x40, 0x80, 0x10, 0x20, 0x30, 0]).unwrap();
+        let data = mid
+            .encode_into(
+                &lvgl::LVGL {},
+                EncoderParams::new()
+                    .with_color_format(lvgl::ColorFormat::ARGB8888)
+                    .with_lvgl_version(lvgl::LVGLVersion::V9)
+                    .with_premultiply(true),
+            )
+            .unwrap();
+
+        let info = lvgl::LVGL {}.info(&data).unwrap();
+        assert_eq!(info.other_info["Flags"], "1");
+        // BGRA in the file, scaled by alpha
+        assert_eq!(&data[12..], [0x20, 0x40, 0x80, 0x80, 0, 0, 0, 0]);
+
+        match MiData::decode_from(&lvgl::LVGL {}, data).unwrap() {
+            MiData::RGBA(img) => assert_eq!(img.into_raw(), [0xFF, 0x80, 0x40, 0x80, 0, 0, 0, 0]),
+            _ => panic!("LVGL decoder must produce RGBA"),
+        }
+    }
+    #[test]
+    fn lvgl_v8_true_color_round_trip() {
+        use lvgl::{ColorDepth, ColorFormat, LVGLV8};
+
+        // Opaque red, half transparent blue, transparent black
+        let mid =
+            MiData::from_rgba(3, 1, vec![0xFF, 0, 0, 0xFF, 0, 0, 0xFF, 0x80, 0, 0, 0, 0]).unwrap();
+        let encode = |v8: LVGLV8, color_format| {
+            mid.encode_into(&v8, EncoderParams::new().with_color_format(color_format))
+                .unwrap()
+        };
+        let decode = |v8: LVGLV8, data| match MiData::decode_from(&v8, data).unwrap() {
+            MiData::RGBA(img) => img.into_raw(),
+            _ => panic!("LVGL decoder must produce RGBA"),
+        };
+
+        // RGB565 and an alpha byte at 16 bits, byte swapped with LV_COLOR_16_SWAP
+        for (color_16_swap, red) in [(false, [0x00, 0xF8]), (true, [0xF8, 0x00])] {
+            let v8 = LVGLV8 {
+                color_depth: ColorDepth::Depth16,
+                color_16_swap,
+            };
+            let data = encode(v8, ColorFormat::TrueColorAlpha);
+            assert_eq!(data[0], 0x05);
+            assert_eq!(&data[4..6], red);
+            assert_eq!(data.len(), 4 + 3 * 3);
+            assert_eq!(
+                decode(v8, data),
+                [0xF8, 0, 0, 0xFF, 0, 0, 0xF8, 0x80, 0, 0, 0, 0]
+            );
+        }
+
+        // RGB332 at 8 bits, transparent pixels become the green chroma key
+        let v8 = LVGLV8 {
+            color_depth: ColorDepth::Depth8,
+            color_16_swap: false,
+        };
+        let data = encode(v8, ColorFormat::TrueColorChromaKeyed);
+        assert_eq!(data[0], 0x06);
+        assert_eq!(&data[4..], [0xE0, 0x03, 0x1C]);
+        let info = v8.info(&data).unwrap();
+        assert_eq!(info.format, "LVGL.V8(TrueColorChromaKeyed)");
+        assert_eq!(info.other_info["Color Depth"], 8);
+        let alphas = decode(v8, data)
+            .chunks_exact(4)
+            .map(|pix| pix[3])
+            .collect::<Vec<_>>();
+        assert_eq!(alphas, [0xFF, 0xFF, 0]);
+
+        // `LVGL` reads V8 images as 32 bit colors
+        let data = encode(LVGLV8::default(), ColorFormat::TrueColor);
+        assert_eq!(&data[4..8], [0, 0, 0xFF, 0xFF]);
+        match MiData::decode_from(&lvgl::LVGL {}, data).unwrap() {
+            MiData::RGBA(img) => assert_eq!(img.as_raw()[..4], [0xFF, 0, 0, 0xFF]),
+            _ => panic!("LVGL decoder must produce RGBA"),
+        }
+
+        // V9 only formats have no V8 code
+        assert!(mid
+            .encode_into(
+                &LVGLV8::default(),
+                EncoderParams::new().with_color_format(ColorFormat::L8)
+            )
+            .is_err());
+    }
+    #[test]
+    fn transform_pipeline() {
+        use icu_lib::midata::transform::{Rect, ResizeFilter, Rotation, TransformParams};
+
+        let rgba = |mid: MiData| match mid {
+            MiData::RGBA(img) => img,
+            _ => panic!("transforms keep RGBA images RGBA"),
+        };
+
+        // 3x2 image with a distinct red value per pixel
+        let pixels = (0..6u8).flat_map(|i| [i, 0, 0, 0xFF]).collect::<Vec<_>>();
+        let mid = || MiData::from_rgba(3, 2, pixels.clone()).unwrap();
+
+        // Crop the right 2x2, rotate it clockwise, mirror it and pad it to 4x4
+        let img = rgba(
+            mid()
+                .transform(
+                    &TransformParams::new()
+                        .with_crop(Rect {
+                            x: 1,
+                            y: 0,
+                            width: 2,
+                            height: 2,
+                        })
+                        .with_rotation(Rotation::Rotate90)
+                        .with_flip_horizontal(true)
+                        .with_pad(4, 4)
+                        .with_pad_color([0, 0, 0xFF, 0xFF]),
+                )
+                .unwrap(),
+        );
+        assert_eq!(img.dimensions(), (4, 4));
+        let red = |x, y| img.get_pixel(x, y).0[0];
+        assert_eq!([red(1, 1), red(2, 1), red(1, 2), red(2, 2)], [1, 4, 2, 5]);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0xFF, 0xFF]);
+
+        // A 0 side keeps the aspect ratio
+        let img = rgba(
+            mid()
+                .transform(&TransformParams::new().with_resize(6, 0))
+                .unwrap(),
+        );
+        assert_eq!(img.dimensions(), (6, 4));
+
+        // Hidden colors of transparent pixels must not bleed into the result
+        let edge = MiData::from_rgba(2, 1, vec![0xFF, 0, 0, 0xFF, 0, 0xFF, 0, 0]).unwrap();
+        let img = rgba(
+            edge.transform(
+                &TransformParams::new()
+                    .with_resize(1, 1)
+                    .with_resize_filter(ResizeFilter::Triangle),
+            )
+            .unwrap(),
+        );
+        let [r, g, _, a] = img.get_pixel(0, 0).0;
+        assert_eq!((r, g), (0xFF, 0));
+        assert!((0x70..0x90).contains(&a), "alpha {a}");
+
+        // Out of bounds crops and canvases smaller than the image are errors
+        let crop = Rect {
+            x: 2,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        assert!(mid()
+            .transform(&TransformParams::new().with_crop(crop))
+            .is_err());
+        assert!(mid()
+            .transform(&TransformParams::new().with_pad(2, 2))
+            .is_err());
+    }
+    #[test]
+    fn palette_quantizers() {
+        use icu_lib::endecoder::utils::quantize::Quantizer;
+        use lvgl::ColorFormat;
+
+        let decode = |ed: &dyn EnDecoder, data| {
+            MiData::decode_from(ed, data)
+                .unwrap()
+                .into_rgba()
+                .expect("decoders must produce images")
+                .into_raw()
+        };
+        let quantizers = [
+            Quantizer::Exact,
+            Quantizer::NeuQuant,
+            Quantizer::MedianCut,
+            Quantizer::Octree,
+            Quantizer::KMeans,
+        ];
+
+        // 5 colors fit in I4, and transparent pixels share a single entry whatever their color
+        let icon = [
+            [0xFF, 0, 0, 0xFF],
+            [0, 0xFF, 0, 0x80],
+            [0x12, 0x34, 0x56, 0xFF],
+            [0xFF, 0xFF, 0xFF, 0xFF],
+            [0, 0, 0, 0],
+            [0, 0, 0xFF, 0],
+        ]
+        .repeat(4)
+        .concat();
+        let expected = icon
+            .chunks_exact(4)
+            .flat_map(|pix| {
+                if pix[3] == 0 {
+                    [0; 4]
+                } else {
+                    [pix[0], pix[1], pix[2], pix[3]]
+                }
+            })
+            .collect::<Vec<_>>();
+        let mid = MiData::from_rgba(6, 4, icon).unwrap();
+        for quantizer in quantizers {
+            let params = || {
+                EncoderParams::new()
+                    .with_color_format(ColorFormat::I4)
+                    .with_lvgl_version(lvgl::LVGLVersion::V9)
+                    .with_quantizer(quantizer)
+            };
+            let bin = mid.encode_into(&lvgl::LVGL {}, params()).unwrap();
+            assert_eq!(decode(&lvgl::LVGL {}, bin), expected, "{quantizer:?}");
+            let png = mid.encode_into(&common::PNG {}, params()).unwrap();
+            assert_eq!(decode(&common::PNG {}, png), expected, "{quantizer:?}");
+        }
+
+        // A gradient needs to be reduced, only the exact quantizer gives up
+        let gradient = (0..64u8)
+            .flat_map(|i| [i * 4, 0xFF - i * 4, 0x80, 0xFF])
+            .collect::<Vec<_>>();
+        let mid = MiData::from_rgba(64, 1, gradient.clone()).unwrap();
+        for quantizer in quantizers {
+            let result = mid.encode_into(
+                &lvgl::LVGL {},
+                EncoderParams::new()
+                    .with_color_format(ColorFormat::I2)
+                    .with_lvgl_version(lvgl::LVGLVersion::V9)
+                    .with_quantizer(quantizer),
+            );
+            if quantizer == Quantizer::Exact {
+                assert!(result.is_err());
+                continue;
+            }
+            let decoded = decode(&lvgl::LVGL {}, result.unwrap());
+            let mut colors = decoded.chunks_exact(4).collect::<Vec<_>>();
+            colors.sort();
+            colors.dedup();
+            assert!(colors.len() <= 4, "{quantizer:?}");
+            if quantizer == Quantizer::NeuQuant {
+                // NeuQuant needs far more pixels than this to converge
+                continue;
+            }
+            let error = decoded
+                .iter()
+                .zip(&gradient)
+                .map(|(a, b)| a.abs_diff(*b) as f64)
+                .sum::<f64>()
+                / gradient.len() as f64;
+            assert!(error < 16.0, "{quantizer:?} mean error {error}");
+        }
+    }
+    #[test]
+    fn fixed_palettes() {
+        use icu_lib::endecoder::utils::palette::{
+            extract_palette, read_palette, write_palette, PaletteFormat,
+        };
+        use lvgl::ColorFormat;
+
+        let brand = vec![
+            [0x10, 0x20, 0x30, 0xFF],
+            [0xF0, 0x80, 0x10, 0xFF],
+            [0xFF, 0xFF, 0xFF, 0xFF],
+            [0, 0, 0, 0],
+        ];
+        for format in [PaletteFormat::Gpl, PaletteFormat::Act, PaletteFormat::Png] {
+            let file = write_palette(&brand, format).unwrap();
+            assert_eq!(read_palette(&file).unwrap(), brand, "{format:?}");
+        }
+        // JASC palettes have no alpha
+        let pal = write_palette(&brand, PaletteFormat::Pal).unwrap();
+        assert_eq!(read_palette(&pal).unwrap()[..3], brand[..3]);
+        let gpl =
+            b"GIMP Palette\nName: Brand\nColumns: 2\n#\n 16  32  48\tNavy\n240 128 16 Orange\n";
+        assert_eq!(read_palette(gpl).unwrap(), brand[..2]);
+
+        // Every pixel maps to the nearest brand color, the palette is kept as is
+        let data = [
+            [0x12, 0x22, 0x2E, 0xFF],
+            [0xE0, 0x90, 0x00, 0xFF],
+            [0xFE, 0xFE, 0xFE, 0xFF],
+            [0x80, 0, 0, 0],
+        ]
+        .repeat(3)
+        .concat();
+        let mid = MiData::from_rgba(4, 3, data).unwrap();
+        let expected = brand.repeat(3).concat();
+        let params = |color_format| {
+            EncoderParams::new()
+                .with_color_format(color_format)
+                .with_lvgl_version(lvgl::LVGLVersion::V9)
+                .with_palette(brand.clone())
+        };
+
+        let bin = mid
+            .encode_into(&lvgl::LVGL {}, params(ColorFormat::I4))
+            .unwrap();
+        assert_eq!(extract_palette(&bin).unwrap(), brand);
+        let Some(img) = MiData::decode_from(&lvgl::LVGL {}, bin)
+            .unwrap()
+            .into_rgba()
+        else {
+            panic!("LVGL decodes to an image")
+        };
+        assert_eq!(img.into_raw(), expected);
+
+        let png = mid
+            .encode_into(&common::PNG {}, params(ColorFormat::I2))
+            .unwrap();
+        assert_eq!(extract_palette(&png).unwrap(), brand);
+        let Some(img) = MiData::decode_from(&common::PNG {}, png)
+            .unwrap()
+            .into_rgba()
+        else {
+            panic!("PNG decodes to an image")
+        };
+        assert_eq!(img.into_raw(), expected);
+
+        // The palette has to fit in the color format
+        assert!(mid
+            .encode_into(&lvgl::LVGL {}, params(ColorFormat::I1))
+            .is_err());
+    }
+    #[test]
+    fn dither_to_target_depth() {
+        use icu_lib::endecoder::utils::dither::DitherAlgorithm;
+        use lvgl::ColorFormat;
+
+        let encode = |mid: &MiData, color_format, algorithm: Option<DitherAlgorithm>, strength| {
+            let mut params = EncoderParams::new()
+                .with_color_format(color_format)
+                .with_lvgl_version(lvgl::LVGLVersion::V9)
+                .with_dither_strength(strength);
+            if let Some(algorithm) = algorithm {
+                params = params
+                    .with_dither(Some(10))
+                    .with_dither_algorithm(algorithm);
+            }
+            let bin = mid.encode_into(&lvgl::LVGL {}, params).unwrap();
+            match MiData::decode_from(&lvgl::LVGL {}, bin).unwrap() {
+                MiData::RGBA(img) => img.into_raw(),
+                _ => panic!("LVGL decodes to RGBA"),
+            }
+        };
+        // Mean error of the 8x8 block averages, what the eye sees of the banding
+        let block_error = |decoded: &[u8], original: &[u8], width: usize| {
+            let block = |data: &[u8], bx: usize, by: usize, c: usize| {
+                (0..64)
+                    .map(|i| data[((by * 8 + i / 8) * width + bx * 8 + i % 8) * 4 + c] as f64)
+                    .sum::<f64>()
+                    / 64.
+            };
+            let blocks = width / 8;
+            let mut error = 0.;
+            for bx in 0..blocks {
+                for c in 0..4 {
+                    error += (block(decoded, bx, 0, c) - block(original, bx, 0, c)).abs();
+                }
+            }
+            error / blocks as f64
+        };
+
+        // A slow gradient bands in RGB565, dithering brings the local averages back
+        let width = 256;
+        let gradient = (0..8 * width)
+            .flat_map(|i| {
+                let x = (i % width) as u8;
+                [x, x / 2 + 0x40, 0xFF - x, 0xFF]
+            })
+            .collect::<Vec<_>>();
+        let mid = MiData::from_rgba(width as u32, 8, gradient.clone()).unwrap();
+        // The RGB565 decoder shifts the levels up without filling the low bits, undo that for the display levels
+        let display = |decoded: Vec<u8>| {
+            decoded
+                .chunks_exact(4)
+                .flat_map(|pix| {
+                    [
+                        (pix[0] as u32 * 0xFF / 0xF8) as u8,
+                        (pix[1] as u32 * 0xFF / 0xFC) as u8,
+                        (pix[2] as u32 * 0xFF / 0xF8) as u8,
+                        pix[3],
+                    ]
+                })
+                .collect::<Vec<_>>()
+        };
+        let banded = block_error(
+            &display(encode(&mid, ColorFormat::RGB565, None, 1.0)),
+            &gradient,
+            width,
+        );
+        for algorithm in [
+            DitherAlgorithm::FloydSteinberg,
+            DitherAlgorithm::Atkinson,
+            DitherAlgorithm::Bayer,
+        ] {
+            let decoded = display(encode(&mid, ColorFormat::RGB565, Some(algorithm), 1.0));
+            let dithered = block_error(&decoded, &gradient, width);
+            assert!(
+                dithered < banded / 2.,
+                "{algorithm:?} {dithered} vs {banded}"
+            );
+        }
+
+        // A1 keeps as many opaque pixels as the alpha asks for
+        let translucent = [0x20, 0x40, 0x60, 0x50].repeat(64 * 8);
+        let mid = MiData::from_rgba(64, 8, translucent).unwrap();
+        let decoded = encode(
+            &mid,
+            ColorFormat::A1,
+            Some(DitherAlgorithm::FloydSteinberg),
+            1.0,
+        );
+        let opaque = decoded.chunks_exact(4).filter(|pix| pix[3] != 0).count();
+        assert!((opaque as f64 / 512. - 0x50 as f64 / 255.).abs() < 0.02);
+
+        // Without any strength only the rounding to the nearest level is left
+        let rounded = encode(&mid, ColorFormat::A1, Some(DitherAlgorithm::Atkinson), 0.0);
+        assert!(rounded.chunks_exact(4).all(|pix| pix[3] == 0));
+    }
+
+    #[test]
+    fn indexed_png_lvgl_lossless() {
+        use icu_lib::endecoder::utils::palette::extract_palette;
+        use icu_lib::midata::IndexedImage;
+        use lvgl::ColorFormat;
+
+        // Unsorted, with a duplicate color and an unused entry, so any requantization shows
+        let palette = vec![
+            [0x10, 0x20, 0x30, 0xFF],
+            [0xFF, 0xFF, 0xFF, 0xFF],
+            [0x00, 0x00, 0x00, 0x00],
+            [0x10, 0x20, 0x30, 0xFF],
+            [0xC0, 0x40, 0x00, 0x80],
+            [0x00, 0xFF, 0x00, 0xFF],
+        ];
+        let indexes = (0..7 * 5)
+            .map(|i| [0, 1, 2, 3, 4][i % 5])
+            .collect::<Vec<u8>>();
+        let indexed = IndexedImage::new(7, 5, palette.clone(), indexes.clone()).unwrap();
+        let mid = MiData::INDEXED(indexed.clone());
+
+        // Without a color format, the PNG depth follows the palette size
+        let png = mid
+            .encode_into(&common::PNG {}, EncoderParams::new())
+            .unwrap();
+        assert_eq!(extract_palette(&png).unwrap(), palette);
+        let decoded = MiData::decode_from(&common::PNG {}, png).unwrap();
+        let MiData::INDEXED(ref from_png) = decoded else {
+            panic!("indexed PNGs decode to indexes")
+        };
+        assert_eq!(from_png, &indexed);
+
+        // Crops, rotations, flips and transparent padding move the indexes, the palette stays
+        {
+            use icu_lib::midata::transform::{Rect, ResizeFilter, Rotation, TransformParams};
+
+            let params = TransformParams::new()
+                .with_crop(Rect {
+                    x: 1,
+                    y: 1,
+                    width: 4,
+                    height: 3,
+                })
+                .with_rotation(Rotation::Rotate90)
+                .with_flip_horizontal(true);
+            let transformed = decoded.clone().transform(&params).unwrap();
+            let MiData::INDEXED(ref rotated) = transformed else {
+                panic!("cropping and rotating keep indexes")
+            };
+            assert_eq!(rotated.palette(), palette.as_slice());
+            assert_eq!(
+                rotated.to_rgba(),
+                params.apply(indexed.to_rgba()).unwrap(),
+                "indexes follow the pixels"
+            );
+            let bin = transformed
+                .encode_into(
+                    &lvgl::LVGL {},
+                    EncoderParams::new()
+                        .with_color_format(ColorFormat::I8)
+                        .with_lvgl_version(lvgl::LVGLVersion::V9),
+                )
+                .unwrap();
+            let MiData::INDEXED(from_lvgl) = MiData::decode_from(&lvgl::LVGL {}, bin).unwrap()
+            else {
+                panic!("LVGL I8 decodes to indexes")
+            };
+            assert_eq!(from_lvgl.indexes(), rotated.indexes());
+            assert_eq!(&from_lvgl.palette()[..palette.len()], palette.as_slice());
+
+            let padded = decoded
+                .clone()
+                .transform(&TransformParams::new().with_pad(9, 7))
+                .unwrap();
+            let MiData::INDEXED(padded) = padded else {
+                panic!("a transparent pad color is in the palette")
+            };
+            assert_eq!(padded.indexes()[0], 2);
+
+            // Colors that aren't in the palette need RGBA
+            for params in [
+                TransformParams::new()
+                    .with_pad(9, 7)
+                    .with_pad_color([1, 2, 3, 0xFF]),
+                TransformParams::new()
+                    .with_resize(14, 10)
+                    .with_resize_filter(ResizeFilter::Triangle),
+            ] {
+                assert!(matches!(
+                    decoded.clone().transform(&params).unwrap(),
+                    MiData::RGBA(_)
+                ));
+            }
+            let resized = decoded
+                .clone()
+                .transform(
+                    &TransformParams::new()
+                        .with_resize(14, 10)
+                        .with_resize_filter(ResizeFilter::Nearest),
+                )
+                .unwrap();
+            assert!(matches!(resized, MiData::INDEXED(_)));
+        }
+
+        for (color_format, premultiply) in [
+            (ColorFormat::I8, false),
+            (ColorFormat::I4, true),
+            (ColorFormat::I8, true),
+        ] {
+            let bin = decoded
+                .encode_into(
+                    &lvgl::LVGL {},
+                    EncoderParams::new()
+                        .with_color_format(color_format)
+                        .with_lvgl_version(lvgl::LVGLVersion::V9)
+                        .with_premultiply(premultiply),
+                )
+                .unwrap();
+            let MiData::INDEXED(from_lvgl) = MiData::decode_from(&lvgl::LVGL {}, bin).unwrap()
+            else {
+                panic!("LVGL {color_format:?} decodes to indexes")
+            };
+            assert_eq!(from_lvgl.indexes(), indexes.as_slice());
+            // Premultiplying rounds the translucent entries, but keeps their order
+            let expected = palette
+                .iter()
+                .zip(from_lvgl.palette())
+                .map(|(color, decoded)| match color[3] {
+                    0x01..=0xFE if premultiply => *decoded,
+                    _ => *color,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(&from_lvgl.palette()[..palette.len()], expected.as_slice());
+
+            let png = MiData::INDEXED(from_lvgl)
+                .encode_into(
+                    &common::PNG {},
+                    EncoderParams::new().with_color_format(color_format),
+                )
+                .unwrap();
+            let MiData::INDEXED(back) = MiData::decode_from(&common::PNG {}, png).unwrap() else {
+                panic!("indexed PNGs decode to indexes")
+            };
+            assert_eq!(back.indexes(), indexes.as_slice());
+            assert_eq!(&back.palette()[..palette.len()], expected.as_slice());
+        }
+
+        // Indexes past the palette of a smaller format fall back to quantizing
+        let bin = mid
+            .encode_into(
+                &lvgl::LVGL {},
+                EncoderParams::new()
+                    .with_color_format(ColorFormat::I2)
+                    .with_lvgl_version(lvgl::LVGLVersion::V9),
+            )
+            .unwrap();
+        assert!(MiData::decode_from(&lvgl::LVGL {}, bin).is_ok());
+    }
+
+    #[test]
+    fn gray_pipeline() {
+        use icu_lib::endecoder::utils::diff::diff_image;
+        use lvgl::ColorFormat;
+
+        let luma = (0..12).map(|i| i * 0x17).collect::<Vec<u8>>();
+        let pgm = [b"P5\n4 3 255\n".as_slice(), &luma].concat();
+        let mid = MiData::decode_from(&common::PGM {}, pgm.clone()).unwrap();
+        let MiData::GRAY(ref gray) = mid else {
+            panic!("PGM decodes to GRAY")
+        };
+        assert!(matches!(
+            MiData::decode_from(&common::AutoDetect {}, pgm.clone()).unwrap(),
+            MiData::GRAY(_)
+        ));
+        assert_eq!(
+            mid.encode_into(&common::PGM {}, EncoderParams::new())
+                .unwrap(),
+            pgm
+        );
+
+        let encode = |mid: &MiData, color_format| {
+            let bin = mid
+                .encode_into(
+                    &lvgl::LVGL {},
+                    EncoderParams::new()
+                        .with_color_format(color_format)
+                        .with_lvgl_version(lvgl::LVGLVersion::V9),
+                )
+                .unwrap();
+            MiData::decode_from(&lvgl::LVGL {}, bin).unwrap()
+        };
+
+        // L8 keeps the luminance and decodes back to GRAY
+        let MiData::GRAY(l8) = encode(&mid, ColorFormat::L8) else {
+            panic!("L8 decodes to GRAY")
+        };
+        assert_eq!(&l8, gray);
+
+        // A8 to A1 take the alpha channel
+        let alphas = [0x00, 0x40, 0x80, 0xFF].repeat(3);
+        let translucent = MiData::from_gray(
+            4,
+            3,
+            alphas.iter().flat_map(|&alpha| [0x80, alpha]).collect(),
+        )
+        .unwrap();
+        for (color_format, bits) in [
+            (ColorFormat::A8, 8),
+            (ColorFormat::A4, 4),
+            (ColorFormat::A2, 2),
+            (ColorFormat::A1, 1),
+        ] {
+            let decoded = encode(&translucent, color_format).into_rgba().unwrap();
+            let decoded = decoded.pixels().map(|pix| pix[3] >> (8 - bits));
+            assert!(
+                decoded.eq(alphas.iter().map(|alpha| alpha >> (8 - bits))),
+                "{color_format:?}"
+            );
+        }
+
+        // PNG writes grayscale, with alpha only when it is needed
+        for mid in [&mid, &translucent] {
+            let png = mid
+                .encode_into(&common::PNG {}, EncoderParams::new())
+                .unwrap();
+            let info = common::PNG {}.info(&png).unwrap();
+            let color_type = if matches!(mid, MiData::GRAY(img) if img.pixels().all(|pix| pix[1] == 0xFF))
+            {
+                "Grayscale"
+            } else {
+                "GrayscaleAlpha"
+            };
+            assert_eq!(info.other_info["PNG Color Type"], color_type);
+            let MiData::GRAY(decoded) = MiData::decode_from(&common::PNG {}, png).unwrap() else {
+                panic!("grayscale PNGs decode to GRAY")
+            };
+            assert_eq!(Some(&decoded), mid.to_gray().as_deref());
+        }
+
+        // PNG and LVGL L8 agree on the luminance of colors, translucent ones included
+        let colors = MiData::from_rgba(
+            3,
+            1,
+            vec![0xFF, 0, 0, 0xFF, 0x20, 0xC0, 0x60, 0x80, 0, 0, 0xFF, 0xFF],
+        )
+        .unwrap();
+        let png = colors
+            .encode_into(
+                &common::PNG {},
+                EncoderParams::new().with_color_format(ColorFormat::L8),
+            )
+            .unwrap();
+        let (MiData::GRAY(png), MiData::GRAY(l8)) = (
+            MiData::decode_from(&common::PNG {}, png).unwrap(),
+            encode(&colors, ColorFormat::L8),
+        ) else {
+            panic!("L8 decodes to GRAY")
+        };
+        assert_eq!(png, l8);
+
+        // GRAY diffs against its RGBA conversion, and finds the changed pixels
+        let rgba = MiData::RGBA(mid.to_rgba().unwrap().into_owned());
+        assert!(diff_image(&mid, &rgba).is_none());
+        let mut changed = gray.clone();
+        changed.get_pixel_mut(1, 2).0 = [0xFF, 0xFF];
+        let diff = diff_image(&mid, &MiData::GRAY(changed)).unwrap();
+        assert_eq!(diff.diff_filter(1.).count(), 1);
+    }
+
+    #[test]
+    fn high_bit_depth() {
+        use icu_lib::endecoder::utils::dither::DitherAlgorithm;
+        use icu_lib::midata::transform::{Rotation, TransformParams};
+        use icu_lib::midata::Rgba16Image;
+        use image::{DynamicImage, Rgba};
+
+        // One 8-bit level spread over 256 columns
+        let (width, height) = (256, 8);
+        let ramp = Rgba16Image::from_fn(width, height, |x, _| {
+            let level = (64 * 257 + x * 257 / 256) as u16;
+            Rgba([level, level, level, 0xFFFF])
+        });
+        let mid = MiData::RGBA16(ramp.clone());
+
+        // 16-bit PNG and TIFF keep every bit
+        let png = mid
+            .encode_into(&common::PNG {}, EncoderParams::new())
+            .unwrap();
+        assert_eq!(
+            common::PNG {}.info(&png).unwrap().other_info["Bit Depth"],
+            "Sixteen"
+        );
+        let MiData::RGBA16(decoded) = MiData::decode_from(&common::PNG {}, png).unwrap() else {
+            panic!("16-bit PNGs decode to RGBA16")
+        };
+        assert_eq!(decoded, ramp);
+
+        let tiff = mid
+            .encode_into(&common::TIFF {}, EncoderParams::new())
+            .unwrap();
+        let MiData::RGBA16(decoded) = MiData::decode_from(&common::TIFF {}, tiff).unwrap() else {
+            panic!("16-bit TIFFs decode to RGBA16")
+        };
+        assert_eq!(decoded, ramp);
+
+        let float = DynamicImage::ImageRgba16(ramp.clone()).into_rgba32f();
+        let tiff = MiData::RGBA32F(float.clone())
+            .encode_into(&common::TIFF {}, EncoderParams::new())
+            .unwrap();
+        let MiData::RGBA32F(decoded) = MiData::decode_from(&common::TIFF {}, tiff).unwrap() else {
+            panic!("float TIFFs decode to RGBA32F")
+        };
+        assert_eq!(decoded, float);
+
+        // Transforms don't round to 8 bits
+        let MiData::RGBA16(rotated) = mid
+            .clone()
+            .transform(&TransformParams::new().with_rotation(Rotation::Rotate90))
+            .unwrap()
+        else {
+            panic!("transforms keep RGBA16")
+        };
+        assert_eq!(
+            rotated.get_pixel(3, 200),
+            ramp.get_pixel(200, height - 1 - 3)
+        );
+
+        // Dithering to L8 follows the bits below 8, which an 8-bit copy has lost
+        let block_error = |mid: &MiData| {
+            let bin = mid
+                .encode_into(
+                    &lvgl::LVGL {},
+                    EncoderParams::new()
+                        .with_color_format(lvgl::ColorFormat::L8)
+                        .with_lvgl_version(lvgl::LVGLVersion::V9)
+                        .with_dither(Some(1))
+                        .with_dither_algorithm(DitherAlgorithm::FloydSteinberg),
+                )
+                .unwrap();
+            let MiData::GRAY(gray) = MiData::decode_from(&lvgl::LVGL {}, bin).unwrap() else {
+                panic!("L8 decodes to GRAY")
+            };
+            (0..width / 32)
+                .map(|block| {
+                    let sum = gray
+                        .enumerate_pixels()
+                        .filter(|(x, _, _)| x / 32 == block)
+                        .map(|(_, _, pix)| pix[0] as f64)
+                        .sum::<f64>();
+                    let ideal = 64. + (block * 32) as f64 / 256. + 15.5 / 256.;
+                    (sum / (32 * height) as f64 - ideal).abs()
+                })
+                .fold(0., f64::max)
+        };
+        assert!(block_error(&mid) < 0.1);
+        assert!(block_error(&MiData::RGBA(mid.to_rgba().unwrap().into_owned())) > 0.3);
+    }
+
+    #[test]
+    fn color_profiles() {
+        use icu_lib::endecoder::utils::color_space::ColorSpace;
+        use image::codecs::png::PngEncoder;
+        use image::{ExtendedColorType, ImageEncoder, Rgba, RgbaImage};
+
+        let pixels = RgbaImage::from_fn(4, 4, |x, _| match x {
+            0 => Rgba([200, 100, 50, 0xFF]),
+            _ => Rgba([128, 128, 128, 0xFF]),
+        });
+
+        // Display P3 ICC profile, the saturated color spreads in sRGB, the gray stays
+        let mut p3 = Vec::new();
+        let mut encoder = PngEncoder::new(&mut p3);
+        encoder
+            .set_icc_profile(moxcms::ColorProfile::new_display_p3().encode().unwrap())
+            .unwrap();
+        encoder
+            .write_image(pixels.as_raw(), 4, 4, ExtendedColorType::Rgba8)
+            .unwrap();
+
+        let info = common::AutoDetect {}.info(&p3).unwrap();
+        assert!(info.other_info["Color Profile"]
+            .as_str()
+            .unwrap()
+            .ends_with("(ICC)"));
+
+        let srgb = MiData::decode_from(&common::AutoDetect {}, p3.clone())
+            .unwrap()
+            .into_rgba()
+            .unwrap();
+        let [r, g, b, a] = srgb.get_pixel(0, 0).0;
+        assert!(r > 200 && g < 100 && b < 50 && a == 0xFF, "{r} {g} {b}");
+        assert!(srgb.get_pixel(1, 0).0[..3]
+            .iter()
+            .all(|&c| c.abs_diff(128) <= 1));
+
+        // A Display P3 panel takes the pixels as they are
+        let p3_panel = common::PNG {}
+            .decode_in(p3, ColorSpace::DisplayP3)
+            .unwrap()
+            .into_rgba()
+            .unwrap();
+        assert_eq!(p3_panel, pixels);
+
+        // Linear gAMA chunk brightens the midtones
+        let mut linear = Vec::new();
+        let mut encoder = png::Encoder::new(&mut linear, 4, 4);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_source_gamma(png::ScaledFloat::new(1.0));
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(pixels.as_raw())
+            .unwrap();
+
+        let info = common::PNG {}.info(&linear).unwrap();
+        assert_eq!(info.other_info["Color Profile"], "gamma 1.00 (gAMA)");
+        let srgb = MiData::decode_from(&common::PNG {}, linear)
+            .unwrap()
+            .into_rgba()
+            .unwrap();
+        let gray = srgb.get_pixel(1, 0).0;
+        assert!(gray[0] > 180 && gray[0] == gray[1] && gray[1] == gray[2]);
+
+        // The sample image is a Display P3 export
+        let raw = image::load_from_memory(DATA).unwrap().into_rgba8();
+        let srgb = MiData::decode_from(&common::AutoDetect {}, DATA.to_vec()).unwrap();
+        assert_ne!(srgb.into_rgba().unwrap(), raw);
+        let p3_panel = common::AutoDetect {}
+            .decode_in(DATA.to_vec(), ColorSpace::DisplayP3)
+            .unwrap();
+        assert_eq!(p3_panel.into_rgba().unwrap(), raw);
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        use icu_lib::endecoder::utils::metadata::{Metadata, MetadataKind};
+        use icu_lib::midata::{DecodeParams, Decoded};
+        use image::metadata::Orientation;
+        use image::{Rgba, RgbaImage};
+
+        // Little endian EXIF with Orientation 6, the camera was turned clockwise
+        let exif = [
+            b"II*\0".as_slice(),
+            &8u32.to_le_bytes(),
+            &1u16.to_le_bytes(),
+            &[0x12, 0x01, 3, 0],
+            &1u32.to_le_bytes(),
+            &[6, 0, 0, 0],
+            &0u32.to_le_bytes(),
+        ]
+        .concat();
+        let metadata = Metadata {
+            exif: Some(exif),
+            text: vec![("Author".to_owned(), "ICU".to_owned())],
+            dpi: Some((300., 300.)),
+        };
+
+        // Left half red, right half blue
+        let pixels = RgbaImage::from_fn(4, 2, |x, _| match x {
+            0..=1 => Rgba([0xFF, 0, 0, 0xFF]),
+            _ => Rgba([0, 0, 0xFF, 0xFF]),
+        });
+        let mid = MiData::RGBA(pixels);
+
+        let png = mid
+            .encode_into(
+                &common::PNG {},
+                EncoderParams::new().with_metadata(metadata.clone()),
+            )
+            .unwrap();
+        let read = Metadata::read(&png);
+        assert_eq!(read.exif, metadata.exif);
+        assert_eq!(read.text, metadata.text);
+        let (x, y) = read.dpi.unwrap();
+        assert!((x - 300.).abs() < 0.01 && (y - 300.).abs() < 0.01);
+
+        // The metadata travels with the decoded image into the next encoder
+        let decoded =
+            Decoded::decode_from(&common::PNG {}, png.clone(), DecodeParams::new()).unwrap();
+        assert_eq!(decoded.metadata, read);
+        let webp = decoded
+            .encode_into(&common::WEBP {}, EncoderParams::new())
+            .unwrap();
+        assert_eq!(Metadata::read(&webp).exif, metadata.exif);
+
+        // Turned upright, the red half ends up on top and the EXIF says it is upright
+        let upright = Decoded::decode_from(
+            &common::PNG {},
+            png,
+            DecodeParams::new().with_auto_orient(true),
+        )
+        .unwrap();
+        assert_eq!(
+            upright.metadata.orientation(),
+            Some(Orientation::NoTransforms)
+        );
+        assert_eq!(upright.metadata.text, metadata.text);
+        let pixels = upright.image.into_rgba().unwrap();
+        assert_eq!(pixels.dimensions(), (2, 4));
+        assert_eq!(pixels.get_pixel(0, 0), &Rgba([0xFF, 0, 0, 0xFF]));
+        assert_eq!(pixels.get_pixel(0, 3), &Rgba([0, 0, 0xFF, 0xFF]));
+
+        // JPEG and WEBP keep the EXIF, JPEG and TIFF the resolution
+        for ed in [&common::JPEG {} as &dyn EnDecoder, &common::WEBP {}] {
+            let data = mid
+                .encode_into(ed, EncoderParams::new().with_metadata(metadata.clone()))
+                .unwrap();
+            assert_eq!(
+                Metadata::read(&data).orientation(),
+                Some(Orientation::Rotate90)
+            );
+        }
+        let jpeg = mid
+            .encode_into(
+                &common::JPEG {},
+                EncoderParams::new().with_metadata(metadata.clone().keep(&[MetadataKind::Dpi])),
+            )
+            .unwrap();
+        let read = Metadata::read(&jpeg);
+        assert_eq!((read.exif, read.dpi), (None, Some((300., 300.))));
+
+        let tiff = mid
+            .encode_into(
+                &common::TIFF {},
+                EncoderParams::new().with_metadata(metadata.clone()),
+            )
+            .unwrap();
+        assert_eq!(Metadata::read(&tiff).dpi, Some((300., 300.)));
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(&tiff))
+            .unwrap();
+        let orientation = exif
+            .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+            .and_then(|field| field.value.get_uint(0));
+        assert_eq!(orientation, Some(6));
+        let artist = exif
+            .get_field(exif::Tag::Artist, exif::In::PRIMARY)
+            .unwrap();
+        assert_eq!(artist.display_value().to_string(), "\"ICU\"");
+    }
+
+    #[test]
+    fn common_encoder_options() {
+        use icu_lib::endecoder::common::options::{
+            ChromaSubsampling, PngCompression, PngFilter, TiffCompression,
+        };
+
+        let mid = MiData::decode_from(&common::PNG {}, DATA.to_vec()).unwrap();
+        let img = mid.to_rgba().unwrap().into_owned();
+        let encode =
+            |ed: &dyn EnDecoder, params: EncoderParams| mid.encode_into(ed, params).unwrap();
+        let decode = |ed: &dyn EnDecoder, data: Vec<u8>| {
+            MiData::decode_from(ed, data).unwrap().into_rgba().unwrap()
+        };
+
+        let low = encode(&common::JPEG {}, EncoderParams::new().with_quality(20));
+        let high = encode(&common::JPEG {}, EncoderParams::new().with_quality(95));
+        assert!(low.len() < high.len());
+        let subsampled = encode(
+            &common::JPEG {},
+            EncoderParams::new()
+                .with_quality(95)
+                .with_jpeg_subsampling(ChromaSubsampling::Yuv420),
+        );
+        assert!(subsampled.len() < high.len());
+
+        // Every PNG option is lossless
+        let default = encode(&common::PNG {}, EncoderParams::new());
+        let small = encode(
+            &common::PNG {},
+            EncoderParams::new()
+                .with_png_compression(PngCompression::High)
+                .with_png_filter(PngFilter::Adaptive),
+        );
+        let stored = encode(
+            &common::PNG {},
+            EncoderParams::new().with_png_compression(PngCompression::None),
+        );
+        assert!(small.len() < default.len() && default.len() < stored.len());
+        for data in [small, stored] {
+            assert_eq!(decode(&common::PNG {}, data), img);
+        }
+
+        let lossless = encode(&common::WEBP {}, EncoderParams::new());
+        assert_eq!(decode(&common::WEBP {}, lossless.clone()), img);
+        let near_lossless = |level| {
+            encode(
+                &common::WEBP {},
+                EncoderParams::new().with_webp_near_lossless(level),
+            )
+        };
+        assert_eq!(near_lossless(100), lossless);
+        assert_ne!(decode(&common::WEBP {}, near_lossless(99)), img);
+        let rounded = near_lossless(50);
+        assert!(rounded.len() < lossless.len());
+        let rounded = decode(&common::WEBP {}, rounded);
+        let max_error = rounded
+            .as_raw()
+            .iter()
+            .zip(img.as_raw())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max();
+        assert!(max_error <= Some(4));
+
+        let uncompressed = encode(&common::TIFF {}, EncoderParams::new());
+        for compression in [TiffCompression::Lzw, TiffCompression::Deflate] {
+            let data = encode(
+                &common::TIFF {},
+                EncoderParams::new().with_tiff_compression(compression),
+            );
+            assert!(data.len() < uncompressed.len());
+            assert_eq!(decode(&common::TIFF {}, data), img);
+        }
+    }
+
+    #[test]
+    fn more_common_formats() {
+        use icu_lib::endecoder::{find_endecoder, find_endecoder_with_extension};
+
+        let mid = MiData::decode_from(&common::PNG {}, DATA.to_vec()).unwrap();
+        let img = mid.to_rgba().unwrap().into_owned();
+
+        // TGA has no magic bytes, the header gives it away
+        let tga = mid
+            .encode_into(&common::TGA {}, EncoderParams::new())
+            .unwrap();
+        let ed = find_endecoder(&tga).unwrap();
+        assert_eq!(
+            MiData::decode_from(ed, tga.clone())
+                .unwrap()
+                .into_rgba()
+                .unwrap(),
+            img
+        );
+        assert!(!common::TGA {}.can_decode(DATA));
+        assert!(!common::AutoDetect {}.can_decode(&tga));
+        assert!(find_endecoder(&[0xFF; 8]).is_none());
+        assert!(find_endecoder_with_extension(&[0xFF; 8], Some("TGA")).is_some());
+
+        for ed in [&common::QOI {} as &dyn EnDecoder, &common::FARBFELD {}] {
+            let data = mid.encode_into(ed, EncoderParams::new()).unwrap();
+            assert!(ed.can_decode(&data));
+            let ed = find_endecoder(&data).unwrap();
+            assert_eq!(
+                MiData::decode_from(ed, data).unwrap().into_rgba().unwrap(),
+                img
+            );
+        }
+
+        // Linear light goes back to sRGB, only rounded; HDR shares one exponent between R, G and B
+        for (ed, tolerance) in [(&common::EXR {} as &dyn EnDecoder, 1), (&common::HDR {}, 6)] {
+            let data = mid.encode_into(ed, EncoderParams::new()).unwrap();
+            let decoded = MiData::decode_from(find_endecoder(&data).unwrap(), data)
+                .unwrap()
+                .into_rgba()
+                .unwrap();
+            let max_error = decoded
+                .pixels()
+                .zip(img.pixels())
+                .flat_map(|(a, b)| (0..3).map(move |c| a[c].abs_diff(b[c])))
+                .max();
+            assert!(max_error <= Some(tolerance), "{max_error:?}");
+        }
+
+        // A single red DXT1 block
+        let mut dds = b"DDS ".to_vec();
+        for value in [124u32, 0x1007, 4, 4, 8, 0, 0] {
+            dds.extend(value.to_le_bytes());
+        }
+        dds.extend([0; 44]);
+        for value in [32u32, 0x4] {
+            dds.extend(value.to_le_bytes());
+        }
+        dds.extend(b"DXT1");
+        dds.extend([0; 20]);
+        dds.extend(0x1000u32.to_le_bytes());
+        dds.extend([0; 16]);
+        dds.extend([0x00, 0xF8, 0x1F, 0x00, 0, 0, 0, 0]);
+        assert!(common::DDS {}.can_decode(&dds));
+        let red = MiData::decode_from(find_endecoder(&dds).unwrap(), dds)
+            .unwrap()
+            .into_rgba()
+            .unwrap();
+        assert_eq!(red.dimensions(), (4, 4));
+        assert!(red.pixels().all(|pix| pix.0 == [0xFF, 0, 0, 0xFF]));
+        assert!(mid
+            .encode_into(&common::DDS {}, EncoderParams::new())
+            .is_err());
+    }
+
+    #[test]
+    fn animation_frames() {
+        use icu_lib::endecoder::lvgl::animimg::{to_animimg_source, FrameSource};
+        use icu_lib::midata::animation::StripDirection;
+        use icu_lib::midata::Animation;
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let mut gif = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut gif);
+            // 0 ms plays at 100 ms, like in browsers
+            let frames = colors.iter().zip([20, 40, 0]).map(|(&color, delay)| {
+                Frame::from_parts(
+                    RgbaImage::from_pixel(4, 2, Rgba(color)),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(delay, 1),
+                )
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+
+        let animation = Animation::decode(&gif).unwrap().unwrap();
+        assert_eq!(animation.delays_ms(), [20, 40, 100]);
+        assert_eq!(animation.duration_ms(), 160);
+        for (frame, color) in animation.frames.iter().zip(colors) {
+            assert_eq!(frame.image.to_rgba().unwrap().get_pixel(3, 1).0, color);
+        }
+        assert!(Animation::decode(DATA).unwrap().is_none());
+        assert!(Animation::is_animated(&gif));
+        assert!(!Animation::is_animated(DATA));
+
+        let strip = animation
+            .sprite_strip(StripDirection::Horizontal)
+            .unwrap()
+            .into_rgba()
+            .unwrap();
+        assert_eq!(strip.dimensions(), (12, 2));
+        assert_eq!(strip.get_pixel(4, 0).0, colors[1]);
+        let strip = animation
+            .sprite_strip(StripDirection::Vertical)
+            .unwrap()
+            .into_rgba()
+            .unwrap();
+        assert_eq!(strip.dimensions(), (4, 6));
+        assert_eq!(strip.get_pixel(0, 5).0, colors[2]);
+
+        // Frames are listed once per 20 ms, 1 + 2 + 5 sources
+        let files = FrameSource::Files(vec![
+            "anim_000.bin".to_owned(),
+            "anim_001.bin".to_owned(),
+            "anim_002.bin".to_owned(),
+        ]);
+        let source = to_animimg_source(
+            "anim",
+            &files,
+            &animation.delays_ms(),
+            lvgl::LVGLVersion::V9,
+        )
+        .unwrap();
+        assert_eq!(source.matches("ANIM_DIR \"anim_002.bin\"").count(), 5);
+        assert!(source.contains("lv_animimg_set_src(animimg, anim_frames, 8);"));
+        assert!(source.contains("lv_animimg_set_duration(animimg, 160);"));
+
+        let descriptors = FrameSource::Descriptors(vec!["anim_000".to_owned(); 3]);
+        let source =
+            to_animimg_source("anim", &descriptors, &[10, 10, 10], lvgl::LVGLVersion::V8).unwrap();
+        assert!(source.contains("LV_IMG_DECLARE(anim_000);"));
+        assert!(source.contains("(lv_img_dsc_t **)anim_frames, 3);"));
+        assert!(to_animimg_source("anim", &descriptors, &[10], lvgl::LVGLVersion::V9).is_err());
+    }
+
+    #[test]
+    fn atlas_packing() {
+        use icu_lib::midata::{Atlas, AtlasParams};
+        use image::{Rgba, RgbaImage};
+
+        let sizes = [(16, 16), (24, 8), (5, 30), (16, 16), (3, 3)];
+        let images = sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &(width, height))| {
+                let color = Rgba([index as u8 * 40, 255, 0, 255]);
+                (
+                    format!("icon {index}"),
+                    MiData::RGBA(RgbaImage::from_pixel(width, height, color)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let params = AtlasParams::new().with_padding(2).with_align(8);
+        let atlas = Atlas::pack(&images, &params).unwrap();
+        let pixels = atlas.image.to_rgba().unwrap();
+        assert_eq!(pixels.dimensions(), (atlas.width(), atlas.height()));
+
+        for (index, sprite) in atlas.sprites.iter().enumerate() {
+            assert_eq!((sprite.width, sprite.height), sizes[index]);
+            assert_eq!(sprite.x % 8, 0);
+            assert_eq!(
+                *pixels.get_pixel(sprite.x + sprite.width - 1, sprite.y),
+                *images[index].1.to_rgba().unwrap().get_pixel(0, 0)
+            );
+            // Sprites keep the padding between each other
+            for other in &atlas.sprites[..index] {
+                assert!(
+                    sprite.x >= other.x + other.width + 2
+                        || other.x >= sprite.x + sprite.width + 2
+                        || sprite.y >= other.y + other.height + 2
+                        || other.y >= sprite.y + sprite.height + 2
+                );
+            }
+        }
+
+        let atlas = Atlas::pack(&images, &AtlasParams::new().with_max_width(24)).unwrap();
+        assert!(atlas.width() <= 24);
+        assert!(Atlas::pack(&images, &AtlasParams::new().with_max_width(20)).is_err());
+        let mut twins = images.clone();
+        twins[1].0 = twins[0].0.clone();
+        assert!(Atlas::pack(&twins, &AtlasParams::new()).is_err());
+        // Names differing only in case or punctuation clash in the header
+        twins[0].0 = "Icon-a".to_owned();
+        twins[1].0 = "icon_A".to_owned();
+        assert!(Atlas::pack(&twins, &AtlasParams::new()).is_err());
+        twins[1].0 = "count".to_owned();
+        assert!(Atlas::pack(&twins, &AtlasParams::new()).is_err());
+
+        let data = atlas
+            .image
+            .encode_into(
+                &lvgl::LVGL {},
+                EncoderParams::new()
+                    .with_color_format(lvgl::ColorFormat::ARGB8888)
+                    .with_lvgl_version(lvgl::LVGLVersion::V9),
+            )
+            .unwrap();
+        let decoded = MiData::decode_from(&lvgl::LVGL {}, data).unwrap();
+        assert_eq!(
+            decoded.to_rgba().unwrap().dimensions(),
+            (atlas.width(), atlas.height())
+        );
+
+        let header = lvgl::atlas::to_atlas_header("ui icons", &atlas);
+        let sprite = &atlas.sprites[2];
+        assert!(header.contains(&format!(
+            "[UI_ICONS_ICON_2] = {{ {}, {}, 5, 30 }},",
+            sprite.x, sprite.y
+        )));
+        assert!(header.contains("ui_icons_rects[UI_ICONS_COUNT]"));
+        let json: serde_json::Value = serde_json::from_str(&atlas.to_json("ui_icons.bin")).unwrap();
+        assert_eq!(json["sprites"][2]["name"], "icon 2");
+        assert_eq!(json["width"], atlas.width());
+    }
+
+    #[test]
+    fn diff_quality_metrics() {
+        use icu_lib::endecoder::utils::diff::metrics::ciede2000;
+        use icu_lib::endecoder::utils::diff::{color_diff_f32, quality_metrics};
+        use image::Rgba;
+
+        // Pairs of the CIEDE2000 test data of Sharma, Wu and Dalal
+        let pairs = [
+            ([50., 2.6772, -79.7751], [50., 0., -82.7485], 2.0425),
+            ([50., -1.3802, -84.2814], [50., 0., -82.7485], 1.0000),
+            ([50., 2.5, 0.], [56., -27., -3.], 31.9030),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+            (
+                [22.7233, 20.0904, -46.694],
+                [23.0331, 14.973, -42.5619],
+                2.0373,
+            ),
+        ];
+        for (lab1, lab2, expected) in pairs {
+            assert!((ciede2000(lab1, lab2) - expected).abs() < 1e-4);
+            assert!((ciede2000(lab2, lab1) - expected).abs() < 1e-4);
+        }
+
+        let mid = MiData::decode_from(&common::PNG {}, DATA.to_vec()).unwrap();
+        let metrics = quality_metrics(&mid, &mid).unwrap();
+        assert_eq!(metrics.mse, [0.; 4]);
+        assert!(metrics.psnr_rgb.is_infinite());
+        assert!((metrics.ssim - 1.).abs() < 1e-9 && (metrics.ms_ssim - 1.).abs() < 1e-9);
+        assert_eq!(metrics.delta_e.max, 0.);
+
+        // Red raised by 10 wherever it can be
+        let mut img = mid.to_rgba().unwrap().into_owned();
+        img.pixels_mut()
+            .filter(|pixel| pixel[0] <= 245)
+            .for_each(|pixel| pixel[0] += 10);
+        let metrics = quality_metrics(&mid, &MiData::RGBA(img)).unwrap();
+        assert!(metrics.mse[0] > 0. && metrics.mse[0] <= 100.);
+        assert_eq!(metrics.mse[1..], [0.; 3]);
+        assert!(metrics.psnr[0] >= 28.1 && metrics.psnr[1].is_infinite());
+        assert!(metrics.ssim < 1. && metrics.ssim > 0.9);
+        assert!(metrics.ms_ssim < 1. && metrics.ms_ssim > 0.9);
+        let delta_e = &metrics.delta_e;
+        assert!(delta_e.mean > 0.);
+        assert!(delta_e.p50 <= delta_e.p95 && delta_e.p95 <= delta_e.p99);
+        assert!(delta_e.p99 <= delta_e.max);
+
+        // Differences hidden by transparency don't count for the colors
+        let clear = |r| MiData::RGBA(image::RgbaImage::from_pixel(16, 16, Rgba([r, 0, 0, 0])));
+        let metrics = quality_metrics(&clear(0), &clear(255)).unwrap();
+        assert!(metrics.mse[0] > 0.);
+        assert_eq!(metrics.delta_e.max, 0.);
+        assert_eq!(metrics.ssim, 1.);
+
+        let small = MiData::RGBA(image::RgbaImage::new(4, 4));
+        assert!(quality_metrics(&mid, &small).is_none());
+
+        let (c1, c2) = (Rgba([0u8, 10, 200, 255]), Rgba([0u8, 0, 100, 255]));
+        assert_eq!(color_diff_f32(&c1, &c2), 100. / 255.);
+    }
+
+    #[test]
+    fn diff_offsets() {
+        use icu_lib::endecoder::utils::diff::{
+            diff_image, diff_image_with, find_offset, quality_metrics_with, DiffParams, DiffRegion,
+        };
+        use icu_lib::midata::transform::{Rect, TransformParams};
+
+        let mid = MiData::decode_from(&common::PNG {}, DATA.to_vec()).unwrap();
+        let crop = Rect {
+            x: 3,
+            y: 2,
+            width: 270,
+            height: 280,
+        };
+        let cropped = mid
+            .clone()
+            .transform(&TransformParams::new().with_crop(crop))
+            .unwrap();
+
+        assert_eq!(find_offset(&mid, &cropped), Some((3, 2)));
+        assert_eq!(find_offset(&cropped, &mid), Some((-3, -2)));
+
+        // Lined up, the overlap is equal
+        let params = DiffParams::new().with_offset(3, 2);
+        assert!(diff_image_with(&mid, &cropped, &params).is_none());
+        let metrics = quality_metrics_with(&mid, &cropped, &params).unwrap();
+        assert!(metrics.psnr_rgb.is_infinite());
+
+        // Images of different sizes are compared where they overlap
+        let diff = diff_image(&mid, &cropped).unwrap();
+        assert_eq!(diff.size(), (270, 280));
+        assert_eq!(diff.origin(), (0, 0));
+        assert!(diff.max_diff() > 0.);
+
+        // The union also compares what only one image has with transparent
+        let params = DiffParams::new()
+            .with_offset(-1, 10)
+            .with_region(DiffRegion::Union);
+        let diff = diff_image_with(&mid, &cropped, &params).unwrap();
+        assert_eq!(diff.size(), (286, 290));
+        assert_eq!(diff.origin(), (-1, 0));
+        let params = DiffParams::new().with_offset(-1, 10);
+        let diff = diff_image_with(&mid, &cropped, &params).unwrap();
+        assert_eq!(diff.size(), (269, 275));
+        assert_eq!(diff.origin(), (0, 10));
+
+        let params = DiffParams::new().with_offset(285, 0);
+        assert!(diff_image_with(&mid, &cropped, &params).is_none());
+    }
+
+    #[test]
+    fn diff_clusters() {
+        use icu_lib::endecoder::utils::diff::diff_image;
+        use image::Rgba;
+
+        let reference = image::RgbaImage::from_pixel(64, 48, Rgba([40, 80, 120, 255]));
+        let mut image = reference.clone();
+        // A 10x5 block
+        for y in 4..9 {
+            for x in 20..30 {
+                image.put_pixel(x, y, Rgba([40, 80, 180, 255]));
+            }
+        }
+        // Two pixels touching diagonally
+        image.put_pixel(50, 40, Rgba([60, 80, 120, 255]));
+        image.put_pixel(51, 41, Rgba([40, 90, 120, 255]));
+        // A faint pixel under the tolerance
+        image.put_pixel(2, 2, Rgba([41, 80, 120, 255]));
+
+        let diff = diff_image(&MiData::RGBA(reference), &MiData::RGBA(image)).unwrap();
+        let clusters = diff.clusters(5.);
+        assert_eq!(clusters.len(), 2);
+
+        let block = &clusters[0];
+        assert_eq!(
+            (block.x, block.y, block.width, block.height),
+            (20, 4, 10, 5)
+        );
+        assert_eq!(block.pixel_count, 50);
+        assert_eq!((block.max_diff, block.mean_diff), (60., 60.));
+
+        let pair = &clusters[1];
+        assert_eq!((pair.x, pair.y, pair.width, pair.height), (50, 40, 2, 2));
+        assert_eq!(pair.pixel_count, 2);
+        assert_eq!((pair.max_diff, pair.mean_diff), (20., 15.));
+
+        assert_eq!(diff.clusters(0.5).len(), 3);
+        assert!(diff.clusters(100.).is_empty());
+    }
 }
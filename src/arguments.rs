@@ -1,10 +1,16 @@
 use clap::error::ErrorKind;
 use clap::{Command, CommandFactory, Parser, Subcommand};
+use icu_lib::EncoderParams;
+use icu_lib::endecoder::lvgl::{ColorFormat, LVGLV8};
+use icu_lib::endecoder::raw::RawImage;
+use icu_lib::midata::transform::{Rect, TransformParams};
 use std::io;
 
 pub use crate::converter::{
-    ImageFormatCategory, ImageFormats, LVGL_Version, OutputColorFormats, OutputCompressedMethod,
-    OutputFileFormatCategory,
+    AnimationOutput, AtlasTable, ChromaSubsampling, ColorSpace, DiffRegion, DitherAlgorithm,
+    ImageFormatCategory, ImageFormats, LVGL_ColorDepth, LVGL_Version, MetadataKind,
+    OutputColorFormats, OutputCompressedMethod, OutputFileFormatCategory, PaletteFormat,
+    PngCompression, PngFilter, Quantizer, ResizeFilter, Rotation, TiffCompression,
 };
 
 #[derive(Parser, Debug)]
@@ -37,6 +43,12 @@ pub(crate) enum SubCommands {
         /// input image formats
         #[arg(short = 'f', long, value_enum, default_value = "auto")]
         input_format: ImageFormatCategory,
+
+        #[command(flatten)]
+        raw_geometry: RawGeometry,
+
+        #[command(flatten)]
+        lvgl_v8_color: LvglV8Color,
     },
 
     /// Show an image file
@@ -48,6 +60,12 @@ pub(crate) enum SubCommands {
         /// input image formats
         #[arg(short = 'f', long, value_enum, default_value = "auto")]
         input_format: ImageFormatCategory,
+
+        #[command(flatten)]
+        raw_geometry: RawGeometry,
+
+        #[command(flatten)]
+        lvgl_v8_color: LvglV8Color,
     },
 
     /// Convert image files to any other image format including LVGL image formats.
@@ -60,6 +78,12 @@ pub(crate) enum SubCommands {
         #[arg(short = 'f', long, value_enum, default_value = "auto")]
         input_format: ImageFormatCategory,
 
+        #[command(flatten)]
+        raw_geometry: RawGeometry,
+
+        #[command(flatten)]
+        lvgl_v8_color: LvglV8Color,
+
         /// output folder
         #[arg(short = 'O', long, value_hint = clap::ValueHint::DirPath)]
         output_folder: Option<String>,
@@ -91,18 +115,384 @@ pub(crate) enum SubCommands {
         #[arg(long)]
         stdout: bool,
 
-        /// dither the output image so that it will look better on screens with low color depth
-        /// 1 to 30, 1 is the best quality and 30 is the worst quality.
+        /// dither the output image against the color depth of the output color format,
+        /// e.g. the 5/6/5 bits of RGB565, the alpha levels of A1/A2/A4 or the palette of indexed formats.
+        /// The value is the NeuQuant sampling from 1 to 30, 1 is the best quality and 30 is the worst quality.
         /// 10 is recommended.
-        #[arg(long)]
+        #[arg(long, num_args = 0..=1, default_missing_value = "10")]
         dither: Option<u32>,
 
+        /// dithering algorithm, needs [--dither]
+        #[arg(long, value_enum, default_value = "floyd-steinberg")]
+        dither_algorithm: DitherAlgorithm,
+
+        /// share of the quantization error that gets dithered, 0.0 to 1.0, needs [--dither]
+        #[arg(long, default_value = "1.0")]
+        dither_strength: f32,
+
+        /// palette quantizer of the indexed color formats,
+        /// images whose colors fit in the palette always keep their exact colors
+        #[arg(long, value_enum, default_value = "neu-quant")]
+        quantizer: Quantizer,
+
+        /// fixed palette of the indexed color formats, a GIMP `.gpl`, Adobe `.act`, JASC `.pal` or PNG swatch file.
+        /// Pixels map to the nearest palette entry instead of being quantized
+        #[arg(long, value_hint = clap::ValueHint::FilePath)]
+        palette: Option<String>,
+
+        /// color space of the panel, common input images are converted to it
+        /// from their embedded ICC profile or PNG gamma, or from sRGB if they have none
+        #[arg(long, value_enum, default_value = "srgb")]
+        color_space: ColorSpace,
+
+        /// turn photos upright as their EXIF orientation says, before the other transforms
+        #[arg(long)]
+        auto_orient: bool,
+
+        /// metadata of the input image written to PNG, JPEG, WEBP and TIFF output,
+        /// e.g. `--keep-metadata exif,dpi`
+        #[arg(long, value_enum, value_delimiter = ',')]
+        keep_metadata: Vec<MetadataKind>,
+
+        /// what to write for animated GIF, APNG and WEBP input.
+        /// `frames` writes `<name>_000.<ext>` and so on, plus `<name>_animimg.c` for LVGL output
+        #[arg(long, value_enum, default_value = "first")]
+        animation: AnimationOutput,
+
+        /// premultiply RGB by alpha and set the PREMULTIPLIED flag,
+        /// only for LVGL V9 ARGB8888, RGB565A8, ARGB8565 and indexed color formats
+        #[arg(long)]
+        premultiply: bool,
+
         /// LVGL Version, needed if [`ImageFormats`] is [`ImageFormats::LVGL`]
         #[arg(long, value_enum, default_value = "v9")]
         lvgl_version: LVGL_Version,
+
+        #[command(flatten)]
+        encoder_options: EncoderOptions,
+
+        #[command(flatten)]
+        transform: Transform,
+    },
+
+    /// Compare an image with a reference and show quality metrics: MSE, PSNR, SSIM, MS-SSIM and CIEDE2000
+    Diff {
+        /// the reference image, e.g. the source of a conversion
+        #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+        reference: String,
+
+        /// the image to judge, e.g. the converted image
+        #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+        image: String,
+
+        /// position of the image on the reference, e.g. `2,0` if it lost 2 columns on the left
+        #[arg(long, value_name = "X,Y", default_value = "0,0", value_parser = parse_offset, allow_hyphen_values = true)]
+        offset: (i32, i32),
+
+        /// search the offset that lines the images up best, instead of [--offset]
+        #[arg(long, conflicts_with = "offset")]
+        auto_offset: bool,
+
+        /// pixels that are compared
+        #[arg(long, value_enum, default_value = "overlap")]
+        region: DiffRegion,
+
+        /// input image formats
+        #[arg(short = 'f', long, value_enum, default_value = "auto")]
+        input_format: ImageFormatCategory,
+
+        #[command(flatten)]
+        raw_geometry: RawGeometry,
+
+        #[command(flatten)]
+        lvgl_v8_color: LvglV8Color,
+    },
+
+    /// Pack many small images into one LVGL image with a table of their sub-rectangles
+    Atlas {
+        /// input files, every sprite is named after its file stem
+        #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+        input_files: Vec<String>,
+
+        /// output LVGL image, the sprite table is written next to it
+        #[arg(short = 'o', long, required = true, value_hint = clap::ValueHint::FilePath)]
+        output: String,
+
+        /// override the output files if they exist
+        #[arg(short = 'r', long)]
+        override_output: bool,
+
+        /// output image format categories
+        #[arg(short = 'G', long, value_enum, default_value = "bin")]
+        output_category: OutputFileFormatCategory,
+
+        /// output color formats
+        #[arg(short = 'C', long, value_enum)]
+        output_color_format: OutputColorFormats,
+
+        /// stride of the output image
+        #[arg(short = 'S', long, default_value = "1")]
+        output_stride_align: u32,
+
+        #[arg(long, value_enum)]
+        output_compressed_method: Option<OutputCompressedMethod>,
+
+        /// dither the atlas against the output color format, see [convert --dither]
+        #[arg(long, num_args = 0..=1, default_missing_value = "10")]
+        dither: Option<u32>,
+
+        /// color space of the panel, see [convert --color-space]
+        #[arg(long, value_enum, default_value = "srgb")]
+        color_space: ColorSpace,
+
+        /// LVGL Version
+        #[arg(long, value_enum, default_value = "v9")]
+        lvgl_version: LVGL_Version,
+
+        #[command(flatten)]
+        lvgl_v8_color: LvglV8Color,
+
+        /// transparent pixels between the sprites
+        #[arg(long, default_value = "0")]
+        padding: u32,
+
+        /// the left edge of every sprite is a multiple of it, e.g. 8 to start I1 sprites on a byte
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+        align: u32,
+
+        /// width of the atlas, about square if not given
+        #[arg(long)]
+        max_width: Option<u32>,
+
+        /// format of the sprite table
+        #[arg(long, value_enum, default_value = "c-header")]
+        table: AtlasTable,
+    },
+
+    /// Work with the palettes of indexed images
+    Palette {
+        #[command(subcommand)]
+        command: PaletteCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum PaletteCommands {
+    /// Dump the palette of an indexed LVGL image or PNG
+    Extract {
+        /// an indexed LVGL image or PNG
+        #[arg(required = true, value_hint = clap::ValueHint::FilePath)]
+        file: String,
+
+        /// output palette file, the palette is written to stdout if not given
+        #[arg(short = 'o', long, value_hint = clap::ValueHint::FilePath)]
+        output: Option<String>,
+
+        /// palette file format, guessed from the output file extension if not given
+        #[arg(short = 'F', long, value_enum)]
+        palette_format: Option<PaletteFormat>,
     },
 }
 
+/// Geometry of headerless raw pixel data, needed if the input format is `raw`
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub(crate) struct RawGeometry {
+    /// width of the raw input image in pixels
+    #[arg(long)]
+    pub(crate) raw_width: Option<u32>,
+
+    /// height of the raw input image in pixels
+    #[arg(long)]
+    pub(crate) raw_height: Option<u32>,
+
+    /// color format of the raw input image
+    #[arg(long, value_enum)]
+    pub(crate) raw_color_format: Option<OutputColorFormats>,
+
+    /// bytes per row of the raw input image, 0 means tightly packed rows
+    #[arg(long, default_value = "0")]
+    pub(crate) raw_stride: u32,
+
+    /// bytes to skip before the first pixel of the raw input image
+    #[arg(long, default_value = "0")]
+    pub(crate) raw_offset: usize,
+}
+
+impl RawGeometry {
+    pub(crate) fn raw_image(&self) -> Option<RawImage> {
+        Some(
+            RawImage::new(
+                self.raw_width?,
+                self.raw_height?,
+                self.raw_color_format?.into(),
+            )
+            .with_stride(self.raw_stride)
+            .with_offset(self.raw_offset),
+        )
+    }
+}
+
+/// Color settings of an LVGL V8 project, used to decode `lvgl_v8` input and to write V8 images
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub(crate) struct LvglV8Color {
+    /// `LV_COLOR_DEPTH` of the LVGL V8 project, decides the layout of the true color formats
+    #[arg(long, value_enum, default_value = "32")]
+    pub(crate) lvgl_color_depth: LVGL_ColorDepth,
+
+    /// `LV_COLOR_16_SWAP` of the LVGL V8 project, swaps the bytes of 16 bit colors
+    #[arg(long)]
+    pub(crate) lvgl_color_16_swap: bool,
+}
+
+impl LvglV8Color {
+    pub(crate) fn lvgl_v8(&self) -> LVGLV8 {
+        LVGLV8 {
+            color_depth: self.lvgl_color_depth.into(),
+            color_16_swap: self.lvgl_color_16_swap,
+        }
+    }
+}
+
+/// Options of the common image encoders, ignored by the other output formats
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub(crate) struct EncoderOptions {
+    /// JPEG quality from 1 to 100
+    #[arg(long, default_value = "75", value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub(crate) quality: u8,
+
+    /// chroma subsampling of JPEG output, `420` is the smallest
+    #[arg(long, value_enum, default_value = "444")]
+    pub(crate) jpeg_subsampling: ChromaSubsampling,
+
+    /// deflate level of PNG output
+    #[arg(long, value_enum, default_value = "balanced")]
+    pub(crate) png_compression: PngCompression,
+
+    /// row filter of PNG output, `adaptive` usually makes photos smaller
+    #[arg(long, value_enum, default_value = "none")]
+    pub(crate) png_filter: PngFilter,
+
+    /// near-lossless level of WEBP output from 0 to 100, rounds the colors before the lossless
+    /// encoding, 1 bit from 80 to 99 up to 5 bits below 20, smaller files that are no longer
+    /// pixel exact. 100 is lossless
+    #[arg(long, default_value = "100", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub(crate) webp_near_lossless: u8,
+
+    /// compression of TIFF output
+    #[arg(long, value_enum, default_value = "none")]
+    pub(crate) tiff_compression: TiffCompression,
+}
+
+impl EncoderOptions {
+    pub(crate) fn apply(&self, params: EncoderParams) -> EncoderParams {
+        params
+            .with_quality(self.quality)
+            .with_jpeg_subsampling(self.jpeg_subsampling.into())
+            .with_png_compression(self.png_compression.into())
+            .with_png_filter(self.png_filter.into())
+            .with_webp_near_lossless(self.webp_near_lossless)
+            .with_tiff_compression(self.tiff_compression.into())
+    }
+}
+
+/// Transforms applied to every image before encoding, in the order crop, resize, rotate, flip and pad
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub(crate) struct Transform {
+    /// crop the image to WIDTHxHEIGHT+X+Y
+    #[arg(long, value_name = "WxH+X+Y", value_parser = parse_rect)]
+    pub(crate) crop: Option<Rect>,
+
+    /// resize the image to WIDTHxHEIGHT, a 0 side keeps the aspect ratio, e.g. 320x0
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub(crate) resize: Option<(u32, u32)>,
+
+    /// filter used to resize the image
+    #[arg(long, value_enum, default_value = "lanczos3")]
+    pub(crate) resize_filter: ResizeFilter,
+
+    /// rotate the image clockwise by degrees
+    #[arg(long, value_enum, default_value = "0")]
+    pub(crate) rotate: Rotation,
+
+    /// mirror the image left to right
+    #[arg(long)]
+    pub(crate) flip_horizontal: bool,
+
+    /// mirror the image top to bottom
+    #[arg(long)]
+    pub(crate) flip_vertical: bool,
+
+    /// center the image on a WIDTHxHEIGHT canvas
+    #[arg(long, value_name = "WxH", value_parser = parse_size)]
+    pub(crate) pad: Option<(u32, u32)>,
+
+    /// color of the padded area as RRGGBBAA hex
+    #[arg(long, value_name = "RRGGBBAA", default_value = "00000000", value_parser = parse_color)]
+    pub(crate) pad_color: [u8; 4],
+}
+
+impl Transform {
+    pub(crate) fn params(&self) -> TransformParams {
+        let mut params = TransformParams::new()
+            .with_resize_filter(self.resize_filter.into())
+            .with_flip_horizontal(self.flip_horizontal)
+            .with_flip_vertical(self.flip_vertical)
+            .with_pad_color(self.pad_color);
+        params.crop = self.crop;
+        params.resize = self.resize;
+        params.rotation = self.rotate.into();
+        params.pad = self.pad;
+        params
+    }
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or("expected WIDTHxHEIGHT, e.g. 320x240")?;
+    Ok((
+        width.trim().parse().map_err(|e| format!("width: {e}"))?,
+        height.trim().parse().map_err(|e| format!("height: {e}"))?,
+    ))
+}
+
+fn parse_offset(value: &str) -> Result<(i32, i32), String> {
+    let (x, y) = value.split_once(',').ok_or("expected X,Y, e.g. 2,-1")?;
+    Ok((
+        x.trim().parse().map_err(|e| format!("x: {e}"))?,
+        y.trim().parse().map_err(|e| format!("y: {e}"))?,
+    ))
+}
+
+fn parse_rect(value: &str) -> Result<Rect, String> {
+    let mut parts = value.splitn(3, '+');
+    let (width, height) = parse_size(parts.next().unwrap_or_default())?;
+    let mut offset = || -> Result<u32, String> {
+        parts
+            .next()
+            .unwrap_or("0")
+            .trim()
+            .parse()
+            .map_err(|e| format!("offset: {e}"))
+    };
+    Ok(Rect {
+        x: offset()?,
+        y: offset()?,
+        width,
+        height,
+    })
+}
+
+fn parse_color(value: &str) -> Result<[u8; 4], String> {
+    let hex = value.trim_start_matches('#');
+    let color = u32::from_str_radix(hex, 16).map_err(|e| format!("color: {e}"))?;
+    match hex.len() {
+        6 => Ok((color << 8 | 0xFF).to_be_bytes()),
+        8 => Ok(color.to_be_bytes()),
+        _ => Err("expected RRGGBB or RRGGBBAA hex".to_owned()),
+    }
+}
+
 pub fn parse_args() -> Args {
     let mut command = Args::command();
     let args = Args::parse();
@@ -117,31 +507,100 @@ pub fn parse_args() -> Args {
     }
 
     if let Some(sub_commands) = &args.commands {
+        let raw_input = match sub_commands {
+            SubCommands::Info {
+                input_format,
+                raw_geometry,
+                ..
+            }
+            | SubCommands::Show {
+                input_format,
+                raw_geometry,
+                ..
+            }
+            | SubCommands::Convert {
+                input_format,
+                raw_geometry,
+                ..
+            }
+            | SubCommands::Diff {
+                input_format,
+                raw_geometry,
+                ..
+            } => Some((input_format, raw_geometry)),
+            SubCommands::Atlas { .. } | SubCommands::Palette { .. } => None,
+        };
+
+        if let Some((ImageFormatCategory::Raw, raw_geometry)) = raw_input
+            && raw_geometry.raw_image().is_none()
+        {
+            let error = command.error(
+                ErrorKind::MissingRequiredArgument,
+                "Raw input format needs the image geometry. \
+                 Please specify it using the [--raw-width], [--raw-height] and [--raw-color-format] options.",
+            );
+
+            error.exit();
+        }
+
         match sub_commands {
-            SubCommands::Show { .. } | SubCommands::Info { .. } => {}
+            SubCommands::Show { .. }
+            | SubCommands::Info { .. }
+            | SubCommands::Diff { .. }
+            | SubCommands::Palette { .. } => {}
+            SubCommands::Atlas {
+                output_color_format,
+                dither,
+                lvgl_version,
+                ..
+            } => {
+                check_lvgl_color_format(&mut command, *output_color_format, *lvgl_version);
+                check_dither(&mut command, *dither);
+            }
             SubCommands::Convert {
+                output_category,
                 output_format,
                 output_color_format,
                 dither,
+                dither_strength,
+                lvgl_version,
                 ..
             } => {
-                if output_format == &ImageFormats::LVGL && output_color_format.is_none() {
+                if output_category == &OutputFileFormatCategory::C_Array
+                    && output_format != &ImageFormats::LVGL
+                {
+                    let error = command.error(
+                        ErrorKind::ArgumentConflict,
+                        "C_Array output category is only available for LVGL image format. \
+                 Please specify it using the [-F --output-format lvgl] option.",
+                    );
+
+                    error.exit();
+                }
+
+                if matches!(output_format, ImageFormats::LVGL | ImageFormats::RAW)
+                    && output_color_format.is_none()
+                {
                     let error = command.error(
                         ErrorKind::MissingRequiredArgument,
-                        "Output color format is required for LVGL image format. \
+                        "Output color format is required for LVGL and RAW image formats. \
                  Please specify it using the [-C --output-color-format] option.",
                     );
 
                     error.exit();
                 }
-                if let Some(dither) = *dither {
-                    if !(1..=30).contains(&dither) {
-                        let error = command.error(
-                            ErrorKind::InvalidValue,
-                            "Dither value must be between 1 and 30.",
-                        );
-                        error.exit();
-                    }
+                if let (ImageFormats::LVGL, Some(color_format)) =
+                    (output_format, output_color_format)
+                {
+                    check_lvgl_color_format(&mut command, *color_format, *lvgl_version);
+                }
+                check_dither(&mut command, *dither);
+                if !(0.0..=1.0).contains(dither_strength) {
+                    let error = command.error(
+                        ErrorKind::InvalidValue,
+                        "Dither strength must be between 0.0 and 1.0.",
+                    );
+                    error.exit();
                 }
             }
         }
@@ -152,3 +611,43 @@ pub fn parse_args() -> Args {
 
     args
 }
+
+/// Exit if LVGL images of `lvgl_version` can't hold `color_format`
+fn check_lvgl_color_format(
+    command: &mut Command,
+    color_format: OutputColorFormats,
+    lvgl_version: LVGL_Version,
+) {
+    let color_format: ColorFormat = color_format.into();
+    let true_color = matches!(
+        color_format,
+        ColorFormat::TrueColor | ColorFormat::TrueColorAlpha | ColorFormat::TrueColorChromaKeyed
+    );
+    let supported = match lvgl_version {
+        LVGL_Version::V8 => color_format.v8_code().is_some(),
+        LVGL_Version::V9 => !true_color,
+    };
+    if !supported {
+        let error = command.error(
+            ErrorKind::ArgumentConflict,
+            format!(
+                "{color_format:?} color format is not available in LVGL {lvgl_version:?} images. \
+                 True color formats are only for V8, please check the [--lvgl-version] option."
+            ),
+        );
+
+        error.exit();
+    }
+}
+
+fn check_dither(command: &mut Command, dither: Option<u32>) {
+    if let Some(dither) = dither {
+        if !(1..=30).contains(&dither) {
+            let error = command.error(
+                ErrorKind::InvalidValue,
+                "Dither value must be between 1 and 30.",
+            );
+            error.exit();
+        }
+    }
+}
@@ -1,11 +1,15 @@
-use crate::arguments::{SubCommands, parse_args};
+use crate::arguments::{LvglV8Color, PaletteCommands, RawGeometry, SubCommands, parse_args};
 use crate::converter::{
-    ImageFormatCategory, ImageFormats, OutputFileFormatCategory,
+    AnimationOutput, AtlasTable, ImageFormatCategory, ImageFormats, OutputFileFormatCategory,
 };
 use crate::image_viewer::show_image;
 use eframe::egui::DroppedFile;
-use icu_lib::endecoder::{EnDecoder, common, find_endecoder, lvgl};
-use icu_lib::midata::MiData;
+use icu_lib::endecoder::lvgl::animimg::{FrameSource, to_animimg_source};
+use icu_lib::endecoder::utils::diff;
+use icu_lib::endecoder::utils::palette;
+use icu_lib::endecoder::{EnDecoder, common, find_endecoder_with_extension, lvgl};
+use icu_lib::midata::animation::StripDirection;
+use icu_lib::midata::{Animation, Atlas, AtlasParams, DecodeParams, Decoded};
 use icu_lib::{EncoderParams, endecoder};
 use std::fs;
 use std::io::Write;
@@ -28,9 +32,20 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
     let commands = args.commands.ok_or("No subcommand provided")?;
 
     match &commands {
-        SubCommands::Info { file, input_format } => {
+        SubCommands::Info {
+            file,
+            input_format,
+            raw_geometry,
+            lvgl_v8_color,
+        } => {
             let data = fs::read(file)?;
-            let info = get_info_with(data, *input_format)?;
+            let info = get_info_with(
+                data,
+                extension(Path::new(file)),
+                *input_format,
+                raw_geometry,
+                lvgl_v8_color,
+            )?;
 
             let yaml = serde_yaml::to_string(&info)?;
 
@@ -38,7 +53,9 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
         }
         SubCommands::Show {
             files,
-            input_format: _image_format,
+            input_format,
+            raw_geometry,
+            lvgl_v8_color,
         } => {
             let files = match files {
                 None => {
@@ -53,11 +70,16 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
                     .collect::<Vec<DroppedFile>>(),
             };
 
-            show_image(files);
+            show_image(
+                files,
+                get_endecoder_with(*input_format, raw_geometry, lvgl_v8_color),
+            );
         }
         SubCommands::Convert {
             input_files,
             input_format,
+            raw_geometry,
+            lvgl_v8_color,
             output_folder,
             override_output,
             output_category,
@@ -67,11 +89,27 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
             output_compressed_method,
             stdout,
             dither,
+            dither_algorithm,
+            dither_strength,
+            quantizer,
+            palette,
+            color_space,
+            auto_orient,
+            keep_metadata,
+            animation,
+            premultiply,
             lvgl_version,
+            encoder_options,
+            transform,
         } => {
             // calculate converting time
             let total_start_time = std::time::Instant::now();
 
+            let palette = match palette {
+                Some(path) => Some(palette::read_palette(&fs::read(path)?)?),
+                None => None,
+            };
+
             let mut user_duration = 0.0;
             let mut converted_files = 0;
 
@@ -98,9 +136,17 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
                 let output_file_path =
                     deal_path_without_extension(file_path, &input_folder, output_folder.clone())
                         .unwrap_or_default()
-                        .with_extension(output_format.get_file_extension());
-
-                let output_file_exists = output_file_path.exists();
+                        .with_extension(match output_category {
+                            OutputFileFormatCategory::C_Array => "c",
+                            _ => output_format.get_file_extension(),
+                        });
+
+                // Frames of an animation are written next to `output_file_path` and checked
+                // one by one in `write_frames`, a stale still image mustn't block them
+                let per_frame = matches!(animation, AnimationOutput::Frames)
+                    && !*stdout
+                    && fs::read(file_path).is_ok_and(|data| Animation::is_animated(&data));
+                let output_file_exists = output_file_path.exists() && !per_frame;
                 let should_convert = !output_file_exists || *override_output || *stdout;
 
                 if should_convert {
@@ -121,9 +167,14 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
 
                 if should_convert {
                     if let Err(e) = (|| -> Result<(), Box<dyn std::error::Error>> {
-                        let params = EncoderParams::new()
+                        let mut params = encoder_options
+                            .apply(EncoderParams::new())
                             .with_stride_align(*output_stride_align)
                             .with_dither(*dither)
+                            .with_dither_algorithm((*dither_algorithm).into())
+                            .with_dither_strength(*dither_strength)
+                            .with_quantizer((*quantizer).into())
+                            .with_premultiply(*premultiply)
                             .with_color_format(
                                 (*output_color_format).map(|f| f.into()).unwrap_or_default(),
                             )
@@ -132,24 +183,84 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
                                     .map(|t| t.into())
                                     .unwrap_or_default(),
                             )
-                            .with_lvgl_version((*lvgl_version).into());
+                            .with_lvgl_version((*lvgl_version).into())
+                            .with_color_depth(lvgl_v8_color.lvgl_color_depth.into())
+                            .with_color_16_swap(lvgl_v8_color.lvgl_color_16_swap);
+                        params.palette = palette.clone();
 
                         let data = fs::read(file_path)?;
+                        let decode_params = DecodeParams::new()
+                            .with_color_space((*color_space).into())
+                            .with_auto_orient(*auto_orient);
+                        let kinds = keep_metadata
+                            .iter()
+                            .map(|&kind| kind.into())
+                            .collect::<Vec<_>>();
+
                         let ed = output_format.get_endecoder();
-                        let mid = decode_with(data, *input_format)?;
-                        let data = mid.encode_into(ed, params);
-
-                        match output_category {
-                            OutputFileFormatCategory::Common | OutputFileFormatCategory::Bin => {
-                                if *stdout {
-                                    std::io::stdout().write_all(&data)?;
-                                } else {
-                                    fs::write(&output_file_path, data)?;
+                        let frames = match animation {
+                            AnimationOutput::First => None,
+                            _ => Animation::decode_with(&data, decode_params)?,
+                        };
+                        let decoded = match frames {
+                            Some(mut frames) => {
+                                frames.metadata = frames.metadata.keep(&kinds);
+                                let frames = frames.transform(&transform.params())?;
+                                log::info!(
+                                    "<{}> has {} frames, {} ms per loop",
+                                    file_path.to_string_lossy(),
+                                    frames.frames.len(),
+                                    frames.duration_ms()
+                                );
+                                match animation {
+                                    AnimationOutput::Strip => Decoded {
+                                        image: frames.sprite_strip(StripDirection::Horizontal)?,
+                                        metadata: frames.metadata,
+                                    },
+                                    AnimationOutput::StripVertical => Decoded {
+                                        image: frames.sprite_strip(StripDirection::Vertical)?,
+                                        metadata: frames.metadata,
+                                    },
+                                    _ if *stdout => {
+                                        return Err(
+                                            "the frames of an animation can't be written to stdout"
+                                                .into(),
+                                        );
+                                    }
+                                    _ => {
+                                        return write_frames(
+                                            &frames,
+                                            &params,
+                                            *output_format,
+                                            *output_category,
+                                            &output_file_path,
+                                            lvgl_v8_color,
+                                            *override_output,
+                                        );
+                                    }
                                 }
                             }
-                            OutputFileFormatCategory::C_Array => {
-                                return Err("C_Array output format is not supported yet".into());
+                            None => {
+                                let mut decoded = decode_with(
+                                    data,
+                                    extension(file_path),
+                                    *input_format,
+                                    raw_geometry,
+                                    lvgl_v8_color,
+                                    decode_params,
+                                )?;
+                                decoded.metadata = decoded.metadata.keep(&kinds);
+                                decoded.transform(&transform.params())?
                             }
+                        };
+                        let data = decoded.encode_into(ed, params)?;
+                        let data =
+                            output_data(data, *output_category, &output_file_path, lvgl_v8_color)?;
+
+                        if *stdout {
+                            std::io::stdout().write_all(&data)?;
+                        } else {
+                            fs::write(&output_file_path, data)?;
                         }
                         Ok(())
                     })() {
@@ -165,14 +276,17 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
                 let end_time = std::time::Instant::now();
                 let duration = end_time - start_time;
                 user_duration += duration.as_secs_f64();
-                let output_format_str = if output_format == &ImageFormats::LVGL {
-                    format!(
+                let output_format_str = match output_format {
+                    ImageFormats::LVGL => format!(
                         "LVGL.{:?}({:?})",
                         lvgl_version,
                         (*output_color_format).unwrap() // safe to unwrap because it's required
-                    )
-                } else {
-                    format!("{output_format:?}")
+                    ),
+                    ImageFormats::RAW => format!(
+                        "RAW({:?})",
+                        (*output_color_format).unwrap() // safe to unwrap because it's required
+                    ),
+                    _ => format!("{output_format:?}"),
                 };
                 log::info!(
                     "took {:.6}s for converting <{}> to <{}> with format <{}>",
@@ -209,6 +323,165 @@ pub fn process() -> Result<(), Box<dyn std::error::Error>> {
                 duration.as_secs_f64() - user_duration
             );
         }
+        SubCommands::Diff {
+            reference,
+            image,
+            offset,
+            auto_offset,
+            region,
+            input_format,
+            raw_geometry,
+            lvgl_v8_color,
+        } => {
+            let decode = |file: &String| {
+                decode_with(
+                    fs::read(file)?,
+                    extension(Path::new(file)),
+                    *input_format,
+                    raw_geometry,
+                    lvgl_v8_color,
+                    DecodeParams::new(),
+                )
+                .map(|decoded| decoded.image)
+            };
+            let (reference_mid, image_mid) = (decode(reference)?, decode(image)?);
+            let offset = match auto_offset {
+                true => diff::find_offset(&reference_mid, &image_mid)
+                    .ok_or("Can't line up empty images")?,
+                false => *offset,
+            };
+            let params = diff::DiffParams::new()
+                .with_offset(offset.0, offset.1)
+                .with_region((*region).into());
+            let metrics = diff::quality_metrics_with(&reference_mid, &image_mid, &params)
+                .ok_or_else(|| {
+                    format!("<{image}> doesn't overlap <{reference}> at offset {offset:?}")
+                })?;
+
+            let yaml = serde_yaml::to_string(&DiffReport { offset, metrics })?;
+
+            println!("{yaml}");
+        }
+        SubCommands::Atlas {
+            input_files,
+            output,
+            override_output,
+            output_category,
+            output_color_format,
+            output_stride_align,
+            output_compressed_method,
+            dither,
+            color_space,
+            lvgl_version,
+            lvgl_v8_color,
+            padding,
+            align,
+            max_width,
+            table,
+        } => {
+            let output_path = Path::new(output);
+            let stem = output_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let table_path = output_path.with_file_name(match table {
+                AtlasTable::CHeader => format!("{stem}_atlas.h"),
+                AtlasTable::Json => format!("{stem}_atlas.json"),
+            });
+            for path in [output_path, &table_path] {
+                if path.exists() && !*override_output {
+                    return Err(format!(
+                        "Output file <{}> already exists, use [-r] to override it",
+                        path.to_string_lossy()
+                    )
+                    .into());
+                }
+            }
+
+            let images = input_files
+                .iter()
+                .map(|file| {
+                    let path = Path::new(file);
+                    let data = fs::read(path)?;
+                    let ed = find_endecoder_with_extension(&data, extension(path))
+                        .ok_or_else(|| format!("No supported endecoder found for <{file}>"))?;
+                    let name = path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned();
+                    Ok((name, ed.decode_in(data, (*color_space).into())?))
+                })
+                .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+
+            let mut params = AtlasParams::new().with_padding(*padding).with_align(*align);
+            if let Some(max_width) = max_width {
+                params = params.with_max_width(*max_width);
+            }
+            let atlas = Atlas::pack(&images, &params)?;
+
+            let params = EncoderParams::new()
+                .with_color_format((*output_color_format).into())
+                .with_stride_align(*output_stride_align)
+                .with_dither(*dither)
+                .with_compress(
+                    (*output_compressed_method)
+                        .map(|t| t.into())
+                        .unwrap_or_default(),
+                )
+                .with_lvgl_version((*lvgl_version).into())
+                .with_color_depth(lvgl_v8_color.lvgl_color_depth.into())
+                .with_color_16_swap(lvgl_v8_color.lvgl_color_16_swap);
+            let data = atlas.image.encode_into(&lvgl::LVGL {}, params)?;
+            fs::write(
+                output_path,
+                output_data(data, *output_category, output_path, lvgl_v8_color)?,
+            )?;
+
+            let table_data = match table {
+                AtlasTable::CHeader => lvgl::atlas::to_atlas_header(&stem, &atlas),
+                AtlasTable::Json => atlas.to_json(
+                    &output_path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy(),
+                ),
+            };
+            fs::write(&table_path, table_data)?;
+            log::info!(
+                "Packed {} images into a {}x{} atlas <{output}>",
+                atlas.sprites.len(),
+                atlas.width(),
+                atlas.height()
+            );
+        }
+        SubCommands::Palette { command } => match command {
+            PaletteCommands::Extract {
+                file,
+                output,
+                palette_format,
+            } => {
+                let colors = palette::extract_palette(&fs::read(file)?)?;
+                let format = palette_format
+                    .map(|format| format.into())
+                    .or_else(|| {
+                        output
+                            .as_ref()
+                            .and_then(|output| Path::new(output).extension())
+                            .and_then(|ext| {
+                                palette::PaletteFormat::from_extension(&ext.to_string_lossy())
+                            })
+                    })
+                    .unwrap_or_default();
+                let data = palette::write_palette(&colors, format)?;
+
+                match output {
+                    Some(output) => fs::write(output, data)?,
+                    None => std::io::stdout().write_all(&data)?,
+                }
+                log::info!("Extracted {} colors from <{file}>", colors.len());
+            }
+        },
     }
 
     Ok(())
@@ -298,30 +571,160 @@ fn deal_path_without_extension(
     Ok(output_file_path)
 }
 
+fn get_endecoder_with(
+    input_format: ImageFormatCategory,
+    raw_geometry: &RawGeometry,
+    lvgl_v8_color: &LvglV8Color,
+) -> Option<Box<dyn EnDecoder>> {
+    match input_format {
+        ImageFormatCategory::Auto => None,
+        ImageFormatCategory::Common => Some(Box::new(common::AutoDetect {})),
+        ImageFormatCategory::LVGL_V9 => Some(Box::new(lvgl::LVGL {})),
+        ImageFormatCategory::LVGL_V8 => Some(Box::new(lvgl_v8_color.lvgl_v8())),
+        ImageFormatCategory::C_Array => Some(Box::new(lvgl::LVGLCArray {})),
+        ImageFormatCategory::Raw => raw_geometry
+            .raw_image()
+            .map(|raw_image| Box::new(raw_image) as Box<dyn EnDecoder>),
+    }
+}
+
 fn decode_with(
     data: Vec<u8>,
+    extension: Option<&str>,
     input_format: ImageFormatCategory,
-) -> Result<MiData, Box<dyn std::error::Error>> {
-    match input_format {
-        ImageFormatCategory::Auto => {
-            let ed = find_endecoder(&data);
-            Ok(ed.ok_or("No supported endecoder found")?.decode(data))
+    raw_geometry: &RawGeometry,
+    lvgl_v8_color: &LvglV8Color,
+    params: DecodeParams,
+) -> Result<Decoded, Box<dyn std::error::Error>> {
+    match get_endecoder_with(input_format, raw_geometry, lvgl_v8_color) {
+        Some(ed) => Ok(Decoded::decode_from(ed.as_ref(), data, params)?),
+        None => {
+            let ed = find_endecoder_with_extension(&data, extension)
+                .ok_or("No supported endecoder found")?;
+            Ok(Decoded::decode_from(ed, data, params)?)
         }
-        ImageFormatCategory::Common => Ok(MiData::decode_from(&common::AutoDetect {}, data)),
-        ImageFormatCategory::LVGL_V9 => Ok(MiData::decode_from(&lvgl::LVGL {}, data)),
     }
 }
 
 fn get_info_with(
     data: Vec<u8>,
+    extension: Option<&str>,
     input_format: ImageFormatCategory,
+    raw_geometry: &RawGeometry,
+    lvgl_v8_color: &LvglV8Color,
 ) -> Result<endecoder::ImageInfo, Box<dyn std::error::Error>> {
-    match input_format {
-        ImageFormatCategory::Auto => {
-            let ed = find_endecoder(&data);
-            Ok(ed.ok_or("No endecoder found")?.info(&data))
+    match get_endecoder_with(input_format, raw_geometry, lvgl_v8_color) {
+        Some(ed) => Ok(ed.info(&data)?),
+        None => {
+            let ed = find_endecoder_with_extension(&data, extension);
+            Ok(ed.ok_or("No endecoder found")?.info(&data)?)
+        }
+    }
+}
+
+/// Output of `icu diff`
+#[derive(serde::Serialize)]
+struct DiffReport {
+    /// Position of the image on the reference
+    offset: (i32, i32),
+    #[serde(flatten)]
+    metrics: diff::QualityMetrics,
+}
+
+/// The encoded image as written to `output_file_path`, wrapped in a C array for the C array category
+fn output_data(
+    data: Vec<u8>,
+    output_category: OutputFileFormatCategory,
+    output_file_path: &Path,
+    lvgl_v8_color: &LvglV8Color,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match output_category {
+        OutputFileFormatCategory::Common | OutputFileFormatCategory::Bin => Ok(data),
+        OutputFileFormatCategory::C_Array => {
+            let name = output_file_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let c_array = lvgl::c_array::to_c_array_with(&data, &name, &lvgl_v8_color.lvgl_v8())?;
+            Ok(c_array.into_bytes())
+        }
+    }
+}
+
+/// One output file per frame, named after `output_file_path` with the frame index,
+/// plus an `lv_animimg` source that plays them for LVGL output
+fn write_frames(
+    animation: &Animation,
+    params: &EncoderParams,
+    output_format: ImageFormats,
+    output_category: OutputFileFormatCategory,
+    output_file_path: &Path,
+    lvgl_v8_color: &LvglV8Color,
+    override_output: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stem = output_file_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let ext = output_file_path
+        .extension()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let frame_names = (0..animation.frames.len())
+        .map(|index| format!("{stem}_{index:03}"))
+        .collect::<Vec<_>>();
+    let frame_paths = frame_names
+        .iter()
+        .map(|name| output_file_path.with_file_name(format!("{name}.{ext}")))
+        .collect::<Vec<_>>();
+    let animimg_path = (output_format == ImageFormats::LVGL)
+        .then(|| output_file_path.with_file_name(format!("{stem}_animimg.c")));
+
+    for path in frame_paths.iter().chain(&animimg_path) {
+        if path.exists() {
+            if !override_output {
+                return Err(format!(
+                    "Output file <{}> already exists, use [-r] to override it",
+                    path.to_string_lossy()
+                )
+                .into());
+            }
+            log::warn!("Override output file <{}>", path.to_string_lossy());
         }
-        ImageFormatCategory::Common => Ok(common::AutoDetect {}.info(&data)),
-        ImageFormatCategory::LVGL_V9 => Ok(lvgl::LVGL {}.info(&data)),
     }
+
+    let params = params.clone().with_metadata(animation.metadata.clone());
+    let mut names = Vec::new();
+    for ((frame, name), path) in animation.frames.iter().zip(frame_names).zip(&frame_paths) {
+        let data = frame
+            .image
+            .encode_into(output_format.get_endecoder(), params.clone())?;
+        fs::write(
+            path,
+            output_data(data, output_category, path, lvgl_v8_color)?,
+        )?;
+        names.push(match output_category {
+            OutputFileFormatCategory::C_Array => name,
+            _ => path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+        });
+    }
+
+    if let Some(animimg_path) = animimg_path {
+        let frames = match output_category {
+            OutputFileFormatCategory::C_Array => FrameSource::Descriptors(names),
+            _ => FrameSource::Files(names),
+        };
+        let source =
+            to_animimg_source(&stem, &frames, &animation.delays_ms(), params.lvgl_version)?;
+        fs::write(animimg_path, source)?;
+    }
+    Ok(())
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|extension| extension.to_str())
 }
@@ -14,6 +14,15 @@ pub enum ImageFormatCategory {
 
     /// LVGL image formats like: RGB565, RGB888, ARGB8888, etc.
     LVGL_V9,
+
+    /// LVGL V8 image formats, the true color layout is given by the `--lvgl-color-*` options.
+    LVGL_V8,
+
+    /// LVGL C array sources with `lv_image_dsc_t` or `lv_img_dsc_t` descriptors.
+    C_Array,
+
+    /// Headerless raw pixel data, the geometry is given by the `--raw-*` options.
+    Raw,
 }
 
 #[allow(non_camel_case_types)]
@@ -36,6 +45,176 @@ pub enum LVGL_Version {
     V8,
 }
 
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Serialize, Deserialize)]
+pub enum LVGL_ColorDepth {
+    #[value(name = "8")]
+    Depth8,
+    #[value(name = "16")]
+    Depth16,
+    #[value(name = "32")]
+    Depth32,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum Quantizer {
+    /// Only the exact colors, fails if the image has more colors than the palette
+    Exact,
+    /// NeuQuant neural network, sampled with the `--dither` value
+    #[default]
+    NeuQuant,
+    MedianCut,
+    Octree,
+    KMeans,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum DitherAlgorithm {
+    /// Error diffusion to 4 neighbors, the smoothest result
+    #[default]
+    FloydSteinberg,
+    /// Error diffusion to 6 neighbors, keeps more contrast
+    Atkinson,
+    /// Ordered 8x8 Bayer matrix, a stable pattern
+    Bayer,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum PaletteFormat {
+    /// GIMP palette
+    #[default]
+    Gpl,
+    /// Adobe color table
+    Act,
+    /// JASC (Paint Shop Pro) palette
+    Pal,
+    /// Indexed PNG swatch with one pixel per color
+    Png,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum ColorSpace {
+    /// The color space of most panels
+    #[default]
+    Srgb,
+    DisplayP3,
+    AdobeRgb,
+    Bt2020,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Serialize, Deserialize)]
+pub enum MetadataKind {
+    Exif,
+    /// PNG text chunks
+    Text,
+    Dpi,
+}
+
+/// Resolution of the JPEG chroma planes against the luma plane
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum ChromaSubsampling {
+    /// Full resolution chroma
+    #[default]
+    #[value(name = "444")]
+    Yuv444,
+    /// Half the horizontal chroma resolution
+    #[value(name = "422")]
+    Yuv422,
+    /// Half the horizontal and vertical chroma resolution, the smallest
+    #[value(name = "420")]
+    Yuv420,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum PngCompression {
+    /// Stored deflate blocks, the fastest and the largest
+    None,
+    Fastest,
+    Fast,
+    #[default]
+    Balanced,
+    /// The smallest and the slowest
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum PngFilter {
+    #[default]
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Picks the best filter for every row, usually the smallest for photos
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum TiffCompression {
+    #[default]
+    None,
+    Lzw,
+    Deflate,
+    /// Run length encoding, only pays off for flat images
+    PackBits,
+}
+
+/// Which pixels of two images placed on each other are compared
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum DiffRegion {
+    /// Only where both images have pixels
+    #[default]
+    Overlap,
+    /// All pixels of both images, the ones only one image has are compared with transparent
+    Union,
+}
+
+/// Format of the sprite table of an atlas
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum AtlasTable {
+    /// `<name>_atlas.h` with an enum of the sprites and their rectangles
+    #[default]
+    CHeader,
+    /// `<name>_atlas.json`
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum AnimationOutput {
+    /// Only the first frame, like a still image
+    #[default]
+    First,
+    /// One output file per frame, LVGL output also gets an `lv_animimg` source
+    Frames,
+    /// All frames side by side in one image
+    Strip,
+    /// All frames stacked in one image
+    StripVertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+#[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Default, Serialize, Deserialize)]
+pub enum Rotation {
+    #[default]
+    #[value(name = "0")]
+    Rotate0,
+    #[value(name = "90")]
+    Rotate90,
+    #[value(name = "180")]
+    Rotate180,
+    #[value(name = "270")]
+    Rotate270,
+}
+
 #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Copy, PartialOrd, PartialEq, ValueEnum, Serialize, Deserialize)]
 pub enum ImageFormats {
@@ -51,9 +230,20 @@ pub enum ImageFormats {
     PGM,
     PPM,
     PAM,
+    TGA,
+    QOI,
+    /// Radiance RGBE, written as linear light
+    HDR,
+    /// OpenEXR, written as linear light
+    EXR,
+    /// 16-bit RGBA farbfeld
+    FARBFELD,
 
     /// LVGL image formats
     LVGL,
+
+    /// Headerless raw pixel data in one of the LVGL color formats
+    RAW,
 }
 
 #[allow(non_camel_case_types)]
@@ -74,6 +264,25 @@ pub enum OutputColorFormats {
     I2,
     I4,
     I8,
+    RGB565Swapped,
+    ARGB8565,
+    AL88,
+    ARGB1555,
+    ARGB4444,
+    ARGB2222,
+    L16,
+    A16,
+    I420,
+    I422,
+    I444,
+    NV12,
+    NV21,
+    YUY2,
+    UYVY,
+    // LVGL V8 true color formats, laid out by the `--lvgl-color-*` options
+    TrueColor,
+    TrueColorAlpha,
+    TrueColorChromaKeyed,
 }
 
 #[allow(non_camel_case_types)]
@@ -98,7 +307,22 @@ impl ImageFormats {
             ImageFormats::PGM => &icu_lib::endecoder::common::PGM {} as &dyn EnDecoder,
             ImageFormats::PPM => &icu_lib::endecoder::common::PPM {} as &dyn EnDecoder,
             ImageFormats::PAM => &icu_lib::endecoder::common::PAM {} as &dyn EnDecoder,
+            ImageFormats::TGA => &icu_lib::endecoder::common::TGA {} as &dyn EnDecoder,
+            ImageFormats::QOI => &icu_lib::endecoder::common::QOI {} as &dyn EnDecoder,
+            ImageFormats::HDR => &icu_lib::endecoder::common::HDR {} as &dyn EnDecoder,
+            ImageFormats::EXR => &icu_lib::endecoder::common::EXR {} as &dyn EnDecoder,
+            ImageFormats::FARBFELD => &icu_lib::endecoder::common::FARBFELD {} as &dyn EnDecoder,
             ImageFormats::LVGL => &icu_lib::endecoder::lvgl::LVGL {} as &dyn EnDecoder,
+            ImageFormats::RAW => {
+                // The color format to write comes from the encoder params
+                const RAW: icu_lib::endecoder::raw::RawImage =
+                    icu_lib::endecoder::raw::RawImage::new(
+                        0,
+                        0,
+                        icu_lib::endecoder::lvgl::ColorFormat::UNKNOWN,
+                    );
+                &RAW as &dyn EnDecoder
+            }
         }
     }
 
@@ -115,7 +339,13 @@ impl ImageFormats {
             ImageFormats::PGM => "pgm",
             ImageFormats::PPM => "ppm",
             ImageFormats::PAM => "pam",
+            ImageFormats::TGA => "tga",
+            ImageFormats::QOI => "qoi",
+            ImageFormats::HDR => "hdr",
+            ImageFormats::EXR => "exr",
+            ImageFormats::FARBFELD => "ff",
             ImageFormats::LVGL => "bin",
+            ImageFormats::RAW => "raw",
         }
     }
 }
@@ -129,6 +359,164 @@ impl From<LVGL_Version> for icu_lib::endecoder::lvgl::LVGLVersion {
     }
 }
 
+impl From<Quantizer> for icu_lib::endecoder::utils::quantize::Quantizer {
+    fn from(quantizer: Quantizer) -> Self {
+        match quantizer {
+            Quantizer::Exact => icu_lib::endecoder::utils::quantize::Quantizer::Exact,
+            Quantizer::NeuQuant => icu_lib::endecoder::utils::quantize::Quantizer::NeuQuant,
+            Quantizer::MedianCut => icu_lib::endecoder::utils::quantize::Quantizer::MedianCut,
+            Quantizer::Octree => icu_lib::endecoder::utils::quantize::Quantizer::Octree,
+            Quantizer::KMeans => icu_lib::endecoder::utils::quantize::Quantizer::KMeans,
+        }
+    }
+}
+
+impl From<DitherAlgorithm> for icu_lib::endecoder::utils::dither::DitherAlgorithm {
+    fn from(algorithm: DitherAlgorithm) -> Self {
+        match algorithm {
+            DitherAlgorithm::FloydSteinberg => {
+                icu_lib::endecoder::utils::dither::DitherAlgorithm::FloydSteinberg
+            }
+            DitherAlgorithm::Atkinson => {
+                icu_lib::endecoder::utils::dither::DitherAlgorithm::Atkinson
+            }
+            DitherAlgorithm::Bayer => icu_lib::endecoder::utils::dither::DitherAlgorithm::Bayer,
+        }
+    }
+}
+
+impl From<DiffRegion> for icu_lib::endecoder::utils::diff::DiffRegion {
+    fn from(region: DiffRegion) -> Self {
+        match region {
+            DiffRegion::Overlap => icu_lib::endecoder::utils::diff::DiffRegion::Overlap,
+            DiffRegion::Union => icu_lib::endecoder::utils::diff::DiffRegion::Union,
+        }
+    }
+}
+
+impl From<ColorSpace> for icu_lib::endecoder::utils::color_space::ColorSpace {
+    fn from(color_space: ColorSpace) -> Self {
+        match color_space {
+            ColorSpace::Srgb => icu_lib::endecoder::utils::color_space::ColorSpace::Srgb,
+            ColorSpace::DisplayP3 => icu_lib::endecoder::utils::color_space::ColorSpace::DisplayP3,
+            ColorSpace::AdobeRgb => icu_lib::endecoder::utils::color_space::ColorSpace::AdobeRgb,
+            ColorSpace::Bt2020 => icu_lib::endecoder::utils::color_space::ColorSpace::Bt2020,
+        }
+    }
+}
+
+impl From<ChromaSubsampling> for icu_lib::endecoder::common::options::ChromaSubsampling {
+    fn from(subsampling: ChromaSubsampling) -> Self {
+        match subsampling {
+            ChromaSubsampling::Yuv444 => {
+                icu_lib::endecoder::common::options::ChromaSubsampling::Yuv444
+            }
+            ChromaSubsampling::Yuv422 => {
+                icu_lib::endecoder::common::options::ChromaSubsampling::Yuv422
+            }
+            ChromaSubsampling::Yuv420 => {
+                icu_lib::endecoder::common::options::ChromaSubsampling::Yuv420
+            }
+        }
+    }
+}
+
+impl From<PngCompression> for icu_lib::endecoder::common::options::PngCompression {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::None => icu_lib::endecoder::common::options::PngCompression::None,
+            PngCompression::Fastest => icu_lib::endecoder::common::options::PngCompression::Fastest,
+            PngCompression::Fast => icu_lib::endecoder::common::options::PngCompression::Fast,
+            PngCompression::Balanced => {
+                icu_lib::endecoder::common::options::PngCompression::Balanced
+            }
+            PngCompression::High => icu_lib::endecoder::common::options::PngCompression::High,
+        }
+    }
+}
+
+impl From<PngFilter> for icu_lib::endecoder::common::options::PngFilter {
+    fn from(filter: PngFilter) -> Self {
+        match filter {
+            PngFilter::None => icu_lib::endecoder::common::options::PngFilter::None,
+            PngFilter::Sub => icu_lib::endecoder::common::options::PngFilter::Sub,
+            PngFilter::Up => icu_lib::endecoder::common::options::PngFilter::Up,
+            PngFilter::Avg => icu_lib::endecoder::common::options::PngFilter::Avg,
+            PngFilter::Paeth => icu_lib::endecoder::common::options::PngFilter::Paeth,
+            PngFilter::Adaptive => icu_lib::endecoder::common::options::PngFilter::Adaptive,
+        }
+    }
+}
+
+impl From<TiffCompression> for icu_lib::endecoder::common::options::TiffCompression {
+    fn from(compression: TiffCompression) -> Self {
+        match compression {
+            TiffCompression::None => icu_lib::endecoder::common::options::TiffCompression::None,
+            TiffCompression::Lzw => icu_lib::endecoder::common::options::TiffCompression::Lzw,
+            TiffCompression::Deflate => {
+                icu_lib::endecoder::common::options::TiffCompression::Deflate
+            }
+            TiffCompression::PackBits => {
+                icu_lib::endecoder::common::options::TiffCompression::PackBits
+            }
+        }
+    }
+}
+
+impl From<MetadataKind> for icu_lib::endecoder::utils::metadata::MetadataKind {
+    fn from(kind: MetadataKind) -> Self {
+        match kind {
+            MetadataKind::Exif => icu_lib::endecoder::utils::metadata::MetadataKind::Exif,
+            MetadataKind::Text => icu_lib::endecoder::utils::metadata::MetadataKind::Text,
+            MetadataKind::Dpi => icu_lib::endecoder::utils::metadata::MetadataKind::Dpi,
+        }
+    }
+}
+
+impl From<PaletteFormat> for icu_lib::endecoder::utils::palette::PaletteFormat {
+    fn from(format: PaletteFormat) -> Self {
+        match format {
+            PaletteFormat::Gpl => icu_lib::endecoder::utils::palette::PaletteFormat::Gpl,
+            PaletteFormat::Act => icu_lib::endecoder::utils::palette::PaletteFormat::Act,
+            PaletteFormat::Pal => icu_lib::endecoder::utils::palette::PaletteFormat::Pal,
+            PaletteFormat::Png => icu_lib::endecoder::utils::palette::PaletteFormat::Png,
+        }
+    }
+}
+
+impl From<ResizeFilter> for icu_lib::midata::transform::ResizeFilter {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => icu_lib::midata::transform::ResizeFilter::Nearest,
+            ResizeFilter::Triangle => icu_lib::midata::transform::ResizeFilter::Triangle,
+            ResizeFilter::CatmullRom => icu_lib::midata::transform::ResizeFilter::CatmullRom,
+            ResizeFilter::Gaussian => icu_lib::midata::transform::ResizeFilter::Gaussian,
+            ResizeFilter::Lanczos3 => icu_lib::midata::transform::ResizeFilter::Lanczos3,
+        }
+    }
+}
+
+impl From<Rotation> for Option<icu_lib::midata::transform::Rotation> {
+    fn from(rotation: Rotation) -> Self {
+        match rotation {
+            Rotation::Rotate0 => None,
+            Rotation::Rotate90 => Some(icu_lib::midata::transform::Rotation::Rotate90),
+            Rotation::Rotate180 => Some(icu_lib::midata::transform::Rotation::Rotate180),
+            Rotation::Rotate270 => Some(icu_lib::midata::transform::Rotation::Rotate270),
+        }
+    }
+}
+
+impl From<LVGL_ColorDepth> for icu_lib::endecoder::lvgl::ColorDepth {
+    fn from(depth: LVGL_ColorDepth) -> Self {
+        match depth {
+            LVGL_ColorDepth::Depth8 => icu_lib::endecoder::lvgl::ColorDepth::Depth8,
+            LVGL_ColorDepth::Depth16 => icu_lib::endecoder::lvgl::ColorDepth::Depth16,
+            LVGL_ColorDepth::Depth32 => icu_lib::endecoder::lvgl::ColorDepth::Depth32,
+        }
+    }
+}
+
 impl From<OutputColorFormats> for icu_lib::endecoder::lvgl::ColorFormat {
     fn from(format: OutputColorFormats) -> Self {
         match format {
@@ -146,6 +534,30 @@ impl From<OutputColorFormats> for icu_lib::endecoder::lvgl::ColorFormat {
             OutputColorFormats::I2 => icu_lib::endecoder::lvgl::ColorFormat::I2,
             OutputColorFormats::I4 => icu_lib::endecoder::lvgl::ColorFormat::I4,
             OutputColorFormats::I8 => icu_lib::endecoder::lvgl::ColorFormat::I8,
+            OutputColorFormats::RGB565Swapped => {
+                icu_lib::endecoder::lvgl::ColorFormat::RGB565Swapped
+            }
+            OutputColorFormats::ARGB8565 => icu_lib::endecoder::lvgl::ColorFormat::ARGB8565,
+            OutputColorFormats::AL88 => icu_lib::endecoder::lvgl::ColorFormat::AL88,
+            OutputColorFormats::ARGB1555 => icu_lib::endecoder::lvgl::ColorFormat::ARGB1555,
+            OutputColorFormats::ARGB4444 => icu_lib::endecoder::lvgl::ColorFormat::ARGB4444,
+            OutputColorFormats::ARGB2222 => icu_lib::endecoder::lvgl::ColorFormat::ARGB2222,
+            OutputColorFormats::L16 => icu_lib::endecoder::lvgl::ColorFormat::L16,
+            OutputColorFormats::A16 => icu_lib::endecoder::lvgl::ColorFormat::A16,
+            OutputColorFormats::I420 => icu_lib::endecoder::lvgl::ColorFormat::I420,
+            OutputColorFormats::I422 => icu_lib::endecoder::lvgl::ColorFormat::I422,
+            OutputColorFormats::I444 => icu_lib::endecoder::lvgl::ColorFormat::I444,
+            OutputColorFormats::NV12 => icu_lib::endecoder::lvgl::ColorFormat::NV12,
+            OutputColorFormats::NV21 => icu_lib::endecoder::lvgl::ColorFormat::NV21,
+            OutputColorFormats::YUY2 => icu_lib::endecoder::lvgl::ColorFormat::YUY2,
+            OutputColorFormats::UYVY => icu_lib::endecoder::lvgl::ColorFormat::UYVY,
+            OutputColorFormats::TrueColor => icu_lib::endecoder::lvgl::ColorFormat::TrueColor,
+            OutputColorFormats::TrueColorAlpha => {
+                icu_lib::endecoder::lvgl::ColorFormat::TrueColorAlpha
+            }
+            OutputColorFormats::TrueColorChromaKeyed => {
+                icu_lib::endecoder::lvgl::ColorFormat::TrueColorChromaKeyed
+            }
         }
     }
 }
@@ -159,5 +571,3 @@ impl From<OutputCompressedMethod> for icu_lib::endecoder::lvgl::Compress {
         }
     }
 }
-
-
@@ -1,31 +1,39 @@
-use crate::image_viewer::model::ViewerState;
+use crate::image_viewer::model::{AppContext, ViewerState};
 use crate::image_viewer::ui;
 use crate::image_viewer::utils::process_images;
 use crate::utils;
 use eframe::egui;
 use eframe::egui::{Color32, DroppedFile};
+use icu_lib::endecoder::EnDecoder;
+use icu_lib::endecoder::utils::diff::DiffParams;
 
 pub struct MyEguiApp {
     state: ViewerState,
 }
 
 impl MyEguiApp {
-    pub fn new(cc: &eframe::CreationContext<'_>, files: Vec<DroppedFile>) -> Self {
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        files: Vec<DroppedFile>,
+        endecoder: Option<Box<dyn EnDecoder>>,
+    ) -> Self {
         log::info!(
             "Starting Egui App with system language: {}",
             crate::image_viewer::utils::get_system_locale()
         );
+        let context: AppContext = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
         let mut state = ViewerState {
-            image_items: process_images(&files),
-            context: cc
-                .storage
-                .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
-                .unwrap_or_default(),
+            image_items: process_images(&files, endecoder.as_deref(), context.auto_orient),
+            context,
             ..Default::default()
         };
 
         if let Some(first) = state.image_items.first() {
             state.current_image = Some(first.clone());
+            state.playback = Default::default();
             state.selected_image_item_index = Some(0);
         }
         rust_i18n::set_locale(&state.context.language);
@@ -46,15 +54,45 @@ impl MyEguiApp {
 
     fn reset_state(state: &mut ViewerState) {
         state.current_image = None;
+        state.playback = Default::default();
         state.selected_image_item_index = None;
         state.hovered_image_item_index = None;
         state.diff_image1_index = None;
         state.diff_image2_index = None;
         state.diff_result = None;
+        state.diff_metrics = None;
+        state.diff_clusters = None;
+        state.selected_diff_cluster = None;
+        state.diff_offset = (0, 0);
         state.selected_diff_pixel = None;
         state.hovered_diff_pixel = None;
     }
 
+    /// Advance the frames of an animated image, returns whether it is playing
+    fn play_animation(ctx: &egui::Context, state: &mut ViewerState) -> bool {
+        let Some(image) = &mut state.current_image else {
+            return false;
+        };
+        if image.frames.is_empty() {
+            return false;
+        }
+
+        let playback = &mut state.playback;
+        if playback.playing {
+            playback.elapsed += ctx.input(|i| i.stable_dt);
+            loop {
+                let delay = image.frames[playback.frame].delay_ms.max(1) as f32 / 1000.0;
+                if playback.elapsed < delay {
+                    break;
+                }
+                playback.elapsed -= delay;
+                playback.frame = (playback.frame + 1) % image.frames.len();
+            }
+        }
+        image.show_frame(playback.frame);
+        playback.playing
+    }
+
     fn ui_file_drag_and_drop(&mut self, ctx: &egui::Context) {
         use std::fmt::Write as _;
 
@@ -98,9 +136,11 @@ impl MyEguiApp {
 
         // Show dropped files (if any):
         if !self.state.dropped_files.is_empty() {
-            self.state
-                .image_items
-                .append(&mut process_images(&self.state.dropped_files));
+            self.state.image_items.append(&mut process_images(
+                &self.state.dropped_files,
+                None,
+                self.state.context.auto_orient,
+            ));
 
             if self.state.image_items.len() == 1 {
                 self.state.context.show_convert_panel = true;
@@ -108,6 +148,7 @@ impl MyEguiApp {
 
             if let Some(image) = self.state.image_items.first() {
                 self.state.current_image = Some(image.clone());
+                self.state.playback = Default::default();
                 self.state.selected_image_item_index = Some(0);
             }
             self.state.dropped_files.clear();
@@ -141,9 +182,13 @@ impl eframe::App for MyEguiApp {
         {
             let img1 = &self.state.image_items[i1];
             let img2 = &self.state.image_items[i2];
+            let params = DiffParams::new()
+                .with_offset(self.state.diff_offset.0, self.state.diff_offset.1)
+                .with_region(self.state.context.diff_region.into());
             let diff_result = utils::diff_image(
                 img1,
                 img2,
+                &params,
                 self.state.context.diff_blend,
                 self.state.context.diff_tolerance,
                 self.state.context.only_show_diff,
@@ -153,8 +198,27 @@ impl eframe::App for MyEguiApp {
                 self.state.context.max_diff = diff_result.max_diff() + 1.0;
                 (img, diff_result)
             });
+            let key = (i1, i2, params);
+            if self.state.diff_metrics.as_ref().map(|(key, _)| *key) != Some(key) {
+                let metrics = utils::quality_metrics(img1, img2, &params);
+                self.state.diff_metrics = Some((key, metrics));
+            }
+            let tolerance = self.state.context.diff_tolerance;
+            let key = ((i1, i2, params), tolerance.to_bits());
+            if self.state.diff_clusters.as_ref().map(|(key, _)| *key) != Some(key) {
+                let clusters = self
+                    .state
+                    .diff_result
+                    .as_ref()
+                    .map(|(_, diff_result)| diff_result.clusters(tolerance))
+                    .unwrap_or_default();
+                self.state.diff_clusters = Some((key, clusters));
+                self.state.selected_diff_cluster = None;
+            }
         } else {
             self.state.diff_result = None;
+            self.state.diff_metrics = None;
+            self.state.diff_clusters = None;
         }
 
         // Fast switch logic: update diff_blend if enabled
@@ -172,6 +236,9 @@ impl eframe::App for MyEguiApp {
             self.state.context.diff_blend = if phase < 0.5 { 0.0 } else { 1.0 };
         }
 
+        ui::draw_animation_panel(ctx, &mut self.state);
+        let playing = Self::play_animation(ctx, &mut self.state);
+
         ui::draw_central_panel(ctx, &mut self.state);
         ui::draw_image_info(ctx, &mut self.state);
 
@@ -180,7 +247,7 @@ impl eframe::App for MyEguiApp {
         // When fast_switch is enabled, force continues mode for rendering
         let render_continues = self.state.context.fast_switch && !self.state.context.only_show_diff;
 
-        if render_continues {
+        if render_continues || playing {
             ctx.request_repaint();
         }
     }
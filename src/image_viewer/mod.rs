@@ -1,5 +1,6 @@
 use crate::image_viewer::app::MyEguiApp;
 use eframe::egui::DroppedFile;
+use icu_lib::endecoder::EnDecoder;
 
 pub mod app;
 pub mod model;
@@ -42,7 +43,7 @@ fn setup_custom_fonts(ctx: &eframe::egui::Context) {
 
 // When compiling to web using trunk:
 #[cfg(target_arch = "wasm32")]
-pub fn show_image(files: Vec<DroppedFile>) {
+pub fn show_image(files: Vec<DroppedFile>, endecoder: Option<Box<dyn EnDecoder>>) {
     use eframe::wasm_bindgen::JsCast as _;
 
     // Redirect `log` message to `console.log` and friends:
@@ -68,7 +69,7 @@ pub fn show_image(files: Vec<DroppedFile>) {
                 web_options,
                 Box::new(|cc| {
                     setup_custom_fonts(&cc.egui_ctx);
-                    Ok(Box::new(MyEguiApp::new(cc, files)))
+                    Ok(Box::new(MyEguiApp::new(cc, files, endecoder)))
                 }),
             )
             .await;
@@ -91,7 +92,7 @@ pub fn show_image(files: Vec<DroppedFile>) {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub fn show_image(files: Vec<DroppedFile>) {
+pub fn show_image(files: Vec<DroppedFile>, endecoder: Option<Box<dyn EnDecoder>>) {
     let native_options = eframe::NativeOptions::default();
 
     eframe::run_native(
@@ -99,7 +100,7 @@ pub fn show_image(files: Vec<DroppedFile>) {
         native_options,
         Box::new(move |cc| {
             setup_custom_fonts(&cc.egui_ctx);
-            Ok(Box::new(MyEguiApp::new(cc, files)))
+            Ok(Box::new(MyEguiApp::new(cc, files, endecoder)))
         }),
     )
     .expect("Failed to run eframe");
@@ -1,11 +1,14 @@
 pub use crate::converter::{
-    ImageFormats as ImageFormat, LVGL_Version as LvglVersion,
+    ChromaSubsampling, ColorSpace, DiffRegion, DitherAlgorithm, ImageFormats as ImageFormat,
+    LVGL_ColorDepth as LvglColorDepth, LVGL_Version as LvglVersion,
     OutputColorFormats as LvglColorFormat, OutputCompressedMethod as LvglCompression,
+    PngCompression, PngFilter, Quantizer, ResizeFilter, Rotation, TiffCompression,
 };
 use clap::ValueEnum;
 use eframe::egui::{Color32, DroppedFile};
 use icu_lib::endecoder::ImageInfo;
-use icu_lib::endecoder::utils::diff::ImageDiffResult;
+use icu_lib::endecoder::utils::diff::{DiffCluster, DiffParams, ImageDiffResult, QualityMetrics};
+use icu_lib::midata::transform::{Rect, TransformParams};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, PartialEq)]
@@ -15,12 +18,50 @@ pub struct ImageItem {
     pub width: u32,
     pub height: u32,
     pub image_data: Vec<Color32>,
+    /// Frames of animated GIF, APNG and WEBP images, empty for still images
+    pub frames: Vec<AnimationFrame>,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct AnimationFrame {
+    pub image_data: Vec<Color32>,
+    pub delay_ms: u32,
+}
+
+impl ImageItem {
+    /// Show the frame at `index` of an animated image
+    pub fn show_frame(&mut self, index: usize) {
+        if let Some(frame) = self.frames.get(index) {
+            self.image_data = frame.image_data.clone();
+        }
+    }
+}
+
+/// Playback of the frames of the current image
+pub struct AnimationPlayback {
+    pub frame: usize,
+    pub playing: bool,
+    /// Seconds the current frame has been shown
+    pub elapsed: f32,
+}
+
+impl Default for AnimationPlayback {
+    fn default() -> Self {
+        Self {
+            frame: 0,
+            playing: true,
+            elapsed: 0.0,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct AppContext {
     pub show_grid: bool,
     pub anti_alias: bool,
+    /// Turn photos upright as their EXIF orientation says when they are opened
+    #[serde(default = "auto_orient_default")]
+    pub auto_orient: bool,
     pub image_diff: bool,
     pub background_color: Color32,
     pub diff_blend: f32,     // Controls the alpha blending for diff mode
@@ -35,6 +76,8 @@ pub struct AppContext {
     pub language: String,
 
     pub diff_sorting: DiffSorting,
+    #[serde(default)]
+    pub diff_region: DiffRegion, // Pixels compared when the images don't line up
     pub diff_page_index: usize,
     pub diff_page_size: usize,
 
@@ -53,13 +96,71 @@ pub enum DiffSorting {
 }
 
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct ConvertParams {
     pub output_format: ImageFormat,
     pub lvgl_version: LvglVersion,
     pub color_format: LvglColorFormat,
+    pub color_depth: LvglColorDepth,
+    pub color_16_swap: bool,
     pub compression: LvglCompression,
     pub stride_align: u8,
     pub dither: bool,
+    pub dither_algorithm: DitherAlgorithm,
+    pub dither_strength: f32,
+    pub quantizer: Quantizer,
+    pub color_space: ColorSpace,
+    pub quality: u8,
+    pub jpeg_subsampling: ChromaSubsampling,
+    pub png_compression: PngCompression,
+    pub png_filter: PngFilter,
+    pub webp_near_lossless: u8,
+    pub tiff_compression: TiffCompression,
+    pub transform: TransformOptions,
+}
+
+/// Transforms applied before converting, in the order crop, resize, rotate, flip and pad
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TransformOptions {
+    pub crop: bool,
+    pub crop_rect: [u32; 4],
+    pub resize: bool,
+    pub resize_size: [u32; 2],
+    pub resize_filter: ResizeFilter,
+    pub rotation: Rotation,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub pad: bool,
+    pub pad_size: [u32; 2],
+    pub pad_color: [u8; 4],
+}
+
+impl TransformOptions {
+    pub fn params(&self) -> TransformParams {
+        let mut params = TransformParams::new()
+            .with_resize_filter(self.resize_filter.into())
+            .with_flip_horizontal(self.flip_horizontal)
+            .with_flip_vertical(self.flip_vertical)
+            .with_pad_color(self.pad_color);
+        if self.crop {
+            let [x, y, width, height] = self.crop_rect;
+            params = params.with_crop(Rect {
+                x,
+                y,
+                width,
+                height,
+            });
+        }
+        if self.resize {
+            params = params.with_resize(self.resize_size[0], self.resize_size[1]);
+        }
+        params.rotation = self.rotation.into();
+        if self.pad {
+            params = params.with_pad(self.pad_size[0], self.pad_size[1]);
+        }
+        params
+    }
 }
 
 impl Default for ConvertParams {
@@ -68,18 +169,36 @@ impl Default for ConvertParams {
             output_format: ImageFormat::LVGL,
             lvgl_version: LvglVersion::V9,
             color_format: LvglColorFormat::RGB565,
+            color_depth: LvglColorDepth::Depth32,
+            color_16_swap: false,
             compression: LvglCompression::None,
             stride_align: 1,
             dither: false,
+            dither_algorithm: DitherAlgorithm::FloydSteinberg,
+            dither_strength: 1.0,
+            quantizer: Quantizer::NeuQuant,
+            color_space: ColorSpace::Srgb,
+            quality: 75,
+            jpeg_subsampling: ChromaSubsampling::Yuv444,
+            png_compression: PngCompression::Balanced,
+            png_filter: PngFilter::None,
+            webp_near_lossless: 100,
+            tiff_compression: TiffCompression::None,
+            transform: Default::default(),
         }
     }
 }
 
+fn auto_orient_default() -> bool {
+    true
+}
+
 impl Default for AppContext {
     fn default() -> Self {
         Self {
             show_grid: true,
             anti_alias: true,
+            auto_orient: true,
             image_diff: false,
             background_color: Default::default(),
             diff_blend: 0.5,     // Default alpha for diff blending
@@ -92,6 +211,7 @@ impl Default for AppContext {
             only_show_diff: false,
             language: crate::image_viewer::utils::get_system_locale(),
             diff_sorting: DiffSorting::Z,
+            diff_region: DiffRegion::Overlap,
             diff_page_index: 0,
             diff_page_size: 100,
             show_convert_panel: false,
@@ -100,6 +220,9 @@ impl Default for AppContext {
     }
 }
 
+/// Indices of the diffed images and how they are compared
+pub type DiffKey = (usize, usize, DiffParams);
+
 #[derive(Default)]
 pub struct ViewerState {
     pub current_image: Option<ImageItem>,
@@ -115,10 +238,22 @@ pub struct ViewerState {
     pub diff_image1_index: Option<usize>,
     pub diff_image2_index: Option<usize>,
     pub diff_result: Option<(ImageItem, ImageDiffResult)>,
+    /// Position of the second diffed image on the first one
+    pub diff_offset: (i32, i32),
+    /// Metrics of the diffed images and how they were compared, only computed when that changes
+    pub diff_metrics: Option<(DiffKey, Option<QualityMetrics>)>,
+    /// Regions of the pixels over the tolerance, also recomputed when its bits change
+    pub diff_clusters: Option<((DiffKey, u32), Vec<DiffCluster>)>,
+    pub selected_diff_cluster: Option<usize>,
+    pub hovered_diff_cluster: Option<usize>,
+    /// Rectangle `[x, y, width, height]` the viewer moves onto in the next frame
+    pub diff_zoom: Option<[u32; 4]>,
 
     pub selected_diff_pixel: Option<[u32; 2]>,
     pub hovered_diff_pixel: Option<[u32; 2]>,
     pub hovered_diff_pixel_from_plot: Option<[u32; 2]>,
 
     pub is_converting: bool,
+
+    pub playback: AnimationPlayback,
 }
@@ -2,7 +2,7 @@ use crate::image_viewer::model::ImageItem;
 use eframe::egui;
 use eframe::egui::load::SizedTexture;
 use eframe::egui::{Color32, ColorImage, PointerButton};
-use egui_plot::{CoordinatesFormatter, Corner, PlotImage, PlotPoint};
+use egui_plot::{CoordinatesFormatter, Corner, PlotBounds, PlotImage, PlotPoint};
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -13,6 +13,10 @@ pub struct ImagePlotter<'a> {
     show_only: bool,
     background_color: Color32,
     highlight_pixel: Option<[u32; 2]>,
+    /// Rectangles `[x, y, width, height]` outlined on the image
+    outlines: Vec<[u32; 4]>,
+    highlight_outline: Option<usize>,
+    zoom_to: Option<[u32; 4]>,
     on_hover: Option<&'a mut Option<[u32; 2]>>,
 }
 
@@ -25,6 +29,9 @@ impl<'a> ImagePlotter<'a> {
             show_only: false,
             background_color: Default::default(),
             highlight_pixel: None,
+            outlines: Vec::new(),
+            highlight_outline: None,
+            zoom_to: None,
             on_hover: None,
         }
     }
@@ -40,6 +47,21 @@ impl<'a> ImagePlotter<'a> {
         s
     }
 
+    /// Outline the rectangles `[x, y, width, height]`, the one at `highlight` stands out
+    pub fn outlines(self, outlines: Vec<[u32; 4]>, highlight: Option<usize>) -> Self {
+        let mut s = self;
+        s.outlines = outlines;
+        s.highlight_outline = highlight;
+        s
+    }
+
+    /// Move the view onto the rectangle `[x, y, width, height]`
+    pub fn zoom_to(self, rect: Option<[u32; 4]>) -> Self {
+        let mut s = self;
+        s.zoom_to = rect;
+        s
+    }
+
     pub fn anti_alias(self, sure: bool) -> Self {
         let mut s = self;
         s.anti_alias = sure;
@@ -167,6 +189,33 @@ impl<'a> ImagePlotter<'a> {
                         texture.size,
                     ));
 
+                    if let Some([x, y, w, h]) = self.zoom_to {
+                        let margin = (w.max(h) as f64 / 4.0).max(4.0);
+                        plot_ui.set_plot_bounds(PlotBounds::from_min_max(
+                            [x as f64 - margin, -((y + h) as f64) - margin],
+                            [(x + w) as f64 + margin, -(y as f64) + margin],
+                        ));
+                    }
+
+                    for (index, &[x, y, w, h]) in self.outlines.iter().enumerate() {
+                        let (x0, y0) = (x as f64, -(y as f64));
+                        let (x1, y1) = ((x + w) as f64, -((y + h) as f64));
+                        let stroke = if self.highlight_outline == Some(index) {
+                            egui::Stroke::new(3.0, Color32::CYAN)
+                        } else {
+                            egui::Stroke::new(1.5, Color32::ORANGE)
+                        };
+                        plot_ui.polygon(
+                            egui_plot::Polygon::new(
+                                format!("outline{index}"),
+                                vec![[x0, y0], [x1, y0], [x1, y1], [x0, y1]],
+                            )
+                            .fill_color(Color32::TRANSPARENT)
+                            .stroke(stroke)
+                            .allow_hover(false),
+                        );
+                    }
+
                     let plot_bounds = plot_ui.plot_bounds();
                     let plot_size = plot_ui.response().rect;
                     let scale_fact = 1.2f64;
@@ -0,0 +1,51 @@
+use crate::image_viewer::model::ViewerState;
+use eframe::egui;
+
+/// Draws the playback controls of an animated image above the bottom panel.
+pub fn draw_animation_panel(ctx: &egui::Context, state: &mut ViewerState) {
+    let Some(image) = &state.current_image else {
+        return;
+    };
+    let count = image.frames.len();
+    if count == 0 || state.context.image_diff {
+        return;
+    }
+
+    let playback = &mut state.playback;
+    egui::TopBottomPanel::bottom("animation_panel").show(ctx, |ui| {
+        ui.horizontal(|ui| {
+            ui.set_height(30.0);
+
+            if ui.button("⏮").on_hover_text(t!("previous_frame")).clicked() {
+                playback.playing = false;
+                playback.frame = (playback.frame + count - 1) % count;
+            }
+            let (icon, hint) = if playback.playing {
+                ("⏸", t!("pause"))
+            } else {
+                ("▶", t!("play"))
+            };
+            if ui.button(icon).on_hover_text(hint).clicked() {
+                playback.playing = !playback.playing;
+                playback.elapsed = 0.0;
+            }
+            if ui.button("⏭").on_hover_text(t!("next_frame")).clicked() {
+                playback.playing = false;
+                playback.frame = (playback.frame + 1) % count;
+            }
+
+            ui.separator();
+            let mut frame = playback.frame + 1;
+            if ui
+                .add(egui::Slider::new(&mut frame, 1..=count).text(t!("frame")))
+                .changed()
+            {
+                playback.playing = false;
+                playback.frame = frame - 1;
+            }
+
+            ui.separator();
+            ui.label(format!("{} ms", image.frames[playback.frame].delay_ms));
+        });
+    });
+}
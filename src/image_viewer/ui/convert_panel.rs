@@ -1,6 +1,8 @@
 use crate::cus_component::toggle;
 use crate::image_viewer::model::{
-    ImageFormat, LvglColorFormat, LvglCompression, LvglVersion, ViewerState,
+    ChromaSubsampling, ColorSpace, DitherAlgorithm, ImageFormat, LvglColorDepth, LvglColorFormat,
+    LvglCompression, LvglVersion, PngCompression, PngFilter, Quantizer, ResizeFilter, Rotation,
+    TiffCompression, ViewerState,
 };
 use clap::ValueEnum;
 use eframe::egui;
@@ -57,27 +59,59 @@ fn draw_convert_options(ui: &mut egui::Ui, state: &mut ViewerState) {
     ui.add_space(16.0);
 
     // LVGL Specific Options Group
-    if state.context.convert_params.output_format == ImageFormat::LVGL {
+    let output_format = state.context.convert_params.output_format;
+    if matches!(output_format, ImageFormat::LVGL | ImageFormat::RAW) {
         draw_section_frame(ui, "LVGL Settings", |ui| {
             egui::Grid::new("lvgl_settings_grid")
                 .num_columns(2)
                 .spacing([12.0, 8.0])
                 .striped(false)
                 .show(ui, |ui| {
-                    ui.label(t!("lvgl_version"));
-                    egui::ComboBox::from_id_salt("lvgl_version")
-                        .selected_text(format!("{:?}", state.context.convert_params.lvgl_version))
-                        .width(160.0)
-                        .show_ui(ui, |ui| {
-                            for &version in LvglVersion::value_variants() {
-                                ui.selectable_value(
-                                    &mut state.context.convert_params.lvgl_version,
-                                    version,
-                                    format!("{version:?}"),
-                                );
-                            }
-                        });
-                    ui.end_row();
+                    if output_format == ImageFormat::LVGL {
+                        ui.label(t!("lvgl_version"));
+                        egui::ComboBox::from_id_salt("lvgl_version")
+                            .selected_text(format!(
+                                "{:?}",
+                                state.context.convert_params.lvgl_version
+                            ))
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for &version in LvglVersion::value_variants() {
+                                    ui.selectable_value(
+                                        &mut state.context.convert_params.lvgl_version,
+                                        version,
+                                        format!("{version:?}"),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
+
+                    if output_format == ImageFormat::LVGL
+                        && state.context.convert_params.lvgl_version == LvglVersion::V8
+                    {
+                        ui.label(t!("color_depth"));
+                        egui::ComboBox::from_id_salt("color_depth")
+                            .selected_text(format!(
+                                "{:?}",
+                                state.context.convert_params.color_depth
+                            ))
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for &depth in LvglColorDepth::value_variants() {
+                                    ui.selectable_value(
+                                        &mut state.context.convert_params.color_depth,
+                                        depth,
+                                        format!("{depth:?}"),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label(t!("color_16_swap"));
+                        ui.add(toggle("", &mut state.context.convert_params.color_16_swap));
+                        ui.end_row();
+                    }
 
                     ui.label(t!("color_format"));
                     egui::ComboBox::from_id_salt("color_format")
@@ -94,16 +128,59 @@ fn draw_convert_options(ui: &mut egui::Ui, state: &mut ViewerState) {
                         });
                     ui.end_row();
 
-                    ui.label(t!("compression"));
-                    egui::ComboBox::from_id_salt("compression")
-                        .selected_text(format!("{:?}", state.context.convert_params.compression))
+                    if output_format == ImageFormat::LVGL {
+                        ui.label(t!("compression"));
+                        egui::ComboBox::from_id_salt("compression")
+                            .selected_text(format!(
+                                "{:?}",
+                                state.context.convert_params.compression
+                            ))
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for &compression in LvglCompression::value_variants() {
+                                    ui.selectable_value(
+                                        &mut state.context.convert_params.compression,
+                                        compression,
+                                        format!("{compression:?}"),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
+
+                    if matches!(
+                        state.context.convert_params.color_format,
+                        LvglColorFormat::I1
+                            | LvglColorFormat::I2
+                            | LvglColorFormat::I4
+                            | LvglColorFormat::I8
+                    ) {
+                        ui.label(t!("quantizer"));
+                        egui::ComboBox::from_id_salt("quantizer")
+                            .selected_text(format!("{:?}", state.context.convert_params.quantizer))
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for &quantizer in Quantizer::value_variants() {
+                                    ui.selectable_value(
+                                        &mut state.context.convert_params.quantizer,
+                                        quantizer,
+                                        format!("{quantizer:?}"),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+                    }
+
+                    ui.label(t!("color_space"));
+                    egui::ComboBox::from_id_salt("color_space")
+                        .selected_text(format!("{:?}", state.context.convert_params.color_space))
                         .width(160.0)
                         .show_ui(ui, |ui| {
-                            for &compression in LvglCompression::value_variants() {
+                            for &color_space in ColorSpace::value_variants() {
                                 ui.selectable_value(
-                                    &mut state.context.convert_params.compression,
-                                    compression,
-                                    format!("{compression:?}"),
+                                    &mut state.context.convert_params.color_space,
+                                    color_space,
+                                    format!("{color_space:?}"),
                                 );
                             }
                         });
@@ -118,10 +195,139 @@ fn draw_convert_options(ui: &mut egui::Ui, state: &mut ViewerState) {
                     ui.label(t!("dither"));
                     ui.add(toggle("", &mut state.context.convert_params.dither));
                     ui.end_row();
+
+                    if state.context.convert_params.dither {
+                        ui.label(t!("dither_algorithm"));
+                        egui::ComboBox::from_id_salt("dither_algorithm")
+                            .selected_text(format!(
+                                "{:?}",
+                                state.context.convert_params.dither_algorithm
+                            ))
+                            .width(160.0)
+                            .show_ui(ui, |ui| {
+                                for &algorithm in DitherAlgorithm::value_variants() {
+                                    ui.selectable_value(
+                                        &mut state.context.convert_params.dither_algorithm,
+                                        algorithm,
+                                        format!("{algorithm:?}"),
+                                    );
+                                }
+                            });
+                        ui.end_row();
+
+                        ui.label(t!("dither_strength"));
+                        ui.add(egui::Slider::new(
+                            &mut state.context.convert_params.dither_strength,
+                            0.0..=1.0,
+                        ));
+                        ui.end_row();
+                    }
                 });
         });
     }
 
+    if matches!(
+        output_format,
+        ImageFormat::PNG | ImageFormat::JPEG | ImageFormat::WEBP | ImageFormat::TIFF
+    ) {
+        ui.add_space(16.0);
+        draw_encoder_options(ui, state, output_format);
+    }
+
+    ui.add_space(16.0);
+
+    draw_section_frame(ui, &t!("transform"), |ui| {
+        let transform = &mut state.context.convert_params.transform;
+        egui::Grid::new("transform_settings_grid")
+            .num_columns(2)
+            .spacing([12.0, 8.0])
+            .striped(false)
+            .show(ui, |ui| {
+                ui.label(t!("crop"));
+                ui.add(toggle("", &mut transform.crop));
+                ui.end_row();
+                if transform.crop {
+                    ui.label("");
+                    ui.horizontal(|ui| {
+                        for (value, prefix) in
+                            transform.crop_rect.iter_mut().zip(["x ", "y ", "w ", "h "])
+                        {
+                            ui.add(egui::DragValue::new(value).prefix(prefix));
+                        }
+                    });
+                    ui.end_row();
+                }
+
+                ui.label(t!("resize"));
+                ui.add(toggle("", &mut transform.resize));
+                ui.end_row();
+                if transform.resize {
+                    ui.label("");
+                    ui.horizontal(|ui| {
+                        for (value, prefix) in transform.resize_size.iter_mut().zip(["w ", "h "]) {
+                            ui.add(egui::DragValue::new(value).prefix(prefix));
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label(t!("resize_filter"));
+                    egui::ComboBox::from_id_salt("resize_filter")
+                        .selected_text(format!("{:?}", transform.resize_filter))
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for &filter in ResizeFilter::value_variants() {
+                                ui.selectable_value(
+                                    &mut transform.resize_filter,
+                                    filter,
+                                    format!("{filter:?}"),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                }
+
+                ui.label(t!("rotate"));
+                egui::ComboBox::from_id_salt("rotate")
+                    .selected_text(format!("{:?}", transform.rotation))
+                    .width(160.0)
+                    .show_ui(ui, |ui| {
+                        for &rotation in Rotation::value_variants() {
+                            ui.selectable_value(
+                                &mut transform.rotation,
+                                rotation,
+                                format!("{rotation:?}"),
+                            );
+                        }
+                    });
+                ui.end_row();
+
+                ui.label(t!("flip_horizontal"));
+                ui.add(toggle("", &mut transform.flip_horizontal));
+                ui.end_row();
+
+                ui.label(t!("flip_vertical"));
+                ui.add(toggle("", &mut transform.flip_vertical));
+                ui.end_row();
+
+                ui.label(t!("pad"));
+                ui.add(toggle("", &mut transform.pad));
+                ui.end_row();
+                if transform.pad {
+                    ui.label("");
+                    ui.horizontal(|ui| {
+                        for (value, prefix) in transform.pad_size.iter_mut().zip(["w ", "h "]) {
+                            ui.add(egui::DragValue::new(value).prefix(prefix));
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label(t!("pad_color"));
+                    ui.color_edit_button_srgba_unmultiplied(&mut transform.pad_color);
+                    ui.end_row();
+                }
+            });
+    });
+
     ui.add_space(24.0);
 
     // Convert Action
@@ -156,6 +362,96 @@ fn draw_convert_options(ui: &mut egui::Ui, state: &mut ViewerState) {
     });
 }
 
+/// Draws the options of the PNG, JPEG, WEBP and TIFF encoders.
+fn draw_encoder_options(ui: &mut egui::Ui, state: &mut ViewerState, output_format: ImageFormat) {
+    let params = &mut state.context.convert_params;
+    draw_section_frame(ui, &t!("encoder_settings"), |ui| {
+        egui::Grid::new("encoder_settings_grid")
+            .num_columns(2)
+            .spacing([12.0, 8.0])
+            .striped(false)
+            .show(ui, |ui| {
+                if output_format == ImageFormat::PNG {
+                    ui.label(t!("compression"));
+                    egui::ComboBox::from_id_salt("png_compression")
+                        .selected_text(format!("{:?}", params.png_compression))
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for &compression in PngCompression::value_variants() {
+                                ui.selectable_value(
+                                    &mut params.png_compression,
+                                    compression,
+                                    format!("{compression:?}"),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label(t!("png_filter"));
+                    egui::ComboBox::from_id_salt("png_filter")
+                        .selected_text(format!("{:?}", params.png_filter))
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for &filter in PngFilter::value_variants() {
+                                ui.selectable_value(
+                                    &mut params.png_filter,
+                                    filter,
+                                    format!("{filter:?}"),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                }
+
+                if output_format == ImageFormat::WEBP {
+                    ui.label(t!("webp_near_lossless"));
+                    ui.add(egui::Slider::new(&mut params.webp_near_lossless, 0..=100));
+                    ui.end_row();
+                }
+
+                if output_format == ImageFormat::JPEG {
+                    ui.label(t!("quality"));
+                    ui.add(egui::Slider::new(&mut params.quality, 1..=100));
+                    ui.end_row();
+                }
+
+                if output_format == ImageFormat::JPEG {
+                    ui.label(t!("chroma_subsampling"));
+                    egui::ComboBox::from_id_salt("jpeg_subsampling")
+                        .selected_text(format!("{:?}", params.jpeg_subsampling))
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for &subsampling in ChromaSubsampling::value_variants() {
+                                ui.selectable_value(
+                                    &mut params.jpeg_subsampling,
+                                    subsampling,
+                                    format!("{subsampling:?}"),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                }
+
+                if output_format == ImageFormat::TIFF {
+                    ui.label(t!("compression"));
+                    egui::ComboBox::from_id_salt("tiff_compression")
+                        .selected_text(format!("{:?}", params.tiff_compression))
+                        .width(160.0)
+                        .show_ui(ui, |ui| {
+                            for &compression in TiffCompression::value_variants() {
+                                ui.selectable_value(
+                                    &mut params.tiff_compression,
+                                    compression,
+                                    format!("{compression:?}"),
+                                );
+                            }
+                        });
+                    ui.end_row();
+                }
+            });
+    });
+}
+
 fn draw_section_frame(ui: &mut egui::Ui, title: &str, add_contents: impl FnOnce(&mut egui::Ui)) {
     egui::containers::Frame::default()
         .inner_margin(8.0)
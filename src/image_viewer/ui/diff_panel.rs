@@ -1,9 +1,9 @@
 use crate::cus_component::toggle;
-use crate::image_viewer::model::{DiffSorting, ViewerState};
+use crate::image_viewer::model::{DiffRegion, DiffSorting, ViewerState};
 use clap::ValueEnum;
 use eframe::egui;
 use eframe::egui::{Color32, Sense};
-use icu_lib::endecoder::utils::diff::ImageDiffPixel;
+use icu_lib::endecoder::utils::diff::{DiffCluster, ImageDiffPixel, QualityMetrics};
 
 /// Draws the right panel containing difference settings and pixel details.
 pub fn draw_right_panel(ctx: &egui::Context, state: &mut ViewerState) {
@@ -18,6 +18,28 @@ pub fn draw_right_panel(ctx: &egui::Context, state: &mut ViewerState) {
 
                 ui.separator();
 
+                if let Some((_, Some(metrics))) = &state.diff_metrics {
+                    draw_quality_metrics(ui, metrics);
+                    ui.separator();
+                }
+
+                state.hovered_diff_cluster = None;
+                if let Some((_, clusters)) = &state.diff_clusters
+                    && !clusters.is_empty()
+                {
+                    draw_diff_cluster_list(
+                        ui,
+                        clusters,
+                        &mut state.selected_diff_cluster,
+                        &mut state.hovered_diff_cluster,
+                        &mut state.diff_zoom,
+                    );
+                    if state.diff_zoom.is_some() {
+                        state.selected_diff_pixel = None;
+                    }
+                    ui.separator();
+                }
+
                 state.hovered_diff_pixel = None;
                 if let Some((_, diff_result)) = &state.diff_result {
                     if let (Some(i1), Some(i2)) = (state.diff_image1_index, state.diff_image2_index)
@@ -56,6 +78,138 @@ fn draw_diff_panel_controls(ui: &mut egui::Ui, state: &mut ViewerState) {
     } else {
         state.context.fast_switch = false;
     }
+    draw_diff_alignment(ui, state);
+}
+
+/// Draws the offset of the second image on the first one and the compared region.
+fn draw_diff_alignment(ui: &mut egui::Ui, state: &mut ViewerState) {
+    ui.horizontal(|ui| {
+        ui.label(t!("diff_offset"));
+        ui.add(egui::DragValue::new(&mut state.diff_offset.0).prefix("x: "));
+        ui.add(egui::DragValue::new(&mut state.diff_offset.1).prefix("y: "));
+        if ui
+            .button(t!("auto_align"))
+            .on_hover_text(t!("auto_align_hint"))
+            .clicked()
+            && let (Some(i1), Some(i2)) = (state.diff_image1_index, state.diff_image2_index)
+            && let Some(offset) =
+                crate::utils::find_offset(&state.image_items[i1], &state.image_items[i2])
+        {
+            state.diff_offset = offset;
+        }
+    });
+    egui::ComboBox::from_label(t!("diff_region"))
+        .selected_text(t!(format!(
+            "diff_region_kind.{:?}",
+            state.context.diff_region
+        )))
+        .show_ui(ui, |ui| {
+            for &variant in DiffRegion::value_variants() {
+                ui.selectable_value(
+                    &mut state.context.diff_region,
+                    variant,
+                    t!(format!("diff_region_kind.{variant:?}")),
+                );
+            }
+        });
+}
+
+/// Draws the quality metrics of the second image against the first one.
+fn draw_quality_metrics(ui: &mut egui::Ui, metrics: &QualityMetrics) {
+    let decibel = |psnr: f64| match psnr.is_finite() {
+        true => format!("{psnr:.2} dB"),
+        false => "∞".to_string(),
+    };
+    egui::CollapsingHeader::new(t!("quality_metrics"))
+        .default_open(true)
+        .show(ui, |ui| {
+            egui::Grid::new("quality_metrics_grid")
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("MSE R/G/B/A");
+                    ui.label(metrics.mse.map(|mse| format!("{mse:.2}")).join(" / "));
+                    ui.end_row();
+
+                    ui.label("PSNR RGB");
+                    ui.label(decibel(metrics.psnr_rgb));
+                    ui.end_row();
+
+                    ui.label("PSNR R/G/B/A");
+                    ui.label(metrics.psnr.map(decibel).join(" / "));
+                    ui.end_row();
+
+                    ui.label("SSIM");
+                    ui.label(format!("{:.4}", metrics.ssim));
+                    ui.end_row();
+
+                    ui.label("MS-SSIM");
+                    ui.label(format!("{:.4}", metrics.ms_ssim));
+                    ui.end_row();
+
+                    let delta_e = &metrics.delta_e;
+                    ui.label("ΔE2000").on_hover_text(t!("delta_e_hint"));
+                    ui.label(format!(
+                        "{:.2} / {:.2} / {:.2} / {:.2}",
+                        delta_e.mean, delta_e.p95, delta_e.p99, delta_e.max
+                    ));
+                    ui.end_row();
+                });
+        });
+}
+
+/// Draws the regions of differing pixels, clicking one moves the view onto it.
+fn draw_diff_cluster_list(
+    ui: &mut egui::Ui,
+    clusters: &[DiffCluster],
+    selected_diff_cluster: &mut Option<usize>,
+    hovered_diff_cluster: &mut Option<usize>,
+    diff_zoom: &mut Option<[u32; 4]>,
+) {
+    egui::CollapsingHeader::new(format!("{} ({})", t!("diff_clusters"), clusters.len()))
+        .default_open(true)
+        .show(ui, |ui| {
+            ui.label(
+                egui::RichText::new(t!("diff_clusters_hint"))
+                    .small()
+                    .color(ui.style().visuals.weak_text_color()),
+            );
+            let row_height = ui.text_style_height(&egui::TextStyle::Body);
+            egui::ScrollArea::vertical()
+                .id_salt("diff_clusters")
+                .max_height(160.0)
+                .show_rows(ui, row_height, clusters.len(), |ui, rows| {
+                    for index in rows {
+                        let cluster = &clusters[index];
+                        let is_selected = *selected_diff_cluster == Some(index);
+                        let response = ui.selectable_label(
+                            is_selected,
+                            format!(
+                                "({}, {}) {}×{}  {} px  {:.1} / {:.1}",
+                                cluster.x,
+                                cluster.y,
+                                cluster.width,
+                                cluster.height,
+                                cluster.pixel_count,
+                                cluster.max_diff,
+                                cluster.mean_diff
+                            ),
+                        );
+                        if response.clicked() {
+                            if is_selected {
+                                *selected_diff_cluster = None;
+                            } else {
+                                *selected_diff_cluster = Some(index);
+                                *diff_zoom =
+                                    Some([cluster.x, cluster.y, cluster.width, cluster.height]);
+                            }
+                        }
+                        if response.hovered() {
+                            *hovered_diff_cluster = Some(index);
+                        }
+                    }
+                });
+        });
 }
 
 /// Draws preset buttons for diff blend (Diff1, Blended, Diff2).
@@ -153,9 +307,7 @@ fn get_sorted_diff_pixels<'a>(
     context: &crate::image_viewer::model::AppContext,
     diff_result: &'a icu_lib::endecoder::utils::diff::ImageDiffResult,
 ) -> Vec<&'a ImageDiffPixel> {
-    let mut diff_pixels: Vec<_> = diff_result
-        .diff_filter(context.diff_tolerance)
-        .collect();
+    let mut diff_pixels: Vec<_> = diff_result.diff_filter(context.diff_tolerance).collect();
 
     match context.diff_sorting {
         DiffSorting::Z => {
@@ -174,14 +326,18 @@ fn get_sorted_diff_pixels<'a>(
             diff_pixels.sort_by(|a, b| {
                 let diff_a = a.diff.iter().cloned().reduce(f32::max).unwrap_or(0.0);
                 let diff_b = b.diff.iter().cloned().reduce(f32::max).unwrap_or(0.0);
-                diff_a.partial_cmp(&diff_b).unwrap_or(std::cmp::Ordering::Equal)
+                diff_a
+                    .partial_cmp(&diff_b)
+                    .unwrap_or(std::cmp::Ordering::Equal)
             });
         }
         DiffSorting::DiffDesc => {
             diff_pixels.sort_by(|a, b| {
                 let diff_a = a.diff.iter().cloned().reduce(f32::max).unwrap_or(0.0);
                 let diff_b = b.diff.iter().cloned().reduce(f32::max).unwrap_or(0.0);
-                diff_b.partial_cmp(&diff_a).unwrap_or(std::cmp::Ordering::Equal)
+                diff_b
+                    .partial_cmp(&diff_a)
+                    .unwrap_or(std::cmp::Ordering::Equal)
             });
         }
     }
@@ -211,8 +367,7 @@ fn draw_diff_list_row(
     diff_pixel: &ImageDiffPixel,
 ) -> Option<egui::Rect> {
     let is_selected = *selected_diff_pixel == Some([diff_pixel.pos.0, diff_pixel.pos.1]);
-    let is_hovered =
-        hovered_diff_pixel_from_plot == Some([diff_pixel.pos.0, diff_pixel.pos.1]);
+    let is_hovered = hovered_diff_pixel_from_plot == Some([diff_pixel.pos.0, diff_pixel.pos.1]);
     let mut target_rect = None;
 
     egui::containers::Frame::default()
@@ -228,7 +383,10 @@ fn draw_diff_list_row(
                 .spacing([8.0, 4.0])
                 .min_col_width(60.0)
                 .show(ui, |ui| {
-                    ui.add(egui::Label::new(format!("({}, {})", diff_pixel.pos.0, diff_pixel.pos.1)).wrap());
+                    ui.add(
+                        egui::Label::new(format!("({}, {})", diff_pixel.pos.0, diff_pixel.pos.1))
+                            .wrap(),
+                    );
                     ui.color_edit_button_srgba_unmultiplied(&mut color1);
                     ui.color_edit_button_srgba_unmultiplied(&mut color2);
                     let diff = diff_pixel.diff.into_iter().reduce(f32::max).unwrap_or(0.0);
@@ -251,7 +409,12 @@ fn draw_diff_list_row(
                 *hovered_diff_pixel = Some([diff_pixel.pos.0, diff_pixel.pos.1]);
             }
 
-            if is_selected || response.hovered() || response.highlighted() || response.has_focus() || is_hovered {
+            if is_selected
+                || response.hovered()
+                || response.highlighted()
+                || response.has_focus()
+                || is_hovered
+            {
                 let rect = rect.expand(4.0);
                 let painter = ui.painter_at(rect);
                 let rect = rect.expand(-2.0);
@@ -351,11 +514,7 @@ fn draw_diff_pagination_controls(
         if ui.button("<").clicked() && context.diff_page_index > 0 {
             context.diff_page_index -= 1;
         }
-        ui.label(format!(
-            "{}/{}",
-            context.diff_page_index + 1,
-            total_pages
-        ));
+        ui.label(format!("{}/{}", context.diff_page_index + 1, total_pages));
         if ui.button(">").clicked() && context.diff_page_index + 1 < total_pages {
             context.diff_page_index += 1;
         }
@@ -388,11 +547,7 @@ fn draw_diff_list_scroll_area(
         }
 
         if let Some(target_rect) = target_rect {
-            ui.scroll_to_rect_animation(
-                target_rect,
-                None,
-                egui::style::ScrollAnimation::default(),
-            );
+            ui.scroll_to_rect_animation(target_rect, None, egui::style::ScrollAnimation::default());
         }
     });
 }
@@ -117,6 +117,7 @@ fn handle_item_interaction(
     if response.clicked() {
         state.selected_image_item_index = Some(index);
         state.current_image = Some(image_item.clone());
+        state.playback = Default::default();
     }
     if response.hovered() {
         state.hovered_image_item_index = Some(index);
@@ -12,6 +12,8 @@ pub fn draw_top_panel(ctx: &egui::Context, state: &mut ViewerState) {
             ui.separator();
             ui.toggle_value(&mut state.context.show_grid, t!("show_grid"));
             ui.toggle_value(&mut state.context.anti_alias, t!("anti_aliasing"));
+            ui.toggle_value(&mut state.context.auto_orient, t!("auto_orient"))
+                .on_hover_text(t!("auto_orient_hint"));
 
             ui.separator();
             if ui.button(t!("clear")).clicked() {
@@ -1,9 +1,11 @@
+pub mod animation;
 pub mod convert_panel;
 pub mod diff_panel;
 pub mod image_list;
 pub mod layout;
 pub mod viewer;
 
+pub use animation::draw_animation_panel;
 pub use convert_panel::draw_convert_panel;
 pub use diff_panel::draw_right_panel;
 pub use image_list::draw_left_panel;
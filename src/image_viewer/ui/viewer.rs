@@ -3,9 +3,20 @@ use crate::image_viewer::plotter::ImagePlotter;
 use eframe::egui;
 use serde::Serialize;
 
+/// Largest diff regions outlined on the image, more only clutter it
+const MAX_OUTLINES: usize = 256;
+
 /// Draws the central panel displaying the image or drag-drop area.
 pub fn draw_central_panel(ctx: &egui::Context, state: &mut ViewerState) {
     egui::CentralPanel::default().show(ctx, |ui| {
+        let outlines = match &state.diff_clusters {
+            Some((_, clusters)) if state.diff_result.is_some() => clusters
+                .iter()
+                .take(MAX_OUTLINES)
+                .map(|cluster| [cluster.x, cluster.y, cluster.width, cluster.height])
+                .collect(),
+            _ => Vec::new(),
+        };
         let mut image_plotter = ImagePlotter::new("viewer")
             .anti_alias(state.context.anti_alias)
             .show_grid(state.context.show_grid)
@@ -15,6 +26,11 @@ pub fn draw_central_panel(ctx: &egui::Context, state: &mut ViewerState) {
             } else {
                 state.hovered_diff_pixel
             })
+            .outlines(
+                outlines,
+                state.hovered_diff_cluster.or(state.selected_diff_cluster),
+            )
+            .zoom_to(state.diff_zoom.take())
             .on_hover(&mut state.hovered_diff_pixel_from_plot);
 
         if state.context.only_show_diff {
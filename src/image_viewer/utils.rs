@@ -1,13 +1,22 @@
-use crate::image_viewer::model::{ConvertParams, ImageItem};
+use crate::image_viewer::model::{AnimationFrame, ConvertParams, ImageItem};
 use eframe::egui::{Color32, DroppedFile};
-use icu_lib::midata::MiData;
-use icu_lib::EncoderParams;
+use icu_lib::endecoder::utils::color_space::{ColorSpace, convert_color_space};
+use icu_lib::endecoder::{EnDecoder, ImageInfo};
+use icu_lib::midata::{Animation, DecodeParams, Decoded, MiData};
+use icu_lib::{EncoderParams, IcuError};
 use std::path::Path;
 
-pub fn process_images(files: &[DroppedFile]) -> Vec<ImageItem> {
+/// Decode the files with the given endecoder, or detect one per file if it is `None`.
+/// `auto_orient` turns photos upright as their EXIF orientation says.
+pub fn process_images(
+    files: &[DroppedFile],
+    endecoder: Option<&dyn EnDecoder>,
+    auto_orient: bool,
+) -> Vec<ImageItem> {
+    let params = DecodeParams::new().with_auto_orient(auto_orient);
     files
         .iter()
-        .map_while(|file| {
+        .filter_map(|file| {
             let file_path_info = if let Some(path) = &file.path {
                 path.display().to_string()
             } else if !file.name.is_empty() {
@@ -16,10 +25,15 @@ pub fn process_images(files: &[DroppedFile]) -> Vec<ImageItem> {
                 return None;
             };
 
-            let (mi_data, image_info) = match &file.bytes {
+            let decoded = match &file.bytes {
                 Some(bytes) => {
-                    if let Some(coder) = icu_lib::endecoder::find_endecoder(bytes) {
-                        (coder.decode(bytes.to_vec()), coder.info(bytes))
+                    if let Some(coder) = endecoder.or_else(|| {
+                        icu_lib::endecoder::find_endecoder_with_extension(
+                            bytes,
+                            extension(&file_path_info),
+                        )
+                    }) {
+                        decode_and_info(coder, bytes.to_vec(), params)
                     } else {
                         return None;
                     }
@@ -28,8 +42,13 @@ pub fn process_images(files: &[DroppedFile]) -> Vec<ImageItem> {
                     let data = std::fs::read(&file_path_info);
                     match data {
                         Ok(data) => {
-                            if let Some(coder) = icu_lib::endecoder::find_endecoder(&data) {
-                                (coder.decode(data.clone()), coder.info(&data))
+                            if let Some(coder) = endecoder.or_else(|| {
+                                icu_lib::endecoder::find_endecoder_with_extension(
+                                    &data,
+                                    extension(&file_path_info),
+                                )
+                            }) {
+                                decode_and_info(coder, data, params)
                             } else {
                                 return None;
                             }
@@ -39,16 +58,19 @@ pub fn process_images(files: &[DroppedFile]) -> Vec<ImageItem> {
                 }
             };
 
-            match mi_data {
-                MiData::RGBA(img_buffer) => {
+            let (mi_data, image_info, frames) = match decoded {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    log::error!("Failed to decode <{file_path_info}>: {err}");
+                    return None;
+                }
+            };
+
+            match mi_data.to_rgba() {
+                Some(img_buffer) => {
                     let width = img_buffer.width();
                     let height = img_buffer.height();
-                    let image_data = img_buffer
-                        .chunks(4)
-                        .map(|pixel| {
-                            Color32::from_rgba_unmultiplied(pixel[0], pixel[1], pixel[2], pixel[3])
-                        })
-                        .collect::<Vec<Color32>>();
+                    let image_data = to_color32(&img_buffer);
 
                     Some(ImageItem {
                         path: file_path_info,
@@ -56,15 +78,54 @@ pub fn process_images(files: &[DroppedFile]) -> Vec<ImageItem> {
                         width,
                         height,
                         image_data,
+                        frames,
                     })
                 }
-                MiData::GRAY(_) => None,
-                MiData::PATH => None,
+                None => None,
             }
         })
         .collect()
 }
 
+fn extension(path: &str) -> Option<&str> {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+}
+
+fn decode_and_info(
+    coder: &dyn EnDecoder,
+    data: Vec<u8>,
+    params: DecodeParams,
+) -> Result<(MiData, ImageInfo, Vec<AnimationFrame>), IcuError> {
+    let image_info = coder.info(&data)?;
+    let frames = match Animation::decode_with(&data, params) {
+        Ok(Some(animation)) => animation
+            .frames
+            .iter()
+            .filter_map(|frame| {
+                Some(AnimationFrame {
+                    image_data: to_color32(&frame.image.to_rgba()?),
+                    delay_ms: frame.delay_ms,
+                })
+            })
+            .collect(),
+        Ok(None) => Vec::new(),
+        Err(err) => {
+            log::warn!("Failed to decode the frames of the animation: {err}");
+            Vec::new()
+        }
+    };
+    let decoded = Decoded::decode_from(coder, data, params)?;
+    Ok((decoded.image, image_info, frames))
+}
+
+fn to_color32(rgba: &[u8]) -> Vec<Color32> {
+    rgba.chunks(4)
+        .map(|pixel| Color32::from_rgba_unmultiplied(pixel[0], pixel[1], pixel[2], pixel[3]))
+        .collect()
+}
+
 pub fn get_system_locale() -> String {
     let locale = sys_locale::get_locale().unwrap_or_else(|| "en-US".to_string());
     locale.replace('_', "-")
@@ -77,22 +138,48 @@ pub fn convert_image(
     let midata = MiData::from_rgba(
         image_item.width,
         image_item.height,
-        image_item.image_data.iter().flat_map(|x| x.to_array()).collect::<Vec<u8>>(),
-    ).ok_or("Failed to create MiData")?;
+        image_item
+            .image_data
+            .iter()
+            .flat_map(|x| x.to_array())
+            .collect::<Vec<u8>>(),
+    )
+    .ok_or("Failed to create MiData")?;
+    // The viewer shows the images in sRGB
+    let midata = convert_color_space(
+        midata,
+        &ColorSpace::Srgb.profile(),
+        params.color_space.into(),
+    )
+    .and_then(|midata| midata.transform(&params.transform.params()))
+    .map_err(|err| err.to_string())?;
 
     let encoder_params = EncoderParams {
         lvgl_version: params.lvgl_version.into(),
         color_format: params.color_format.into(),
         stride_align: params.stride_align as u32,
         dither: if params.dither { Some(1) } else { None },
+        dither_algorithm: params.dither_algorithm.into(),
+        dither_strength: params.dither_strength,
+        quantizer: params.quantizer.into(),
         compress: params.compression.into(),
+        color_depth: params.color_depth.into(),
+        color_16_swap: params.color_16_swap,
+        quality: params.quality,
+        jpeg_subsampling: params.jpeg_subsampling.into(),
+        png_compression: params.png_compression.into(),
+        png_filter: params.png_filter.into(),
+        webp_near_lossless: params.webp_near_lossless,
+        tiff_compression: params.tiff_compression.into(),
         ..Default::default()
     };
 
     let output_format = params.output_format;
 
     let encoder = output_format.get_endecoder();
-    let data = encoder.encode(&midata, encoder_params);
+    let data = encoder
+        .encode(&midata, encoder_params)
+        .map_err(|err| err.to_string())?;
     let ext = output_format.get_file_extension().to_string();
 
     Ok((data, ext))
@@ -103,15 +190,18 @@ pub fn save_images(items: &[ImageItem], params: &ConvertParams) {
     let folder = rfd::FileDialog::new().pick_folder();
     if let Some(folder) = folder {
         for item in items {
-            if let Ok((data, ext)) = convert_image(item, params) {
-                let file_name = Path::new(&item.path)
-                    .file_stem()
-                    .unwrap_or_default()
-                    .to_string_lossy();
-                let new_path = folder.join(format!("{}.{}", file_name, ext));
-                if let Err(e) = std::fs::write(&new_path, data) {
-                    log::error!("Failed to save file: {}", e);
+            match convert_image(item, params) {
+                Ok((data, ext)) => {
+                    let file_name = Path::new(&item.path)
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy();
+                    let new_path = folder.join(format!("{file_name}.{ext}"));
+                    if let Err(e) = std::fs::write(&new_path, data) {
+                        log::error!("Failed to save file: {e}");
+                    }
                 }
+                Err(e) => log::error!("Failed to convert <{}>: {e}", item.path),
             }
         }
     }
@@ -127,7 +217,7 @@ pub fn save_images(items: &[ImageItem], params: &ConvertParams) {
                 .file_stem()
                 .unwrap_or_default()
                 .to_string_lossy();
-            let file_name = format!("{}.{}", file_name, ext);
+            let file_name = format!("{file_name}.{ext}");
 
             let window = web_sys::window().expect("window not found");
             let document = window.document().expect("document not found");
@@ -138,11 +228,8 @@ pub fn save_images(items: &[ImageItem], params: &ConvertParams) {
             array.push(&uint8_array);
             let blob_options = web_sys::BlobPropertyBag::new();
             blob_options.set_type("application/octet-stream");
-            let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(
-                &array,
-                &blob_options,
-            )
-            .expect("failed to create blob");
+            let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&array, &blob_options)
+                .expect("failed to create blob");
 
             let url = web_sys::Url::create_object_url_with_blob(&blob)
                 .expect("failed to create object url");
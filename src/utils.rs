@@ -1,33 +1,21 @@
 use crate::image_viewer::model::ImageItem;
 use eframe::egui::Color32;
 use icu_lib::endecoder::ImageInfo;
-use icu_lib::endecoder::utils::diff::ImageDiffResult;
+use icu_lib::endecoder::utils::diff::{DiffParams, ImageDiffResult, QualityMetrics};
 use icu_lib::midata::MiData;
 
 pub fn diff_image(
     img1: &ImageItem,
     img2: &ImageItem,
+    params: &DiffParams,
     diff_blend: f32,
     diff_tolerance: f32,
     only_show_diff: bool,
 ) -> Option<(ImageItem, ImageDiffResult)> {
-    let (diff, diff_result) = icu_lib::endecoder::utils::diff::blend_diff_image(
-        &MiData::from_rgba(
-            img1.width,
-            img1.height,
-            img1.image_data
-                .iter()
-                .flat_map(|x| x.to_array())
-                .collect::<Vec<u8>>(),
-        )?,
-        &MiData::from_rgba(
-            img2.width,
-            img2.height,
-            img2.image_data
-                .iter()
-                .flat_map(|x| x.to_array())
-                .collect::<Vec<u8>>(),
-        )?,
+    let (diff, diff_result) = icu_lib::endecoder::utils::diff::blend_diff_image_with(
+        &to_midata(img1)?,
+        &to_midata(img2)?,
+        params,
         diff_blend,
         diff_tolerance,
         only_show_diff,
@@ -35,19 +23,21 @@ pub fn diff_image(
 
     match diff {
         MiData::RGBA(rgba) => {
+            // The diff covers the compared region of the images
+            let (width, height) = rgba.dimensions();
             let rgba = rgba.to_vec();
             Some((
                 ImageItem {
                     path: "".to_string(),
                     info: ImageInfo {
-                        width: img1.width,
-                        height: img2.height,
+                        width,
+                        height,
                         data_size: 0,
                         format: "diff".to_string(),
                         other_info: serde_json::Value::Null,
                     },
-                    width: img1.width,
-                    height: img2.height,
+                    width,
+                    height,
                     image_data: rgba
                         .to_vec()
                         .chunks(4)
@@ -55,6 +45,7 @@ pub fn diff_image(
                             Color32::from_rgba_unmultiplied(pixel[0], pixel[1], pixel[2], pixel[3])
                         })
                         .collect::<Vec<Color32>>(),
+                    frames: Vec::new(),
                 },
                 diff_result,
             ))
@@ -62,3 +53,32 @@ pub fn diff_image(
         _ => None,
     }
 }
+
+/// Quality metrics of `img2` against `img1`, `None` if they don't overlap
+pub fn quality_metrics(
+    img1: &ImageItem,
+    img2: &ImageItem,
+    params: &DiffParams,
+) -> Option<QualityMetrics> {
+    icu_lib::endecoder::utils::diff::quality_metrics_with(
+        &to_midata(img1)?,
+        &to_midata(img2)?,
+        params,
+    )
+}
+
+/// Offset of `img2` on `img1` that lines them up best
+pub fn find_offset(img1: &ImageItem, img2: &ImageItem) -> Option<(i32, i32)> {
+    icu_lib::endecoder::utils::diff::find_offset(&to_midata(img1)?, &to_midata(img2)?)
+}
+
+fn to_midata(img: &ImageItem) -> Option<MiData> {
+    MiData::from_rgba(
+        img.width,
+        img.height,
+        img.image_data
+            .iter()
+            .flat_map(|x| x.to_array())
+            .collect::<Vec<u8>>(),
+    )
+}